@@ -0,0 +1,116 @@
+// Runs the WebAuthn ceremonies for the passkey buttons. The server sends the options and checks
+// the responses, this only converts between JSON and what the browser API takes.
+(function () {
+  "use strict";
+
+  // Pages for signed in owners carry a CSRF token, and every response brings the next one
+  let csrfToken = null;
+
+  async function send(url, init) {
+    const headers = { ...init.headers };
+    if (csrfToken) {
+      headers["X-CSRF-Token"] = csrfToken;
+    }
+    const response = await fetch(url, { ...init, headers });
+    csrfToken = response.headers.get("X-CSRF-Token") || csrfToken;
+    return response;
+  }
+
+  function toBytes(value) {
+    const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+    return Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+  }
+
+  function toBase64url(buffer) {
+    const bytes = String.fromCharCode(...new Uint8Array(buffer));
+    return btoa(bytes).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+  }
+
+  function descriptors(list) {
+    return (list || []).map((descriptor) => ({ ...descriptor, id: toBytes(descriptor.id) }));
+  }
+
+  function credentialJSON(credential) {
+    const response = {};
+    for (const field of ["clientDataJSON", "attestationObject", "authenticatorData", "signature", "userHandle"]) {
+      if (credential.response[field]) {
+        response[field] = toBase64url(credential.response[field]);
+      }
+    }
+    return { id: credential.id, rawId: toBase64url(credential.rawId), type: credential.type, response };
+  }
+
+  async function post(url, body) {
+    const response = await send(url, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(body || {}),
+    });
+    const text = await response.text();
+    let data = {};
+    try {
+      data = JSON.parse(text);
+    } catch (_) {
+      data = { error: text };
+    }
+    if (!response.ok) {
+      throw new Error(data.error || response.statusText);
+    }
+    return data;
+  }
+
+  function report(button, error) {
+    const alert = document.getElementById(button.dataset.alert);
+    if (alert) {
+      alert.textContent = error.message;
+      alert.hidden = false;
+    }
+  }
+
+  async function signIn(button) {
+    const options = await post("/oauth/signin/passkey/options");
+    options.challenge = toBytes(options.challenge);
+    options.allowCredentials = descriptors(options.allowCredentials);
+    const credential = await navigator.credentials.get({ publicKey: options });
+    const result = await post("/oauth/signin/passkey?" + button.dataset.query, credentialJSON(credential));
+    window.location.assign(result.location);
+  }
+
+  async function register(button) {
+    const options = await post("/oauth/account/passkeys/options");
+    options.challenge = toBytes(options.challenge);
+    options.user.id = toBytes(options.user.id);
+    options.excludeCredentials = descriptors(options.excludeCredentials);
+    const credential = await navigator.credentials.create({ publicKey: options });
+    const name = document.getElementById(button.dataset.name);
+    await post("/oauth/account/passkeys", {
+      name: name ? name.value : "",
+      credential: credentialJSON(credential),
+    });
+    window.location.reload();
+  }
+
+  async function remove(button) {
+    const response = await send("/oauth/account/passkeys/" + button.dataset.id, { method: "DELETE" });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    window.location.reload();
+  }
+
+  const actions = { signin: signIn, register: register, remove: remove };
+  document.addEventListener("DOMContentLoaded", () => {
+    const holder = document.querySelector("[data-csrf-token]");
+    csrfToken = holder ? holder.dataset.csrfToken : null;
+    if (!window.PublicKeyCredential) {
+      return;
+    }
+    for (const button of document.querySelectorAll("[data-passkey]")) {
+      button.hidden = false;
+      button.addEventListener("click", (event) => {
+        event.preventDefault();
+        actions[button.dataset.passkey](button).catch((error) => report(button, error));
+      });
+    }
+  });
+})();
@@ -0,0 +1,231 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, DeriveInput, Ident, LitStr};
+
+/// Derive `axum_oauth::oauth::scopes::Resource` for a protected resource.
+///
+/// Every resource gets the `read` and `write` actions. Additional actions are declared with
+/// `#[resource(actions(...))]` and each one gets an associated constant on the type and a
+/// `<Type><Action>` marker that can be used with the `Grant<S>` extractor. All scopes of the
+/// resource are added to the scope registry.
+///
+/// `#[scope(<action>, description = "...", warning = "...")]` sets the text shown to users on the
+/// consent page. The description defaults to the scope itself, and a warning marks the scope as
+/// sensitive.
+///
+/// ```ignore
+/// #[derive(Resource)]
+/// #[resource(name = "account", actions(follow))]
+/// #[scope(follow, description = "Follow other accounts on your behalf")]
+/// pub struct Account;
+///
+/// // account:read, account:write and account:follow are now registered, and
+/// // `Grant<AccountFollow>` requires the account:follow scope.
+/// ```
+#[proc_macro_derive(Resource, attributes(resource, scope))]
+pub fn derive_resource(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Attributes {
+    name: Option<LitStr>,
+    actions: Vec<Ident>,
+    scopes: Vec<ScopeAttribute>,
+}
+
+struct ScopeAttribute {
+    action: Ident,
+    description: Option<LitStr>,
+    warning: Option<LitStr>,
+}
+
+fn parse_attributes(input: &DeriveInput) -> syn::Result<Attributes> {
+    let mut attributes = Attributes {
+        name: None,
+        actions: Vec::new(),
+        scopes: Vec::new(),
+    };
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("resource")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                attributes.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("actions") {
+                meta.parse_nested_meta(|action| {
+                    let ident = action.path.require_ident()?;
+                    if ident == "read" || ident == "write" {
+                        return Err(action.error("read and write are implied for every resource"));
+                    }
+                    attributes.actions.push(ident.clone());
+                    Ok(())
+                })
+            } else {
+                Err(meta.error("unsupported resource attribute"))
+            }
+        })?;
+    }
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("scope")) {
+        let mut scope: Option<ScopeAttribute> = None;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("description") || meta.path.is_ident("warning") {
+                let Some(scope) = scope.as_mut() else {
+                    return Err(meta.error("the action must come first"));
+                };
+                let value = Some(meta.value()?.parse()?);
+                if meta.path.is_ident("description") {
+                    scope.description = value;
+                } else {
+                    scope.warning = value;
+                }
+                Ok(())
+            } else if scope.is_none() {
+                scope = Some(ScopeAttribute {
+                    action: meta.path.require_ident()?.clone(),
+                    description: None,
+                    warning: None,
+                });
+                Ok(())
+            } else {
+                Err(meta.error("unsupported scope attribute"))
+            }
+        })?;
+
+        let scope = scope.ok_or_else(|| syn::Error::new_spanned(attr, "missing action"))?;
+        let action = &scope.action;
+        if action != "read" && action != "write" && !attributes.actions.contains(action) {
+            return Err(syn::Error::new_spanned(
+                action,
+                "unknown action, declare it with #[resource(actions(...))]",
+            ));
+        }
+        attributes.scopes.push(scope);
+    }
+
+    Ok(attributes)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Resource cannot be derived for generic types",
+        ));
+    }
+
+    let attributes = parse_attributes(&input)?;
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let name = attributes
+        .name
+        .map(|lit| lit.value())
+        .unwrap_or_else(|| ident.to_string().to_lowercase());
+    if name.is_empty() || name.contains([':', ' ']) {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "resource name must be non-empty and may not contain ':' or spaces",
+        ));
+    }
+
+    let scope = |action: &str| LitStr::new(&format!("{name}:{action}"), Span::call_site());
+    let read = scope("read");
+    let write = scope("write");
+    let name = LitStr::new(&name, Span::call_site());
+
+    let custom = attributes
+        .actions
+        .iter()
+        .map(|action| {
+            let action_str = action.to_string();
+            let scope = scope(&action_str);
+            let constant = format_ident!("{}", action_str.to_uppercase());
+            let marker = format_ident!("{}{}", ident, camel_case(&action_str));
+            let doc = format!("Marker for the `{}` scope.", scope.value());
+
+            (action_str, scope, constant, marker, doc)
+        })
+        .collect::<Vec<_>>();
+
+    let scopes = std::iter::once(&read)
+        .chain(std::iter::once(&write))
+        .chain(custom.iter().map(|(_, scope, ..)| scope));
+    let actions = ["read".to_string(), "write".to_string()]
+        .into_iter()
+        .chain(custom.iter().map(|(action, ..)| action.clone()))
+        .map(|action| LitStr::new(&action, Span::call_site()));
+    let registrations = scopes.clone().zip(actions).map(|(scope, action)| {
+        let attribute = attributes
+            .scopes
+            .iter()
+            .find(|attribute| attribute.action == action.value());
+        let description = attribute
+            .and_then(|attribute| attribute.description.clone())
+            .unwrap_or_else(|| scope.clone());
+        let warning = match attribute.and_then(|attribute| attribute.warning.as_ref()) {
+            Some(warning) => quote! { ::std::option::Option::Some(#warning) },
+            None => quote! { ::std::option::Option::None },
+        };
+
+        quote! {
+            ::axum_oauth::oauth::scopes::inventory::submit! {
+                ::axum_oauth::oauth::scopes::ScopeInfo {
+                    scope: #scope,
+                    resource: #name,
+                    action: #action,
+                    description: #description,
+                    warning: #warning,
+                }
+            }
+        }
+    });
+    let constants = custom.iter().map(|(_, scope, constant, ..)| {
+        quote! { pub const #constant: &'static str = #scope; }
+    });
+    let markers = custom.iter().map(|(_, scope, _, marker, doc)| {
+        quote! {
+            #[doc = #doc]
+            #vis struct #marker;
+
+            impl ::axum_oauth::oauth::scopes::Scope for #marker {
+                const SCOPE: &'static str = #scope;
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl ::axum_oauth::oauth::scopes::Resource for #ident {
+            const NAME: &'static str = #name;
+            const READ: &'static str = #read;
+            const WRITE: &'static str = #write;
+            const SCOPES: &'static [&'static str] = &[#(#scopes),*];
+        }
+
+        impl #ident {
+            #(#constants)*
+        }
+
+        #(#markers)*
+
+        #(#registrations)*
+    })
+}
+
+fn camel_case(s: &str) -> String {
+    s.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,74 @@
+use axum_oauth::oauth::scopes::{self, Read, Resource, Scope, Write};
+use axum_oauth_macros::Resource;
+
+#[derive(Resource)]
+struct Calendar;
+
+#[derive(Resource)]
+#[resource(name = "photos", actions(share, bulk_delete))]
+#[scope(read, description = "See your photos")]
+#[scope(
+    bulk_delete,
+    description = "Delete many photos at once",
+    warning = "Deleted photos can't be recovered."
+)]
+struct Photo;
+
+#[test]
+fn default_name_and_actions() {
+    assert_eq!(Calendar::NAME, "calendar");
+    assert_eq!(Calendar::READ, "calendar:read");
+    assert_eq!(Calendar::WRITE, "calendar:write");
+    assert_eq!(Calendar::SCOPES, &["calendar:read", "calendar:write"]);
+    assert_eq!(<Read<Calendar> as Scope>::SCOPE, "calendar:read");
+    assert_eq!(<Write<Calendar> as Scope>::SCOPE, "calendar:write");
+}
+
+#[test]
+fn custom_actions() {
+    assert_eq!(Photo::NAME, "photos");
+    assert_eq!(Photo::SHARE, "photos:share");
+    assert_eq!(Photo::BULK_DELETE, "photos:bulk_delete");
+    assert_eq!(
+        Photo::SCOPES,
+        &[
+            "photos:read",
+            "photos:write",
+            "photos:share",
+            "photos:bulk_delete"
+        ]
+    );
+    assert_eq!(PhotoShare::SCOPE, "photos:share");
+    assert_eq!(PhotoBulkDelete::SCOPE, "photos:bulk_delete");
+}
+
+#[test]
+fn scopes_are_registered() {
+    for scope in Calendar::SCOPES.iter().chain(Photo::SCOPES) {
+        assert!(scopes::is_registered(scope), "{scope} is registered");
+    }
+    let info = scopes::lookup("photos:share").expect("photos:share is registered");
+    assert_eq!(info.resource, "photos");
+    assert_eq!(info.action, "share");
+
+    // Resources declared by the library itself are registered too.
+    assert!(scopes::is_registered("account:read"));
+    assert!(!scopes::is_registered("account:follow"));
+}
+
+#[test]
+fn scope_descriptions() {
+    let read = scopes::lookup("photos:read").unwrap();
+    assert_eq!(read.description, "See your photos");
+    assert_eq!(read.warning, None);
+
+    let delete = scopes::lookup("photos:bulk_delete").unwrap();
+    assert_eq!(delete.description, "Delete many photos at once");
+    assert_eq!(delete.warning, Some("Deleted photos can't be recovered."));
+
+    // Without a description the scope itself is shown
+    assert_eq!(
+        scopes::lookup("photos:share").unwrap().description,
+        "photos:share"
+    );
+}
@@ -0,0 +1,111 @@
+//! Validating access tokens shouldn't wait on other requests, so the tokens validated per second
+//! should grow with the threads validating them, up to the number of cores.
+
+use std::time::Instant;
+
+use axum_oauth::oauth::{
+    database::{resource::user::HashedPassword, ConsentSource, Database},
+    models::ClientId,
+    primitives::{Lifetimes, OwnerTokenMap, UsageIssuer},
+    settings::TokenLifetimes,
+};
+use chrono::{Duration, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use oxide_auth::primitives::{
+    generator::RandomGenerator,
+    grant::{Extensions, Grant},
+};
+use oxide_auth_async::primitives::Issuer;
+use secrecy::Secret;
+use tokio::runtime::Runtime;
+
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+/// Tokens each thread validates per iteration.
+const REQUESTS: u64 = 1_000;
+
+fn current_thread() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+/// A database with an owner for each thread who authorized the client, and an access token for
+/// each of them.
+fn issued_tokens(rt: &Runtime, tokens: &OwnerTokenMap) -> (Database, Vec<String>) {
+    let mut db = Database::new();
+    let client_id = ClientId::new();
+    let issued = rt.block_on(async {
+        let mut issued = Vec::new();
+        let password = HashedPassword::new(Secret::new("secret".to_owned()))
+            .await
+            .expect("hashing a password");
+        for i in 0..THREADS[THREADS.len() - 1] {
+            let username = format!("user{i}");
+            let user_id = db
+                .register_user(&username, password.clone(), "User")
+                .await
+                .expect("usernames are unique");
+            let scope = "account:read".parse().unwrap();
+            db.update_client_scope(user_id, client_id, scope, ConsentSource::User, 0)
+                .await
+                .expect("the user exists");
+            let grant = Grant {
+                owner_id: format!("{user_id}:{username}"),
+                client_id: client_id.to_string(),
+                scope: "account:read".parse().unwrap(),
+                redirect_uri: "http://localhost:3001/endpoint".parse().unwrap(),
+                until: Utc::now() + Duration::hours(1),
+                extensions: Extensions::new(),
+            };
+            let lifetimes = TokenLifetimes::default();
+            let issued_token = UsageIssuer::new(tokens, &db, Lifetimes::new(&lifetimes, &db))
+                .issue(grant)
+                .await
+                .unwrap();
+            issued.push(issued_token.token);
+        }
+        issued
+    });
+
+    (db, issued)
+}
+
+fn token_validation(c: &mut Criterion) {
+    let tokens = OwnerTokenMap::new(RandomGenerator::new(16));
+    let (db, issued) = issued_tokens(&current_thread(), &tokens);
+
+    let mut group = c.benchmark_group("token_validation");
+    for threads in THREADS {
+        group.throughput(Throughput::Elements(threads as u64 * REQUESTS));
+        group.bench_with_input(
+            BenchmarkId::new("recover_token", threads),
+            &issued[..threads],
+            |b, issued| {
+                b.iter_custom(|iters| {
+                    let start = Instant::now();
+                    std::thread::scope(|s| {
+                        for token in issued {
+                            let (tokens, db) = (&tokens, &db);
+                            s.spawn(move || {
+                                let lifetimes = TokenLifetimes::default();
+                                let lifetimes = Lifetimes::new(&lifetimes, db);
+                                let mut issuer = UsageIssuer::new(tokens, db, lifetimes);
+                                current_thread().block_on(async {
+                                    for _ in 0..iters * REQUESTS {
+                                        let grant = issuer.recover_token(token).await.unwrap();
+                                        assert!(grant.is_some());
+                                    }
+                                })
+                            });
+                        }
+                    });
+                    start.elapsed()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, token_validation);
+criterion_main!(benches);
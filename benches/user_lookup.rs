@@ -0,0 +1,51 @@
+//! Looking up users by name should take the same time however many users there are.
+
+use axum_oauth::oauth::database::{resource::user::HashedPassword, Database};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use secrecy::Secret;
+use tokio::runtime::Runtime;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn database_with_users(rt: &Runtime, count: usize) -> Database {
+    let mut db = Database::new();
+    rt.block_on(async {
+        // Hashing is slow on purpose, so every user gets the same hash
+        let password = HashedPassword::new(Secret::new("secret".to_owned()))
+            .await
+            .expect("hashing a password");
+        for i in 0..count {
+            let username = format!("user{i}");
+            db.register_user(&username, password.clone(), "User")
+                .await
+                .expect("usernames are unique");
+        }
+    });
+
+    db
+}
+
+fn user_lookup(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("user_lookup");
+    for count in SIZES {
+        let db = database_with_users(&rt, count);
+        let username = format!("USER{}", count - 1);
+        group.bench_with_input(
+            BenchmarkId::new("get_user_by_name", count),
+            &username,
+            |b, username| b.iter(|| rt.block_on(db.get_user_by_name(username)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("contains_user_name", count),
+            &username,
+            |b, username| b.iter(|| rt.block_on(db.contains_user_name(username))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, user_lookup);
+criterion_main!(benches);
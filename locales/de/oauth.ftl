@@ -0,0 +1,191 @@
+## Layout
+
+site-name = Axum OAuth
+site-description = OAuth-Backend.
+nav-home = Axum OAuth Backend
+nav-signout = Abmelden
+
+## Sign in
+
+signin-title = Anmelden
+signin-heading = Anmelden
+signin-subheading = Mit einem bestehenden Konto anmelden
+signin-username = Benutzername
+signin-password = Passwort
+signin-submit = Anmelden
+signin-no-account = Noch kein Konto?
+signin-signup = Registrieren
+signin-failed = Falscher Benutzername oder falsches Passwort.
+signin-locked = Zu viele fehlgeschlagene Anmeldeversuche. Die Anmeldung ist für { $minutes } Min. gesperrt.
+signin-passkey = Mit einem Passkey anmelden
+signin-passkey-failed = Der Passkey wurde nicht akzeptiert.
+signin-unverified = Bestätige deine E-Mail-Adresse, bevor du dich anmeldest. Ist der Link abgelaufen, setze dein Passwort zurück, um einen neuen zu erhalten.
+signin-forgot = Passwort vergessen?
+
+signin-totp-title = Bestätigung in zwei Schritten
+signin-totp-heading = Bestätigung in zwei Schritten
+signin-totp-subheading = Gib den Code aus deiner Authenticator-App ein
+signin-totp-code = Code
+signin-totp-submit = Bestätigen
+signin-totp-recovery = Authenticator verloren? Gib stattdessen einen deiner Wiederherstellungscodes ein.
+signin-totp-failed = Falscher Code.
+signin-totp-passkey = Stattdessen einen Passkey verwenden
+
+## Sign up
+
+signup-title = Registrieren
+signup-heading = Registrieren
+signup-subheading = Ein neues Konto erstellen
+signup-username = Benutzername
+signup-email = E-Mail-Adresse
+signup-given-name = Name
+signup-password = Passwort
+signup-submit = Registrieren
+signup-have-account = Schon ein Konto?
+signup-signin = Anmelden
+
+## Email
+
+email-verify-subject = Bestätige deine E-Mail-Adresse
+email-verify-body =
+    Hallo { $username },
+
+    öffne diesen Link, um deine E-Mail-Adresse zu bestätigen:
+
+    { $link }
+
+    Der Link ist 24 Stunden gültig. Wenn du dich nicht registriert hast, kannst du diese E-Mail ignorieren.
+email-reset-subject = Setze dein Passwort zurück
+email-reset-body =
+    Hallo { $username },
+
+    öffne diesen Link, um ein neues Passwort zu wählen:
+
+    { $link }
+
+    Der Link ist eine Stunde gültig. Wenn du ihn nicht angefordert hast, kannst du diese E-Mail ignorieren.
+email-exists-subject = Du hast schon ein Konto
+email-exists-body =
+    Hallo { $username },
+
+    jemand, wahrscheinlich du, wollte sich mit dieser E-Mail-Adresse registrieren, die schon zu deinem Konto gehört. Hier kannst du dich anmelden:
+
+    { $signin }
+
+    Wenn du dein Passwort vergessen hast, kannst du hier ein neues wählen:
+
+    { $forgot }
+
+    Wenn du es nicht warst, kannst du diese E-Mail ignorieren.
+email-verify-heading = E-Mail-Adresse
+email-verified = Deine E-Mail-Adresse ist bestätigt.
+email-link-invalid = Der Link ist ungültig, abgelaufen oder wurde schon verwendet.
+email-verification-sent = Wir haben dir einen neuen Link geschickt. Sieh in deinem Posteingang nach.
+email-unverified-heading = Bestätige deine E-Mail-Adresse
+email-unverified = Öffne den Link, den wir an deine E-Mail-Adresse geschickt haben, bevor du fortfährst.
+email-resend = Neuen Link senden
+
+## Password reset
+
+password-forgot-title = Passwort vergessen
+password-forgot-heading = Passwort vergessen
+password-forgot-subheading = Wir schicken dir einen Link, um ein neues Passwort zu wählen
+password-forgot-submit = Link senden
+password-forgot-sent = Falls ein Konto diese E-Mail-Adresse hat, haben wir einen Link daran geschickt.
+password-forgot-again = Neuen Link anfordern
+password-reset-title = Passwort zurücksetzen
+password-reset-heading = Passwort zurücksetzen
+password-reset-subheading = Wähle ein neues Passwort
+password-reset-password = Neues Passwort
+password-reset-submit = Passwort ändern
+password-reset-done = Dein Passwort wurde geändert. Melde dich mit dem neuen an.
+
+## Sign out
+
+signout-title = Abmelden
+signout-heading = Abmelden
+signout-question = Möchtest du dich von deinem Konto abmelden?
+signout-submit = Abmelden
+
+## Authenticator
+
+totp-title = Authenticator
+totp-heading = Bestätigung in zwei Schritten
+totp-subheading = Nach dem Passwort mit einem Code aus einer Authenticator-App anmelden
+totp-scan = Scanne den QR-Code mit deiner Authenticator-App und gib dann den angezeigten Code ein.
+totp-manual = Oder gib diesen Schlüssel von Hand ein:
+totp-open-app = In der App öffnen
+totp-submit = Einschalten
+totp-failed = Falscher Code. Prüfe die Uhrzeit auf deinem Gerät und versuche es erneut.
+totp-enabled = Die Bestätigung in zwei Schritten ist eingeschaltet.
+totp-recovery-codes = Die Bestätigung in zwei Schritten ist eingeschaltet. Bewahre diese Wiederherstellungscodes sicher auf. Mit jedem kannst du dich einmal anmelden, falls du deinen Authenticator verlierst. Sie werden nicht noch einmal angezeigt.
+totp-recovery-remaining = Noch { $count } Wiederherstellungscodes übrig.
+
+## Passkeys
+
+passkeys-title = Passkeys
+passkeys-heading = Passkeys
+passkeys-subheading = Mit Fingerabdruck, Gesicht oder Displaysperre statt mit einem Passwort anmelden
+passkeys-none = Du hast noch keine Passkeys.
+passkeys-name = Name, z. B. Mein Laptop
+passkeys-add = Passkey hinzufügen
+passkeys-remove = Entfernen
+passkeys-unsupported = Passkeys benötigen JavaScript.
+
+## Connected apps
+
+apps-title = Verbundene Apps
+apps-heading = Verbundene Apps
+apps-subheading = Apps, denen du Zugriff auf dein Konto erlaubt hast
+apps-none = Du hast noch keine Apps verbunden.
+apps-granted = Verbunden am { $date }
+apps-last-used = zuletzt genutzt am { $date }
+apps-never-used = noch nicht genutzt
+apps-revoke = Trennen
+apps-first-party = Gehört zu diesem Dienst und wurde daher ohne Nachfrage verbunden.
+
+## Consent
+
+authorize-title = Autorisieren
+authorize-heading = { $client } autorisieren
+# $username is already HTML-escaped
+authorize-request = { $client } möchte mit den folgenden Berechtigungen auf dein Konto <em>{ $username }</em> zugreifen:
+authorize-logo = Logo von { $client }
+authorize-first-party = Eigene Anwendung
+authorize-verified = Verifizierte Anwendung
+authorize-unverified = Diese Anwendung wurde nicht verifiziert. Erlaube den Zugriff nur, wenn du ihr vertraust.
+authorize-homepage = Webseite
+authorize-privacy-policy = Datenschutzerklärung
+authorize-required = erforderlich
+authorize-allow = Erlauben
+authorize-deny = Ablehnen
+
+## Scopes
+
+scope-account-read = Deinen Benutzernamen, deinen Namen und die mit deinem Konto verbundenen Apps sehen
+scope-account-write = Den Namen in deinem Konto ändern
+scope-account-write-warning = Die App kann dein Profil ändern, ohne dich erneut zu fragen.
+scope-offline_access = Auf dein Konto zugreifen, auch wenn du die App nicht benutzt
+
+## Form errors
+
+field-error-required = Dieses Feld ist erforderlich.
+field-error-too-short = Verwende mindestens { $min } Zeichen.
+field-error-too-long = Verwende höchstens { $max } Zeichen.
+field-error-invalid-characters = Dies enthält unzulässige Zeichen.
+field-error-invalid = Dies ist ungültig.
+field-error-taken = Dies ist bereits vergeben.
+field-error-too-weak = Dieses Passwort ist zu leicht zu erraten.
+field-error-breached = Dieses Passwort ist aus einem Datenleck bekannt. Wähle ein anderes.
+field-error-contains-username = Das Passwort darf den Benutzernamen nicht enthalten.
+
+## Errors
+
+error-title = Fehler
+error-bad-request = Die Anfrage konnte nicht verstanden werden.
+error-unauthorized = Dazu bist du nicht berechtigt.
+error-forbidden = Das Formular ist abgelaufen oder wurde von einer anderen Seite gesendet. Gehe zurück, lade die Seite neu und versuche es erneut.
+error-not-found = Die gesuchte Seite existiert nicht.
+error-conflict = Das existiert bereits.
+error-invalid-input = Einige deiner Angaben sind ungültig.
+error-internal = Bei uns ist etwas schiefgelaufen. Bitte versuche es später erneut.
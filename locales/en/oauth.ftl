@@ -0,0 +1,191 @@
+## Layout
+
+site-name = Axum OAuth
+site-description = OAuth backend.
+nav-home = Axum OAuth Backend
+nav-signout = Signout
+
+## Sign in
+
+signin-title = Sign in
+signin-heading = Sign in
+signin-subheading = Sign in to an existing account
+signin-username = Username
+signin-password = Password
+signin-submit = Sign in
+signin-no-account = Don't have an account?
+signin-signup = Sign up
+signin-failed = Wrong username or password.
+signin-locked = Too many failed sign-in attempts. Sign-in is locked for { $minutes } min.
+signin-passkey = Sign in with a passkey
+signin-passkey-failed = The passkey wasn't accepted.
+signin-unverified = Verify your email address before you sign in. If the link has expired, reset your password to get a new one.
+signin-forgot = Forgot your password?
+
+signin-totp-title = Two-step verification
+signin-totp-heading = Two-step verification
+signin-totp-subheading = Enter the code from your authenticator app
+signin-totp-code = Code
+signin-totp-submit = Verify
+signin-totp-recovery = Lost your authenticator? Enter one of your recovery codes instead.
+signin-totp-failed = Wrong code.
+signin-totp-passkey = Use a passkey instead
+
+## Sign up
+
+signup-title = Sign up
+signup-heading = Sign up
+signup-subheading = Create a new account
+signup-username = Username
+signup-email = Email address
+signup-given-name = Name
+signup-password = Password
+signup-submit = Sign up
+signup-have-account = Already have an account?
+signup-signin = Sign in
+
+## Email
+
+email-verify-subject = Verify your email address
+email-verify-body =
+    Hello { $username },
+
+    open this link to verify your email address:
+
+    { $link }
+
+    The link works for 24 hours. If you didn't sign up, you can ignore this email.
+email-reset-subject = Reset your password
+email-reset-body =
+    Hello { $username },
+
+    open this link to choose a new password:
+
+    { $link }
+
+    The link works for an hour. If you didn't ask for it, you can ignore this email.
+email-exists-subject = You already have an account
+email-exists-body =
+    Hello { $username },
+
+    someone, probably you, tried to sign up with this email address, which already belongs to your account. You can sign in here:
+
+    { $signin }
+
+    If you forgot your password, you can choose a new one here:
+
+    { $forgot }
+
+    If it wasn't you, you can ignore this email.
+email-verify-heading = Email address
+email-verified = Your email address is verified.
+email-link-invalid = The link is invalid, has expired or was already used.
+email-verification-sent = We sent you another link. Check your inbox.
+email-unverified-heading = Verify your email address
+email-unverified = Open the link we sent to your email address before you continue.
+email-resend = Send another link
+
+## Password reset
+
+password-forgot-title = Forgot password
+password-forgot-heading = Forgot password
+password-forgot-subheading = We'll send a link to choose a new password
+password-forgot-submit = Send link
+password-forgot-sent = If an account has that email address, we sent a link to it.
+password-forgot-again = Ask for a new link
+password-reset-title = Reset password
+password-reset-heading = Reset password
+password-reset-subheading = Choose a new password
+password-reset-password = New password
+password-reset-submit = Change password
+password-reset-done = Your password was changed. Sign in with the new one.
+
+## Sign out
+
+signout-title = Sign out
+signout-heading = Sign out
+signout-question = Do you want to sign out of your account?
+signout-submit = Sign out
+
+## Authenticator
+
+totp-title = Authenticator
+totp-heading = Two-step verification
+totp-subheading = Sign in with a code from an authenticator app after your password
+totp-scan = Scan the QR code with your authenticator app, then enter the code it shows.
+totp-manual = Or enter this key by hand:
+totp-open-app = Open in app
+totp-submit = Turn on
+totp-failed = Wrong code. Check the time on your device and try again.
+totp-enabled = Two-step verification is on.
+totp-recovery-codes = Two-step verification is on. Save these recovery codes somewhere safe. Each of them signs you in once if you lose your authenticator, and they won't be shown again.
+totp-recovery-remaining = { $count } recovery codes left.
+
+## Passkeys
+
+passkeys-title = Passkeys
+passkeys-heading = Passkeys
+passkeys-subheading = Sign in with your fingerprint, face or screen lock instead of a password
+passkeys-none = You don't have any passkeys yet.
+passkeys-name = Name, e.g. My laptop
+passkeys-add = Add a passkey
+passkeys-remove = Remove
+passkeys-unsupported = Passkeys need JavaScript.
+
+## Connected apps
+
+apps-title = Connected apps
+apps-heading = Connected apps
+apps-subheading = Apps you allowed to access your account
+apps-none = You haven't connected any apps yet.
+apps-granted = Connected on { $date }
+apps-last-used = last used on { $date }
+apps-never-used = not used yet
+apps-revoke = Disconnect
+apps-first-party = Part of this service, so it was connected without asking you.
+
+## Consent
+
+authorize-title = Authorize
+authorize-heading = Authorize { $client }
+# $username is already HTML-escaped
+authorize-request = { $client } wants access to your <em>{ $username }</em> account with the following permissions:
+authorize-logo = { $client } logo
+authorize-first-party = First-party application
+authorize-verified = Verified application
+authorize-unverified = This application has not been verified. Only allow access if you trust it.
+authorize-homepage = Homepage
+authorize-privacy-policy = Privacy policy
+authorize-required = required
+authorize-allow = Allow
+authorize-deny = Deny
+
+## Scopes
+
+scope-account-read = View your username, your name and the apps connected to your account
+scope-account-write = Change the name on your account
+scope-account-write-warning = The app will be able to change your profile without asking you again.
+scope-offline_access = Keep access to your account while you're not using the app
+
+## Form errors
+
+field-error-required = This field is required.
+field-error-too-short = Use at least { $min } characters.
+field-error-too-long = Use at most { $max } characters.
+field-error-invalid-characters = This contains characters that aren't allowed.
+field-error-invalid = This isn't valid.
+field-error-taken = This is already taken.
+field-error-too-weak = This password is too easy to guess.
+field-error-breached = This password is known from a data breach. Choose another one.
+field-error-contains-username = The password can't contain the username.
+
+## Errors
+
+error-title = Error
+error-bad-request = The request could not be understood.
+error-unauthorized = You are not allowed to do that.
+error-forbidden = The form has expired or was sent from another site. Go back, reload the page and try again.
+error-not-found = The page you were looking for doesn't exist.
+error-conflict = That already exists.
+error-invalid-input = Some of the information you entered is invalid.
+error-internal = Something went wrong on our side. Please try again later.
@@ -0,0 +1,191 @@
+## Layout
+
+site-name = Axum OAuth
+site-description = Serveur OAuth.
+nav-home = Axum OAuth Backend
+nav-signout = Se déconnecter
+
+## Sign in
+
+signin-title = Connexion
+signin-heading = Connexion
+signin-subheading = Se connecter à un compte existant
+signin-username = Nom d'utilisateur
+signin-password = Mot de passe
+signin-submit = Se connecter
+signin-no-account = Pas encore de compte ?
+signin-signup = S'inscrire
+signin-failed = Nom d'utilisateur ou mot de passe incorrect.
+signin-locked = Trop de tentatives de connexion échouées. La connexion est bloquée pendant { $minutes } min.
+signin-passkey = Se connecter avec une clé d'accès
+signin-passkey-failed = La clé d'accès n'a pas été acceptée.
+signin-unverified = Vérifiez votre adresse e-mail avant de vous connecter. Si le lien a expiré, réinitialisez votre mot de passe pour en recevoir un nouveau.
+signin-forgot = Mot de passe oublié ?
+
+signin-totp-title = Validation en deux étapes
+signin-totp-heading = Validation en deux étapes
+signin-totp-subheading = Saisissez le code de votre application d'authentification
+signin-totp-code = Code
+signin-totp-submit = Valider
+signin-totp-recovery = Authentificateur perdu ? Saisissez plutôt l'un de vos codes de récupération.
+signin-totp-failed = Code incorrect.
+signin-totp-passkey = Utiliser plutôt une clé d'accès
+
+## Sign up
+
+signup-title = Inscription
+signup-heading = Inscription
+signup-subheading = Créer un nouveau compte
+signup-username = Nom d'utilisateur
+signup-email = Adresse e-mail
+signup-given-name = Nom
+signup-password = Mot de passe
+signup-submit = S'inscrire
+signup-have-account = Vous avez déjà un compte ?
+signup-signin = Se connecter
+
+## Email
+
+email-verify-subject = Vérifiez votre adresse e-mail
+email-verify-body =
+    Bonjour { $username },
+
+    ouvrez ce lien pour vérifier votre adresse e-mail :
+
+    { $link }
+
+    Le lien est valable 24 heures. Si vous ne vous êtes pas inscrit, ignorez cet e-mail.
+email-reset-subject = Réinitialisez votre mot de passe
+email-reset-body =
+    Bonjour { $username },
+
+    ouvrez ce lien pour choisir un nouveau mot de passe :
+
+    { $link }
+
+    Le lien est valable une heure. Si vous ne l'avez pas demandé, ignorez cet e-mail.
+email-exists-subject = Vous avez déjà un compte
+email-exists-body =
+    Bonjour { $username },
+
+    quelqu'un, probablement vous, a essayé de s'inscrire avec cette adresse e-mail, qui appartient déjà à votre compte. Vous pouvez vous connecter ici :
+
+    { $signin }
+
+    Si vous avez oublié votre mot de passe, vous pouvez en choisir un nouveau ici :
+
+    { $forgot }
+
+    Si ce n'était pas vous, ignorez cet e-mail.
+email-verify-heading = Adresse e-mail
+email-verified = Votre adresse e-mail est vérifiée.
+email-link-invalid = Le lien est invalide, a expiré ou a déjà été utilisé.
+email-verification-sent = Nous vous avons envoyé un nouveau lien. Consultez votre boîte de réception.
+email-unverified-heading = Vérifiez votre adresse e-mail
+email-unverified = Ouvrez le lien envoyé à votre adresse e-mail avant de continuer.
+email-resend = Envoyer un nouveau lien
+
+## Password reset
+
+password-forgot-title = Mot de passe oublié
+password-forgot-heading = Mot de passe oublié
+password-forgot-subheading = Nous vous enverrons un lien pour choisir un nouveau mot de passe
+password-forgot-submit = Envoyer le lien
+password-forgot-sent = Si un compte utilise cette adresse e-mail, nous y avons envoyé un lien.
+password-forgot-again = Demander un nouveau lien
+password-reset-title = Réinitialiser le mot de passe
+password-reset-heading = Réinitialiser le mot de passe
+password-reset-subheading = Choisissez un nouveau mot de passe
+password-reset-password = Nouveau mot de passe
+password-reset-submit = Changer le mot de passe
+password-reset-done = Votre mot de passe a été changé. Connectez-vous avec le nouveau.
+
+## Sign out
+
+signout-title = Déconnexion
+signout-heading = Déconnexion
+signout-question = Voulez-vous vous déconnecter de votre compte ?
+signout-submit = Se déconnecter
+
+## Authenticator
+
+totp-title = Authentificateur
+totp-heading = Validation en deux étapes
+totp-subheading = Se connecter avec un code d'une application d'authentification après le mot de passe
+totp-scan = Scannez le code QR avec votre application d'authentification, puis saisissez le code affiché.
+totp-manual = Ou saisissez cette clé à la main :
+totp-open-app = Ouvrir dans l'application
+totp-submit = Activer
+totp-failed = Code incorrect. Vérifiez l'heure de votre appareil et réessayez.
+totp-enabled = La validation en deux étapes est activée.
+totp-recovery-codes = La validation en deux étapes est activée. Conservez ces codes de récupération en lieu sûr. Chacun vous permet de vous connecter une fois si vous perdez votre authentificateur, et ils ne seront plus affichés.
+totp-recovery-remaining = Il reste { $count } codes de récupération.
+
+## Passkeys
+
+passkeys-title = Clés d'accès
+passkeys-heading = Clés d'accès
+passkeys-subheading = Se connecter avec son empreinte, son visage ou le verrouillage de l'écran au lieu d'un mot de passe
+passkeys-none = Vous n'avez pas encore de clé d'accès.
+passkeys-name = Nom, p. ex. Mon ordinateur portable
+passkeys-add = Ajouter une clé d'accès
+passkeys-remove = Supprimer
+passkeys-unsupported = Les clés d'accès nécessitent JavaScript.
+
+## Connected apps
+
+apps-title = Applications connectées
+apps-heading = Applications connectées
+apps-subheading = Les applications que vous avez autorisées à accéder à votre compte
+apps-none = Vous n'avez encore connecté aucune application.
+apps-granted = Connectée le { $date }
+apps-last-used = utilisée pour la dernière fois le { $date }
+apps-never-used = pas encore utilisée
+apps-revoke = Déconnecter
+apps-first-party = Fait partie de ce service, elle a donc été connectée sans vous le demander.
+
+## Consent
+
+authorize-title = Autoriser
+authorize-heading = Autoriser { $client }
+# $username is already HTML-escaped
+authorize-request = { $client } souhaite accéder à votre compte <em>{ $username }</em> avec les autorisations suivantes :
+authorize-logo = Logo de { $client }
+authorize-first-party = Application interne
+authorize-verified = Application vérifiée
+authorize-unverified = Cette application n'a pas été vérifiée. N'autorisez l'accès que si vous lui faites confiance.
+authorize-homepage = Site web
+authorize-privacy-policy = Politique de confidentialité
+authorize-required = obligatoire
+authorize-allow = Autoriser
+authorize-deny = Refuser
+
+## Scopes
+
+scope-account-read = Voir votre nom d'utilisateur, votre nom et les applications connectées à votre compte
+scope-account-write = Modifier le nom de votre compte
+scope-account-write-warning = L'application pourra modifier votre profil sans vous redemander.
+scope-offline_access = Garder l'accès à votre compte quand vous n'utilisez pas l'application
+
+## Form errors
+
+field-error-required = Ce champ est obligatoire.
+field-error-too-short = Utilisez au moins { $min } caractères.
+field-error-too-long = Utilisez au plus { $max } caractères.
+field-error-invalid-characters = Ceci contient des caractères non autorisés.
+field-error-invalid = Ceci n'est pas valide.
+field-error-taken = Ceci est déjà utilisé.
+field-error-too-weak = Ce mot de passe est trop facile à deviner.
+field-error-breached = Ce mot de passe est connu d'une fuite de données. Choisissez-en un autre.
+field-error-contains-username = Le mot de passe ne peut pas contenir le nom d'utilisateur.
+
+## Errors
+
+error-title = Erreur
+error-bad-request = La requête n'a pas pu être comprise.
+error-unauthorized = Vous n'êtes pas autorisé à faire cela.
+error-forbidden = Le formulaire a expiré ou a été envoyé depuis un autre site. Revenez en arrière, rechargez la page et réessayez.
+error-not-found = La page que vous cherchez n'existe pas.
+error-conflict = Cela existe déjà.
+error-invalid-input = Certaines des informations saisies ne sont pas valides.
+error-internal = Une erreur s'est produite de notre côté. Veuillez réessayer plus tard.
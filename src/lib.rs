@@ -1,67 +1,161 @@
+extern crate self as axum_oauth;
+
 use async_session::MemoryStore;
 use axum::Router;
-use std::net::TcpListener;
+use std::{
+    future::Future,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
 use tower_http::services::ServeDir;
 
 pub mod oauth;
 pub mod routes;
 pub mod state;
 
-use oauth::database::Database as AuthDB;
+use oauth::{
+    database::{resource::user::HashedPassword, Database as AuthDB},
+    maintenance::{Maintenance, MaintenanceMetrics},
+    policy::SignupPolicy,
+    settings::Settings,
+    templates::TemplateProvider,
+    throttle::SignInThrottle,
+};
 use secrecy::Secret;
 use state::AppState;
 
+/// Build the app, and start the [`Maintenance`] task that goes with it.
 pub async fn build_service(
     bind_address: Option<String>,
     server_port: u16,
-) -> (Router, TcpListener) {
-    let router = get_router().await;
+    settings: Settings,
+) -> (Router, TcpListener, Maintenance) {
+    let (router, maintenance) = get_router(settings).await;
 
-    let mut addr = format!("0.0.0.0:{server_port}");
-    if bind_address.is_some() {
-        addr = bind_address.unwrap();
-    }
+    let addr = bind_address.unwrap_or_else(|| format!("0.0.0.0:{server_port}"));
     let listener = TcpListener::bind(addr)
         .map_err(|e| {
             eprintln!("unable to parse local address: {e}");
         })
         .unwrap();
 
-    (router, listener)
+    (router, listener, maintenance)
+}
+
+/// Serve the app until the process is interrupted or terminated.
+pub async fn serve(app: Router, listener: TcpListener, maintenance: Maintenance) {
+    serve_with_shutdown(app, listener, maintenance, shutdown_signal()).await;
 }
 
-pub async fn serve(app: Router, listener: TcpListener) {
+/// Serve the app until `shutdown` completes. Requests under way are finished, and then the
+/// maintenance task is stopped.
+pub async fn serve_with_shutdown(
+    app: Router,
+    listener: TcpListener,
+    maintenance: Maintenance,
+    shutdown: impl Future<Output = ()>,
+) {
     axum::Server::from_tcp(listener)
         .map_err(|e| eprintln!("{e}"))
         .unwrap()
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown)
         .await
         .unwrap();
+    maintenance.shutdown().await;
+}
+
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("unable to listen for interrupts: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                eprintln!("unable to listen for termination: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
 
-async fn get_router() -> Router {
+async fn get_router(settings: Settings) -> (Router, Maintenance) {
+    let templates = TemplateProvider::new(&settings.templates)
+        .map_err(|e| eprintln!("unable to load templates: {e}"))
+        .unwrap();
+
+    let mailer = oauth::mailer::build(&settings.mail)
+        .map_err(|e| eprintln!("unable to set up the mailer: {e}"))
+        .unwrap();
+
+    let signup_policy = SignupPolicy::new(settings.signup.clone())
+        .map_err(|e| eprintln!("unable to read the breached passwords: {e}"))
+        .unwrap();
+
     let mut auth_db = AuthDB::new();
-    let _ = auth_db
-        .register_user("bob", Secret::from("secret".to_string()), "Robert")
-        .await;
+    let password = HashedPassword::new(Secret::from("secret".to_string()))
+        .await
+        .map_err(|e| eprintln!("unable to hash the seeded user's password: {e}"))
+        .unwrap();
+    let bob = auth_db
+        .register_user("bob", password, "Robert")
+        .await
+        .map_err(|e| eprintln!("unable to add the seeded user: {e}"))
+        .unwrap();
+    let _ = auth_db.set_email(bob, "bob@example.com", true).await;
     let _ = auth_db
         .register_public_client(
             "LocalClient",
             "https://www.thunderclient.com/oauth/callback",
             "account::read",
+            Default::default(),
         )
         .await;
-    let state = oauth::state::State::new(auth_db.clone());
+    let state = oauth::state::State::new(auth_db.clone(), settings.lifetimes);
     let sessions = MemoryStore::new();
+    let throttle = SignInThrottle::new(auth_db.clone(), settings.lockout.clone());
+    let oauth_routes =
+        crate::oauth::routes::routes(sessions.clone(), auth_db.clone(), &settings.rate_limit);
+    let metrics = Arc::new(MaintenanceMetrics::new());
+    let maintenance = Maintenance::spawn(
+        state.clone(),
+        sessions.clone(),
+        throttle.clone(),
+        settings.maintenance.interval,
+        metrics.clone(),
+    );
     let state = AppState {
-        sessions,
+        sessions: sessions.clone(),
         state,
         database: auth_db,
+        templates,
+        throttle,
+        mailer,
+        signup_policy,
+        settings: Arc::new(settings),
+        maintenance: metrics,
     };
 
-    Router::new()
+    let router = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
-        .nest("/oauth", crate::oauth::routes::routes())
+        .nest("/oauth", oauth_routes)
         .nest("/api", routes::routes())
-        .with_state(state)
+        .with_state(state);
+
+    (router, maintenance)
 }
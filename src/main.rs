@@ -1,11 +1,8 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use axum_oauth::{build_service, serve};
+use axum_oauth::{build_service, oauth::settings::Settings, serve};
 use oxide_auth_axum::WebError;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
-pub mod oauth;
-pub mod state;
-
 #[tokio::main]
 async fn main() {
     tracing_subscriber::registry()
@@ -16,13 +13,13 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let (app, listener) = build_service(None, 3000).await;
-    serve(app, listener).await;
+    let (app, listener, maintenance) = build_service(None, 3000, Settings::from_env()).await;
+    serve(app, listener, maintenance).await;
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 enum AuthError {
-    #[allow(dead_code)]
     WrongCredentials,
     MissingCredentials,
     InvalidToken,
@@ -0,0 +1,45 @@
+//! Security events. They're logged to the `axum_oauth::audit` target, so they can be routed apart
+//! from the other logs.
+
+use std::fmt;
+
+/// The target that audit events are logged to.
+pub const TARGET: &str = "axum_oauth::audit";
+
+#[derive(Debug)]
+pub enum AuditEvent<'a> {
+    /// A refresh token was presented after it had been rotated, so its family was revoked.
+    RefreshTokenReused {
+        owner_id: &'a str,
+        client_id: &'a str,
+    },
+}
+
+impl AuditEvent<'_> {
+    /// A name to filter the events by.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditEvent::RefreshTokenReused { .. } => "refresh_token_reused",
+        }
+    }
+}
+
+impl fmt::Display for AuditEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditEvent::RefreshTokenReused {
+                owner_id,
+                client_id,
+            } => write!(
+                f,
+                "A rotated refresh token of client {client_id} for owner {owner_id} was reused, \
+                 its family is revoked"
+            ),
+        }
+    }
+}
+
+/// Log the event.
+pub fn record(event: AuditEvent<'_>) {
+    tracing::warn!(target: TARGET, event = event.name(), "{}", event);
+}
@@ -11,6 +11,8 @@ use oxide_auth::{
         scope::Scope,
     },
 };
+use serde::{Deserialize, Serialize};
+use url::Url;
 
 use crate::oauth::scopes;
 
@@ -29,12 +31,20 @@ impl ClientMap {
     }
 
     /// Insert or update the client record.
-    pub fn register_client(&mut self, id: &str, name: &str, client: Client) {
+    pub fn register_client(
+        &mut self,
+        id: &str,
+        name: &str,
+        client: Client,
+        metadata: ClientMetadata,
+    ) {
         let id = id.to_owned();
         let password_policy = Self::current_policy(&self.password_policy);
         let record = ClientRecord {
             id: id.clone(),
             name: name.to_owned(),
+            metadata,
+            lifetimes: ClientLifetimes::default(),
             encoded_client: client.encode(password_policy),
         };
         self.clients.insert(id, record);
@@ -97,7 +107,7 @@ impl Registrar for ClientMap {
             .and_then(|scope| {
                 scope
                     .iter()
-                    .filter(|scope| scopes::SCOPES.contains(scope))
+                    .filter(|scope| scopes::is_registered(scope))
                     .collect::<Vec<_>>()
                     .join(" ")
                     .parse()
@@ -133,9 +143,35 @@ impl Registrar for ClientMap {
 pub struct ClientRecord {
     pub id: String,
     pub name: String,
+    pub metadata: ClientMetadata,
+    pub lifetimes: ClientLifetimes,
     pub(crate) encoded_client: EncodedClient,
 }
 
+/// Information about a client that is shown to users on the consent page.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ClientMetadata {
+    pub logo_uri: Option<Url>,
+    pub homepage_uri: Option<Url>,
+    pub policy_uri: Option<Url>,
+    /// The client is operated by the same party as this server.
+    pub first_party: bool,
+    /// The operator of the client has been vetted.
+    pub verified: bool,
+    /// The client gets refresh tokens without asking for the `offline_access` scope.
+    pub offline_access: bool,
+}
+
+/// Lifetimes of the client's codes and tokens, in seconds, where they differ from the defaults in
+/// [`TokenLifetimes`](crate::oauth::settings::TokenLifetimes).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientLifetimes {
+    pub access_token_secs: Option<u64>,
+    pub refresh_token_secs: Option<u64>,
+    pub refresh_token_max_secs: Option<u64>,
+    pub code_secs: Option<u64>,
+}
+
 impl ClientRecord {
     #[allow(dead_code)]
     fn encoded_client(&self) -> EncodedClient {
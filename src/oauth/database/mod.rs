@@ -1,21 +1,37 @@
 use axum_macros::FromRef;
+use once_cell::sync::Lazy;
 use oxide_auth::{
     endpoint::Scope,
     primitives::registrar::{Client, RegisteredUrl},
 };
-use secrecy::{ExposeSecret, Secret};
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use serde::Serialize;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
 use tokio::sync::RwLock;
 
 use self::{
-    clientmap::ClientMap,
-    resource::{client::ClientName, user::AuthUser},
+    clientmap::{ClientLifetimes, ClientMap, ClientMetadata},
+    resource::{
+        client::ClientName,
+        user::{AuthUser, HashedPassword},
+    },
+    usermap::UserMap,
 };
 
-use super::models::{ClientId, UserId};
+use super::{
+    hash_token,
+    mfa::{RecoveryCodes, TotpFactor},
+    models::{ClientId, UserId},
+    policy::username_key,
+    webauthn::Passkey,
+};
 
 pub mod clientmap;
 pub mod resource;
+pub mod usermap;
+
+/// What the passwords of unknown users are checked against.
+static UNKNOWN_USER_PASSWORD: Lazy<HashedPassword> =
+    Lazy::new(|| HashedPassword::hash("").expect("hashing a password"));
 
 #[derive(Clone, FromRef)]
 pub struct Database {
@@ -40,24 +56,48 @@ impl Database {
     pub fn new() -> Database {
         Database {
             inner: Inner {
-                user_db: Arc::new(RwLock::new(HashMap::<UserId, UserRecord>::new())),
+                user_db: Arc::new(RwLock::new(UserMap::new())),
                 client_db: Arc::new(RwLock::new(ClientMap::new())),
+                sign_in_failures: Arc::new(RwLock::new(HashMap::new())),
+                email_tokens: Arc::new(RwLock::new(HashMap::new())),
             },
         }
     }
 
+    /// Register a user, unless the username is taken regardless of case and Unicode composition.
     pub async fn register_user(
         &mut self,
         username: &str,
-        password: Secret<String>,
+        password: HashedPassword,
         given_name: &str,
-    ) -> UserId {
+    ) -> Result<UserId, StoreError> {
         let id = UserId::new();
-        let u = UserRecord::new(id, username, password.expose_secret(), given_name);
+        let u = UserRecord::new(id, username, password, given_name);
         let mut map_lock = self.inner.user_db.write().await;
-        map_lock.insert(id, u);
+        map_lock.insert(u)?;
 
-        id
+        Ok(id)
+    }
+
+    /// Register a user along with their unverified email address. Both are added under the same
+    /// lock, so if either is taken nothing is left behind, and nobody sees the user without it.
+    pub async fn register_user_with_email(
+        &mut self,
+        username: &str,
+        password: HashedPassword,
+        given_name: &str,
+        email: &str,
+    ) -> Result<UserId, StoreError> {
+        let id = UserId::new();
+        let u = UserRecord::new(id, username, password, given_name);
+        let mut map_lock = self.inner.user_db.write().await;
+        map_lock.insert(u)?;
+        if let Err(e) = map_lock.set_email(&id, email, false) {
+            map_lock.remove(&id);
+            return Err(e);
+        }
+
+        Ok(id)
     }
 
     pub async fn get_user_by_id(&self, user: &AuthUser) -> Result<UserRecord, StoreError> {
@@ -83,36 +123,346 @@ impl Database {
         Ok(true)
     }
 
+    /// The user with the username. Case and Unicode composition don't matter.
     pub async fn get_user_by_name(&self, username: &str) -> Result<UserRecord, StoreError> {
         let map_lock = self.inner.user_db.read().await;
-        for v in (*map_lock).values() {
-            if v.username == username {
-                return Ok(v.clone());
+        map_lock
+            .get_by_name(username)
+            .cloned()
+            .ok_or(StoreError::DoesNotExist)
+    }
+
+    /// The user with this email address. Case doesn't matter.
+    pub async fn get_user_by_email(&self, email: &str) -> Result<UserRecord, StoreError> {
+        let map_lock = self.inner.user_db.read().await;
+        map_lock
+            .get_by_email(email)
+            .cloned()
+            .ok_or(StoreError::DoesNotExist)
+    }
+
+    /// Set the user's email address, and whether they have shown that it's theirs. Email
+    /// addresses are unique across all users, regardless of case, and a user that hasn't verified
+    /// the address loses it to the one it's set for.
+    pub async fn set_email(
+        &self,
+        user_id: UserId,
+        email: &str,
+        verified: bool,
+    ) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        map_lock.set_email(&user_id, email, verified)
+    }
+
+    /// Mark the user's email address as verified, if it's still `email`. Returns whether it was.
+    pub async fn verify_email(&self, user_id: UserId, email: &str) -> bool {
+        let mut map_lock = self.inner.user_db.write().await;
+        let Some(record) = map_lock
+            .get_mut(&user_id)
+            .filter(|record| record.email.as_deref() == Some(email))
+        else {
+            return false;
+        };
+        record.email_verified = true;
+
+        true
+    }
+
+    /// Replace the password. Every session the user signed into ends with the old one.
+    pub async fn set_password(
+        &self,
+        user_id: UserId,
+        password: HashedPassword,
+    ) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        let record = map_lock.get_mut(&user_id).ok_or(StoreError::DoesNotExist)?;
+        record.password = password;
+        record.session_generation += 1;
+
+        Ok(())
+    }
+
+    /// Delete the user along with everything kept for them: their client authorizations, second
+    /// factors, email tokens and failed sign-ins. Tokens issued to clients are kept by the issuer
+    /// and have to be revoked there.
+    pub async fn delete_user(&self, user_id: UserId) -> Result<UserRecord, StoreError> {
+        let record = self
+            .inner
+            .user_db
+            .write()
+            .await
+            .remove(&user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        self.inner
+            .email_tokens
+            .write()
+            .await
+            .retain(|_, issued| issued.user_id != user_id);
+        self.clear_sign_in_failures(&ThrottleKey::account(&record.username))
+            .await;
+
+        Ok(record)
+    }
+
+    /// Issue a token for a link sent to the user at `email`, valid until `expires`, seconds since
+    /// the Unix epoch. Earlier tokens of the user for the same purpose are revoked.
+    pub async fn issue_email_token(
+        &self,
+        user_id: UserId,
+        purpose: EmailTokenPurpose,
+        email: &str,
+        expires: u64,
+    ) -> String {
+        let token = nanoid::nanoid!(32);
+        let mut map_lock = self.inner.email_tokens.write().await;
+        map_lock.retain(|_, issued| issued.user_id != user_id || issued.purpose != purpose);
+        map_lock.insert(
+            hash_token(&token),
+            EmailToken {
+                user_id,
+                purpose,
+                email: email.to_owned(),
+                expires,
+            },
+        );
+
+        token
+    }
+
+    /// The user a token was issued to for `purpose`, unless it expired at `now`. The token stays
+    /// usable.
+    pub async fn peek_email_token(
+        &self,
+        token: &str,
+        purpose: EmailTokenPurpose,
+        now: u64,
+    ) -> Option<AuthUser> {
+        let user_id = {
+            let map_lock = self.inner.email_tokens.read().await;
+            let issued = map_lock
+                .get(&hash_token(token))
+                .filter(|issued| issued.purpose == purpose && issued.expires > now)?;
+            issued.user_id
+        };
+
+        let map_lock = self.inner.user_db.read().await;
+        let record = map_lock.get(&user_id)?;
+
+        Some(AuthUser {
+            user_id: record.id,
+            username: record.username.clone(),
+        })
+    }
+
+    /// Forget the email tokens that expired at `now`, seconds since the Unix epoch. Returns how
+    /// many were forgotten.
+    pub async fn purge_email_tokens(&self, now: u64) -> usize {
+        let mut map_lock = self.inner.email_tokens.write().await;
+        let count = map_lock.len();
+        map_lock.retain(|_, issued| issued.expires > now);
+
+        count - map_lock.len()
+    }
+
+    /// Use up a token issued for `purpose`. Returns the user it was issued to and the address it
+    /// was sent to, unless it expired at `now`.
+    pub async fn consume_email_token(
+        &self,
+        token: &str,
+        purpose: EmailTokenPurpose,
+        now: u64,
+    ) -> Option<(AuthUser, String)> {
+        let hash = hash_token(token);
+        let issued = {
+            let mut map_lock = self.inner.email_tokens.write().await;
+            map_lock.retain(|_, issued| issued.expires > now);
+            if map_lock.get(&hash)?.purpose != purpose {
+                return None;
             }
-        }
+            map_lock.remove(&hash)?
+        };
+
+        let map_lock = self.inner.user_db.read().await;
+        let record = map_lock.get(&issued.user_id)?;
+        let user = AuthUser {
+            user_id: record.id,
+            username: record.username.clone(),
+        };
 
-        Err(StoreError::DoesNotExist)
+        Some((user, issued.email))
     }
 
+    /// Whether a user has the username, or one that differs from it only in case or Unicode
+    /// composition.
     pub async fn contains_user_name(&self, username: &str) -> bool {
-        match self.get_user_by_name(username).await {
-            Ok(record) => record.username == username,
-            Err(_) => false,
-        }
+        let map_lock = self.inner.user_db.read().await;
+        map_lock.contains_name(username)
     }
 
     // XXX - Doesn't really belong in a storage interface. It's just expeditious.
-    pub async fn verify_password(
+    /// The user with these credentials. Unknown users and wrong passwords are indistinguishable.
+    pub async fn authenticate(&self, username: &str, password: &str) -> Option<UserRecord> {
+        let user = self.get_user_by_name(username).await.ok();
+        // Unknown users are checked against a stand-in, so they take as long as wrong passwords
+        let hash = user.as_ref().map_or_else(
+            || UNKNOWN_USER_PASSWORD.clone(),
+            |user| user.password.clone(),
+        );
+        let password = password.to_owned();
+        let verified = tokio::task::spawn_blocking(move || hash.verify(&password))
+            .await
+            .unwrap_or(false);
+
+        user.filter(|_| verified)
+    }
+
+    /// Enroll an authenticator for the user, replacing their recovery codes.
+    pub async fn enroll_totp(
         &self,
-        username: &str,
-        password: &str,
-    ) -> Result<bool, StoreError> {
-        #[allow(unused_variables)]
+        user: &AuthUser,
+        totp: TotpFactor,
+        recovery_codes: RecoveryCodes,
+    ) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        let record = map_lock
+            .get_mut(&user.user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        record.totp = Some(totp);
+        record.recovery_codes = recovery_codes;
+
+        Ok(())
+    }
+
+    /// Check a code of the user's authenticator at `now`, seconds since the Unix epoch. An accepted
+    /// code can't be used again.
+    pub async fn verify_totp(&self, user_id: UserId, code: &str, now: u64) -> bool {
+        let mut map_lock = self.inner.user_db.write().await;
+        let Some(totp) = map_lock
+            .get_mut(&user_id)
+            .and_then(|record| record.totp.as_mut())
+        else {
+            return false;
+        };
+        let Some(step) = totp.verify(code, now) else {
+            return false;
+        };
+        totp.set_last_step(step);
+
+        true
+    }
+
+    /// Use up one of the user's recovery codes. Returns whether it was valid.
+    pub async fn use_recovery_code(&self, user_id: UserId, code: &str) -> bool {
+        let mut map_lock = self.inner.user_db.write().await;
+        map_lock
+            .get_mut(&user_id)
+            .filter(|record| record.totp.is_some())
+            .is_some_and(|record| record.recovery_codes.consume(code))
+    }
+
+    /// Register a passkey for the user. Credential IDs are unique across all users.
+    pub async fn add_passkey(&self, user: &AuthUser, passkey: Passkey) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        map_lock.add_passkey(&user.user_id, passkey)
+    }
+
+    /// The passkey with this credential ID, and the user it belongs to.
+    pub async fn find_passkey(&self, credential_id: &[u8]) -> Option<(AuthUser, Passkey)> {
         let map_lock = self.inner.user_db.read().await;
-        let db = self.get_user_by_name(username).await?;
-        let result = password == db.password.expose_secret();
+        let record = map_lock.get_by_credential(credential_id)?;
+        let passkey = record
+            .passkeys
+            .iter()
+            .find(|passkey| passkey.credential_id() == credential_id)?;
+        let user = AuthUser {
+            user_id: record.id,
+            username: record.username.clone(),
+        };
+
+        Some((user, passkey.clone()))
+    }
+
+    /// Record the signature counter of an assertion made with the user's passkey. Returns
+    /// whether it followed the last one, which is checked again here in case assertions raced.
+    pub async fn use_passkey(
+        &self,
+        user_id: UserId,
+        credential_id: &[u8],
+        sign_count: u32,
+    ) -> bool {
+        let mut map_lock = self.inner.user_db.write().await;
+        let Some(passkey) = map_lock.get_mut(&user_id).and_then(|record| {
+            record
+                .passkeys
+                .iter_mut()
+                .find(|passkey| passkey.credential_id() == credential_id)
+        }) else {
+            return false;
+        };
+        if !passkey.accepts_sign_count(sign_count) {
+            return false;
+        }
+        passkey.set_sign_count(sign_count);
+
+        true
+    }
+
+    pub async fn remove_passkey(
+        &self,
+        user: &AuthUser,
+        credential_id: &[u8],
+    ) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        map_lock.remove_passkey(&user.user_id, credential_id)?;
+
+        Ok(())
+    }
+
+    /// Record a failed sign-in at `at` milliseconds since the Unix epoch. Only the last `keep`
+    /// failures of the key are kept.
+    pub async fn add_sign_in_failure(&self, key: ThrottleKey, at: u64, keep: usize) {
+        let mut map_lock = self.inner.sign_in_failures.write().await;
+        let failures = map_lock.entry(key).or_default();
+        failures.push(at);
+        let excess = failures.len().saturating_sub(keep);
+        failures.drain(..excess);
+    }
+
+    /// Forget the failed sign-ins before `before` milliseconds since the Unix epoch, of every
+    /// key. Returns how many were forgotten.
+    pub async fn purge_sign_in_failures(&self, before: u64) -> usize {
+        let mut map_lock = self.inner.sign_in_failures.write().await;
+        let mut purged = 0;
+        map_lock.retain(|_, failures| {
+            let count = failures.len();
+            failures.retain(|at| *at >= before);
+            purged += count - failures.len();
+            !failures.is_empty()
+        });
+
+        purged
+    }
+
+    /// The failed sign-ins since `since` milliseconds since the Unix epoch, oldest first. Older
+    /// ones are forgotten.
+    pub async fn get_sign_in_failures(&self, key: &ThrottleKey, since: u64) -> Vec<u64> {
+        let mut map_lock = self.inner.sign_in_failures.write().await;
+        let Some(failures) = map_lock.get_mut(key) else {
+            return Vec::new();
+        };
+        failures.retain(|at| *at >= since);
+        let failures = failures.clone();
+        if failures.is_empty() {
+            map_lock.remove(key);
+        }
 
-        Ok(result)
+        failures
+    }
+
+    /// Forget the failed sign-ins. Returns whether there were any.
+    pub async fn clear_sign_in_failures(&self, key: &ThrottleKey) -> bool {
+        let mut map_lock = self.inner.sign_in_failures.write().await;
+        map_lock.remove(key).is_some()
     }
 
     pub async fn register_public_client(
@@ -120,6 +470,7 @@ impl Database {
         client_name: &str,
         url: &str,
         default_scope: &str,
+        metadata: ClientMetadata,
     ) -> Result<(String, Option<String>), StoreError> {
         let id = ClientId::new();
         let client = Client::public(
@@ -130,7 +481,7 @@ impl Database {
         tracing::debug!("Registering public client: {:?}", client);
 
         let mut client_lock = self.inner.client_db.write().await;
-        client_lock.register_client(id.as_str(), client_name, client);
+        client_lock.register_client(id.as_str(), client_name, client, metadata);
 
         // There is currently no easy way to search ClientMap for a record. So, thisscopescopescope
         // function will allways succeed.
@@ -142,6 +493,7 @@ impl Database {
         client_name: &str,
         url: &str,
         default_scope: &str,
+        metadata: ClientMetadata,
     ) -> Result<(String, Option<String>), StoreError> {
         let id = ClientId::new();
         let secret = nanoid::nanoid!(32);
@@ -153,7 +505,7 @@ impl Database {
         );
         tracing::debug!("Registering confidential client: {:?}", &client);
         let mut map_lock = self.inner.client_db.write().await;
-        map_lock.register_client(id.as_str(), client_name, client);
+        map_lock.register_client(id.as_str(), client_name, client, metadata);
 
         // There is currently no easy way to search ClientMap for a record. So, this
         // function will allways succeed.
@@ -172,55 +524,231 @@ impl Database {
         })
     }
 
-    pub async fn get_scope(&self, user_id: UserId, client_id: ClientId) -> Option<Scope> {
-        tracing::debug!("in get_scope()");
-        let map_lock = self.inner.user_db.read().await;
-        let record = map_lock.get(&user_id);
-        if record.is_some() {
-            let client_lst = record.unwrap().get_authorized_clients();
-            for auth in client_lst {
-                if auth.client_id == client_id {
-                    tracing::debug!("  current scope: {:?}", auth.scope);
-                    return Some(auth.scope.clone());
-                }
-            }
+    pub async fn get_client_metadata(
+        &self,
+        client_id: ClientId,
+    ) -> Result<ClientMetadata, StoreError> {
+        let map_lock = self.inner.client_db.read().await;
+        let record = map_lock
+            .clients
+            .get(client_id.as_str())
+            .ok_or(StoreError::DoesNotExist)?;
+
+        Ok(record.metadata.clone())
+    }
+
+    /// The lifetimes the client has of its own.
+    pub async fn get_client_lifetimes(
+        &self,
+        client_id: ClientId,
+    ) -> Result<ClientLifetimes, StoreError> {
+        let map_lock = self.inner.client_db.read().await;
+        let record = map_lock
+            .clients
+            .get(client_id.as_str())
+            .ok_or(StoreError::DoesNotExist)?;
+
+        Ok(record.lifetimes)
+    }
+
+    /// Replace the lifetimes the client has of its own.
+    pub async fn set_client_lifetimes(
+        &self,
+        client_id: ClientId,
+        lifetimes: ClientLifetimes,
+    ) -> Result<(), StoreError> {
+        let mut map_lock = self.inner.client_db.write().await;
+        let record = map_lock
+            .clients
+            .get_mut(client_id.as_str())
+            .ok_or(StoreError::DoesNotExist)?;
+        record.lifetimes = lifetimes;
+
+        Ok(())
+    }
+
+    /// Change whether the client is first-party or verified, and whether it gets refresh tokens
+    /// without asking for them, leaving what isn't given as it is.
+    pub async fn set_client_trust(
+        &self,
+        client_id: ClientId,
+        first_party: Option<bool>,
+        verified: Option<bool>,
+        offline_access: Option<bool>,
+    ) -> Result<ClientMetadata, StoreError> {
+        let mut map_lock = self.inner.client_db.write().await;
+        let record = map_lock
+            .clients
+            .get_mut(client_id.as_str())
+            .ok_or(StoreError::DoesNotExist)?;
+        if let Some(first_party) = first_party {
+            record.metadata.first_party = first_party;
+        }
+        if let Some(verified) = verified {
+            record.metadata.verified = verified;
+        }
+        if let Some(offline_access) = offline_access {
+            record.metadata.offline_access = offline_access;
         }
 
-        Some(Scope::from_str("").unwrap())
+        Ok(record.metadata.clone())
+    }
+
+    pub async fn get_client_default_scope(&self, client_id: ClientId) -> Result<Scope, StoreError> {
+        let map_lock = self.inner.client_db.read().await;
+        let record = map_lock
+            .clients
+            .get(client_id.as_str())
+            .ok_or(StoreError::DoesNotExist)?;
+
+        Ok(record.encoded_client.default_scope.clone())
+    }
+
+    /// The owner's authorization of the client, if they gave one.
+    pub async fn get_authorization(
+        &self,
+        user_id: UserId,
+        client_id: ClientId,
+    ) -> Option<ClientAuthorization> {
+        let map_lock = self.inner.user_db.read().await;
+        map_lock
+            .get(&user_id)?
+            .get_authorized_clients()
+            .iter()
+            .find(|auth| auth.client_id == client_id)
+            .cloned()
     }
 
+    /// Record the owner's consent to the client for `scope`, adding an authorization if they
+    /// have none for it yet.
     pub async fn update_client_scope(
         &self,
         user_id: UserId,
         client_id: ClientId,
         scope: Scope,
+        source: ConsentSource,
+        now: u64,
     ) -> Result<(), StoreError> {
         tracing::debug!("in update_client_scope()");
         let mut map_write = self.inner.user_db.write().await;
-        let record = map_write.get_mut(&user_id);
-        if record.is_some() {
-            let record = record.unwrap();
-            let auth_list: &mut Vec<ClientAuthorization> = record.get_authorized_clients_mut();
-            for auth in auth_list.iter_mut() {
-                if auth.client_id == client_id {
-                    auth.scope = scope;
-                    return Ok(());
-                }
-            }
-
-            // couldn't find client authorization, insert a new one
+        let record = map_write
+            .get_mut(&user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        let auth_list: &mut Vec<ClientAuthorization> = record.get_authorized_clients_mut();
+        if let Some(auth) = auth_list
+            .iter_mut()
+            .find(|auth| auth.client_id == client_id)
+        {
+            auth.scope = scope;
+            auth.source = source;
+            auth.updated_at = now;
+        } else {
             tracing::debug!("  unable to find authorization, inserting new one");
-            record.add_authorized_client(client_id, scope);
+            record.add_authorized_client(client_id, scope, source, now);
+        }
+
+        Ok(())
+    }
+
+    /// Note that the client used the owner's authorization at `now`. Returns whether the owner
+    /// still has one for the client.
+    ///
+    /// Clients use tokens on every request, so the records are only locked for writing when the
+    /// time of the last use changes.
+    pub async fn record_client_use(&self, user_id: UserId, client_id: ClientId, now: u64) -> bool {
+        {
+            let map_lock = self.inner.user_db.read().await;
+            let Some(authorization) = map_lock.get(&user_id).and_then(|record| {
+                record
+                    .authorized_clients
+                    .iter()
+                    .find(|auth| auth.client_id == client_id)
+            }) else {
+                return false;
+            };
+            if authorization.last_used_at.is_some_and(|last| last >= now) {
+                return true;
+            }
         }
 
-        Err(StoreError::DoesNotExist)
+        let mut map_lock = self.inner.user_db.write().await;
+        let Some(authorization) = map_lock.get_mut(&user_id).and_then(|record| {
+            record
+                .authorized_clients
+                .iter_mut()
+                .find(|auth| auth.client_id == client_id)
+        }) else {
+            return false;
+        };
+        authorization.last_used_at = Some(now);
+
+        true
+    }
+
+    /// Remove the owner's authorization of the client. Tokens already issued to the client are
+    /// kept by the issuer and have to be revoked there.
+    pub async fn revoke_client_authorization(
+        &self,
+        user_id: UserId,
+        client_id: ClientId,
+    ) -> Result<ClientAuthorization, StoreError> {
+        let mut map_lock = self.inner.user_db.write().await;
+        let record = map_lock.get_mut(&user_id).ok_or(StoreError::DoesNotExist)?;
+        let position = record
+            .authorized_clients
+            .iter()
+            .position(|auth| auth.client_id == client_id)
+            .ok_or(StoreError::DoesNotExist)?;
+
+        Ok(record.authorized_clients.remove(position))
     }
 }
 
 #[derive(Clone)]
 pub struct Inner {
-    pub(crate) user_db: Arc<RwLock<HashMap<UserId, UserRecord>>>,
+    pub(crate) user_db: Arc<RwLock<UserMap>>,
     pub(crate) client_db: Arc<RwLock<ClientMap>>,
+    pub(crate) sign_in_failures: Arc<RwLock<HashMap<ThrottleKey, Vec<u64>>>>,
+    /// Tokens of the links in emails, by their hash.
+    pub(crate) email_tokens: Arc<RwLock<HashMap<String, EmailToken>>>,
+}
+
+/// What the link with an email token does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+#[derive(Clone, Debug)]
+pub struct EmailToken {
+    user_id: UserId,
+    purpose: EmailTokenPurpose,
+    /// The address the link was sent to.
+    email: String,
+    /// Seconds since the Unix epoch.
+    expires: u64,
+}
+
+/// What failed sign-ins are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ThrottleKey {
+    Account(String),
+    Address(IpAddr),
+    /// An email address that password reset links are sent to.
+    Email(String),
+}
+
+impl ThrottleKey {
+    /// The key of the account with the username, the same however the username is written.
+    pub fn account(username: &str) -> Self {
+        ThrottleKey::Account(username_key(username))
+    }
+
+    /// The key of the email address, the same regardless of case.
+    pub fn email(address: &str) -> Self {
+        ThrottleKey::Email(usermap::email_key(address))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -228,18 +756,35 @@ pub struct UserRecord {
     id: UserId,
     given_name: String,
     username: String,
-    password: Secret<String>,
+    password: HashedPassword,
+    email: Option<String>,
+    /// The owner opened a link sent to `email`.
+    email_verified: bool,
     authorized_clients: Vec<ClientAuthorization>,
+    /// The enrolled authenticator. Signing in takes a code from it, or a recovery code, after the
+    /// password.
+    totp: Option<TotpFactor>,
+    recovery_codes: RecoveryCodes,
+    /// Passkeys sign the user in without a password, or stand in for the authenticator code.
+    passkeys: Vec<Passkey>,
+    /// Sessions signed into before the generation changed are no longer signed in.
+    session_generation: u64,
 }
 
 impl UserRecord {
-    pub fn new(id: UserId, user: &str, password: &str, given_name: &str) -> UserRecord {
+    pub fn new(id: UserId, user: &str, password: HashedPassword, given_name: &str) -> UserRecord {
         Self {
             id,
             username: user.to_owned(),
-            password: Secret::from(password.to_owned()),
+            password,
+            email: None,
+            email_verified: false,
             authorized_clients: Vec::<ClientAuthorization>::new(),
             given_name: given_name.to_owned(),
+            totp: None,
+            recovery_codes: RecoveryCodes::default(),
+            passkeys: Vec::new(),
+            session_generation: 0,
         }
     }
 
@@ -259,6 +804,14 @@ impl UserRecord {
         None
     }
 
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+
+    pub fn email_verified(&self) -> bool {
+        self.email_verified
+    }
+
     pub fn id(&self) -> Option<UserId> {
         if !self.username.is_empty() {
             return Some(self.id);
@@ -267,9 +820,21 @@ impl UserRecord {
         None
     }
 
-    pub fn add_authorized_client(&mut self, client_id: ClientId, scope: Scope) {
-        self.authorized_clients
-            .push(ClientAuthorization { client_id, scope });
+    pub fn add_authorized_client(
+        &mut self,
+        client_id: ClientId,
+        scope: Scope,
+        source: ConsentSource,
+        now: u64,
+    ) {
+        self.authorized_clients.push(ClientAuthorization {
+            client_id,
+            scope,
+            source,
+            granted_at: now,
+            updated_at: now,
+            last_used_at: None,
+        });
     }
 
     pub fn get_authorized_clients(&self) -> &Vec<ClientAuthorization> {
@@ -280,6 +845,22 @@ impl UserRecord {
         &mut self.authorized_clients
     }
 
+    pub fn totp(&self) -> Option<&TotpFactor> {
+        self.totp.as_ref()
+    }
+
+    pub fn recovery_codes(&self) -> &RecoveryCodes {
+        &self.recovery_codes
+    }
+
+    pub fn passkeys(&self) -> &[Passkey] {
+        &self.passkeys
+    }
+
+    pub fn session_generation(&self) -> u64 {
+        self.session_generation
+    }
+
     pub fn update_given_name(&mut self, name: &str) {
         self.given_name = name.to_owned();
     }
@@ -289,6 +870,24 @@ impl UserRecord {
 pub struct ClientAuthorization {
     pub client_id: ClientId,
     pub scope: oxide_auth::primitives::scope::Scope,
+    /// Who gave the latest consent.
+    pub source: ConsentSource,
+    /// When the owner first authorized the client, in seconds since the Unix epoch.
+    pub granted_at: u64,
+    /// When the consent was last given, which is when it expires from.
+    pub updated_at: u64,
+    /// When the client last got tokens with the authorization or accessed a resource with them.
+    pub last_used_at: Option<u64>,
+}
+
+/// Who consented to a client's authorization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConsentSource {
+    /// The owner on the consent page.
+    User,
+    /// Approved without asking, because the client is part of this service.
+    FirstParty,
 }
 
 #[derive(Debug)]
@@ -1,3 +1,8 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 
 use crate::oauth::models::{InvalidLengthError, UserId};
@@ -34,6 +39,67 @@ impl std::str::FromStr for AuthUser {
     }
 }
 
+/// How and when the owner of a session signed in.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Authentication {
+    /// Seconds since the Unix epoch.
+    pub auth_time: u64,
+    /// Authentication method references (RFC 8176), e.g. `pwd`, or `pwd`, `otp` and `mfa`.
+    pub amr: Vec<String>,
+    /// Authentication context class reference: [`Authentication::ACR_SINGLE_FACTOR`] or
+    /// [`Authentication::ACR_MULTI_FACTOR`].
+    pub acr: String,
+}
+
+impl Authentication {
+    pub const ACR_SINGLE_FACTOR: &'static str = "1";
+    pub const ACR_MULTI_FACTOR: &'static str = "2";
+
+    pub fn new(auth_time: u64, amr: &[&str]) -> Self {
+        let acr = if amr.contains(&"mfa") {
+            Self::ACR_MULTI_FACTOR
+        } else {
+            Self::ACR_SINGLE_FACTOR
+        };
+
+        Self {
+            auth_time,
+            amr: amr.iter().map(|method| method.to_string()).collect(),
+            acr: acr.to_owned(),
+        }
+    }
+}
+
+/// A password as the database keeps it: an Argon2id hash in the PHC string format.
+#[derive(Clone, Debug)]
+pub struct HashedPassword(Secret<String>);
+
+impl HashedPassword {
+    /// Hash the password with a random salt. Hashing is slow on purpose, so it runs on a thread
+    /// that may block.
+    pub async fn new(password: Secret<String>) -> Result<Self, argon2::password_hash::Error> {
+        tokio::task::spawn_blocking(move || Self::hash(password.expose_secret()))
+            .await
+            .map_err(|_| argon2::password_hash::Error::Crypto)?
+    }
+
+    pub(crate) fn hash(password: &str) -> Result<Self, argon2::password_hash::Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+
+        Ok(Self(Secret::new(hash.to_string())))
+    }
+
+    /// Whether `password` is the one that was hashed. Like hashing, it blocks.
+    pub(crate) fn verify(&self, password: &str) -> bool {
+        PasswordHash::new(self.0.expose_secret()).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
 pub struct AuthorizationQuery {
     pub user: AuthUser,
     pub client: AuthClient,
@@ -0,0 +1,172 @@
+use std::collections::{hash_map, HashMap};
+
+use super::{StoreError, UserRecord};
+use crate::oauth::{models::UserId, policy::username_key, webauthn::Passkey};
+
+/// The user records, with indexes of their usernames, email addresses and passkeys.
+///
+/// The username index is keyed by [`username_key`], so usernames that differ only in case or
+/// Unicode composition are the same name. Email addresses are compared regardless of ASCII case.
+/// Looking a user up by any of them doesn't scan the records.
+#[derive(Default)]
+pub struct UserMap {
+    users: HashMap<UserId, UserRecord>,
+    by_name: HashMap<String, UserId>,
+    by_email: HashMap<String, UserId>,
+    by_credential: HashMap<Vec<u8>, UserId>,
+}
+
+impl UserMap {
+    /// Create an empty map without any users in it.
+    pub fn new() -> UserMap {
+        UserMap::default()
+    }
+
+    /// Insert a new user, unless their username, or one the same regardless of case, is taken.
+    pub fn insert(&mut self, record: UserRecord) -> Result<(), StoreError> {
+        let key = username_key(&record.username);
+        if self.by_name.contains_key(&key) || self.users.contains_key(&record.id) {
+            return Err(StoreError::DuplicateRecord);
+        }
+        self.by_name.insert(key, record.id);
+        if let Some(email) = &record.email {
+            self.by_email.insert(email_key(email), record.id);
+        }
+        for passkey in &record.passkeys {
+            self.by_credential
+                .insert(passkey.credential_id().to_vec(), record.id);
+        }
+        self.users.insert(record.id, record);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, user_id: &UserId) -> Option<UserRecord> {
+        let record = self.users.remove(user_id)?;
+        self.by_name.remove(&username_key(&record.username));
+        if let Some(email) = &record.email {
+            self.by_email.remove(&email_key(email));
+        }
+        for passkey in &record.passkeys {
+            self.by_credential.remove(passkey.credential_id());
+        }
+
+        Some(record)
+    }
+
+    /// Set the user's email address, unless another user verified it. Another user that has it
+    /// without verifying it loses it, so unverified claims can't keep the owner from using it.
+    pub fn set_email(
+        &mut self,
+        user_id: &UserId,
+        email: &str,
+        verified: bool,
+    ) -> Result<(), StoreError> {
+        let key = email_key(email);
+        if !self.users.contains_key(user_id) {
+            return Err(StoreError::DoesNotExist);
+        }
+        if let Some(holder) = self.by_email.get(&key).filter(|id| *id != user_id) {
+            let holder = self.users.get_mut(holder).ok_or(StoreError::DoesNotExist)?;
+            if holder.email_verified {
+                return Err(StoreError::DuplicateRecord);
+            }
+            holder.email = None;
+        }
+        let record = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        if let Some(previous) = &record.email {
+            self.by_email.remove(&email_key(previous));
+        }
+        self.by_email.insert(key, record.id);
+        record.email = Some(email.to_owned());
+        record.email_verified = verified;
+
+        Ok(())
+    }
+
+    /// Add a passkey to the user's, unless any user has its credential ID.
+    pub fn add_passkey(&mut self, user_id: &UserId, passkey: Passkey) -> Result<(), StoreError> {
+        if self.by_credential.contains_key(passkey.credential_id()) {
+            return Err(StoreError::DuplicateRecord);
+        }
+        let record = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        self.by_credential
+            .insert(passkey.credential_id().to_vec(), record.id);
+        record.passkeys.push(passkey);
+
+        Ok(())
+    }
+
+    pub fn remove_passkey(
+        &mut self,
+        user_id: &UserId,
+        credential_id: &[u8],
+    ) -> Result<Passkey, StoreError> {
+        let record = self
+            .users
+            .get_mut(user_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        let position = record
+            .passkeys
+            .iter()
+            .position(|passkey| passkey.credential_id() == credential_id)
+            .ok_or(StoreError::DoesNotExist)?;
+        self.by_credential.remove(credential_id);
+
+        Ok(record.passkeys.remove(position))
+    }
+
+    pub fn get(&self, user_id: &UserId) -> Option<&UserRecord> {
+        self.users.get(user_id)
+    }
+
+    /// The record to change. Its username, email address and passkeys are only changed through
+    /// the map, which keeps the indexes up to date.
+    pub fn get_mut(&mut self, user_id: &UserId) -> Option<&mut UserRecord> {
+        self.users.get_mut(user_id)
+    }
+
+    /// The user with the username, regardless of case and Unicode composition.
+    pub fn get_by_name(&self, username: &str) -> Option<&UserRecord> {
+        let user_id = self.by_name.get(&username_key(username))?;
+        self.users.get(user_id)
+    }
+
+    /// The user with the email address, regardless of ASCII case.
+    pub fn get_by_email(&self, email: &str) -> Option<&UserRecord> {
+        let user_id = self.by_email.get(&email_key(email))?;
+        self.users.get(user_id)
+    }
+
+    /// The user with a passkey of this credential ID.
+    pub fn get_by_credential(&self, credential_id: &[u8]) -> Option<&UserRecord> {
+        let user_id = self.by_credential.get(credential_id)?;
+        self.users.get(user_id)
+    }
+
+    pub fn contains_name(&self, username: &str) -> bool {
+        self.by_name.contains_key(&username_key(username))
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, UserId, UserRecord> {
+        self.users.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+pub(super) fn email_key(email: &str) -> String {
+    email.to_ascii_lowercase()
+}
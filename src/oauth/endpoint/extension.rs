@@ -3,11 +3,13 @@ use oxide_auth::{
         accesstoken::Request as AccessTokenRequest, authorization::Request as AuthorizationRequest,
     },
     endpoint,
-    frontends::simple::extensions,
-    primitives::grant::Extensions,
+    frontends::simple::extensions::{self, AccessTokenAddon, AddonResult, AuthorizationAddon},
+    primitives::grant::{Extensions, Grant, GrantExtension, Value},
 };
 use oxide_auth_async::endpoint::{AccessTokenExtension, AuthorizationExtension, Extension};
 
+use crate::oauth::database::resource::user::Authentication;
+
 pub struct Empty;
 
 impl Extension for Empty {}
@@ -61,3 +63,52 @@ impl Extension for AddonList {
         Some(self)
     }
 }
+
+/// Records how the owner signed in on authorization codes, and carries it over to the tokens
+/// issued for them.
+pub struct AuthenticationAddon {
+    authentication: Option<Authentication>,
+}
+
+impl AuthenticationAddon {
+    const IDENTIFIER: &'static str = "authentication";
+
+    /// The addon for the authorization endpoint. Without an authentication, it only carries the
+    /// authentication of codes over to tokens.
+    pub fn new(authentication: Option<Authentication>) -> Self {
+        Self { authentication }
+    }
+
+    /// How the owner that authorized the grant signed in.
+    pub fn authentication(grant: &Grant) -> Option<Authentication> {
+        grant
+            .extensions
+            .private()
+            .find(|(identifier, _)| *identifier == Self::IDENTIFIER)
+            .and_then(|(_, value)| serde_json::from_str(value?).ok())
+    }
+}
+
+impl GrantExtension for AuthenticationAddon {
+    fn identifier(&self) -> &'static str {
+        Self::IDENTIFIER
+    }
+}
+
+impl AuthorizationAddon for AuthenticationAddon {
+    fn execute(&self, _: &dyn AuthorizationRequest) -> AddonResult {
+        let Some(authentication) = &self.authentication else {
+            return AddonResult::Ok;
+        };
+        match serde_json::to_string(authentication) {
+            Ok(data) => AddonResult::Data(Value::private(Some(data))),
+            Err(_) => AddonResult::Err,
+        }
+    }
+}
+
+impl AccessTokenAddon for AuthenticationAddon {
+    fn execute(&self, _: &dyn AccessTokenRequest, code_data: Option<Value>) -> AddonResult {
+        code_data.map_or(AddonResult::Ok, AddonResult::Data)
+    }
+}
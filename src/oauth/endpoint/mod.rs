@@ -1,8 +1,9 @@
-use super::primitives::Guard;
+use super::primitives::{CodeAuthorizer, UsageIssuer};
 use oxide_auth::{
+    code_grant::error::AccessTokenErrorType,
     endpoint::{OAuthError, Template, WebRequest},
     frontends::simple::extensions::Pkce,
-    primitives::{authorizer::AuthMap, generator::RandomGenerator, issuer::TokenMap, scope::Scope},
+    primitives::scope::Scope,
 };
 use oxide_auth_async::{
     endpoint::{
@@ -17,12 +18,14 @@ use oxide_auth_async::{
 };
 use oxide_auth_axum::OAuthRequest;
 
+use crate::oauth::database::resource::user::Authentication;
+
 pub mod extension;
 
 pub struct Endpoint<'a, Registrar, Extension, Solicitor, Scopes> {
     pub(super) registrar: &'a Registrar,
-    pub(super) authorizer: Guard<'a, AuthMap<RandomGenerator>>,
-    pub(super) issuer: Guard<'a, TokenMap<RandomGenerator>>,
+    pub(super) authorizer: CodeAuthorizer<'a>,
+    pub(super) issuer: UsageIssuer<'a>,
     pub(super) extension: Extension,
     pub(super) solicitor: Solicitor,
     pub(super) scopes: Scopes,
@@ -50,6 +53,25 @@ where
         }
     }
 
+    /// An endpoint with another registrar, e.g. one that knows which client credentials were
+    /// checked already.
+    pub fn with_registrar<R>(
+        self,
+        registrar: &'a R,
+    ) -> Endpoint<'a, R, Extension, Solicitor, Scopes>
+    where
+        R: primitives::Registrar + Send + Sync,
+    {
+        Endpoint {
+            registrar,
+            authorizer: self.authorizer,
+            issuer: self.issuer,
+            extension: self.extension,
+            solicitor: self.solicitor,
+            scopes: self.scopes,
+        }
+    }
+
     pub fn with_solicitor<S>(
         self,
         solicitor: S,
@@ -71,6 +93,24 @@ where
         }
     }
 
+    /// An endpoint that carries grant extensions from authorization codes over to the tokens
+    /// issued for them.
+    pub fn with_extensions(
+        self,
+    ) -> Endpoint<'a, Registrar, extension::AddonList, Solicitor, Scopes> {
+        let mut extension = extension::AddonList::default();
+        extension.push_code(extension::AuthenticationAddon::new(None));
+
+        Endpoint {
+            registrar: self.registrar,
+            authorizer: self.authorizer,
+            issuer: self.issuer,
+            extension,
+            solicitor: self.solicitor,
+            scopes: self.scopes,
+        }
+    }
+
     pub fn authorization_flow(self) -> AuthorizationFlow<Self, OAuthRequest> {
         match AuthorizationFlow::prepare(self) {
             Ok(flow) => flow,
@@ -107,6 +147,17 @@ where
     }
 }
 
+impl<'a, Registrar, Solicitor, Scopes>
+    Endpoint<'a, Registrar, extension::AddonList, Solicitor, Scopes>
+{
+    /// Record how the owner signed in on the grant, so it can be reported with the tokens.
+    pub fn with_authentication(mut self, authentication: Option<Authentication>) -> Self {
+        self.extension
+            .push_code(extension::AuthenticationAddon::new(authentication));
+        self
+    }
+}
+
 impl<'a, Request, Registrar, Extension, Solicitor, Scopes> endpoint::Endpoint<Request>
     for Endpoint<'a, Registrar, Extension, Solicitor, Scopes>
 where
@@ -140,7 +191,19 @@ where
         Some(&mut self.scopes)
     }
 
-    fn response(&mut self, _: &mut Request, _: Template) -> Result<Request::Response, Self::Error> {
+    fn response(
+        &mut self,
+        _: &mut Request,
+        mut kind: Template,
+    ) -> Result<Request::Response, Self::Error> {
+        // oxide-auth reports a code it can't find as an invalid request, but like a code of
+        // another client it's an invalid grant (RFC 6749, section 5.2)
+        if let Some(error) = kind.access_token_error() {
+            if self.authorizer.unknown_code() {
+                error.set_type(AccessTokenErrorType::InvalidGrant);
+            }
+        }
+
         Ok(Default::default())
     }
 
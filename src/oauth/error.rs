@@ -1,6 +1,12 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::oauth::{
+    policy::Violation,
+    templates::{ErrorPage, Pages},
 };
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -21,6 +27,31 @@ pub enum Error {
         source: oxide_auth_axum::WebError,
     },
     ResourceConflict,
+    InvalidUri {
+        field: &'static str,
+    },
+    /// A passkey ceremony failed.
+    Webauthn {
+        source: crate::oauth::webauthn::WebauthnError,
+    },
+    /// An email could not be sent.
+    Mail {
+        source: crate::oauth::mailer::MailError,
+    },
+    /// The owner confirmed an action with the wrong password.
+    WrongPassword,
+    /// Fields of a form that the sign-up policy doesn't accept.
+    Validation {
+        errors: Vec<crate::oauth::policy::FieldError>,
+    },
+    /// The account is locked out after too many failed attempts.
+    TooManyAttempts,
+    /// A form was posted without a valid CSRF token.
+    Csrf,
+    /// The request lacks valid credentials for the admin API.
+    Unauthorized,
+    /// Only first-party clients may make the request, whatever scope others were granted.
+    FirstPartyOnly,
     InternalError,
 }
 
@@ -37,6 +68,18 @@ impl std::fmt::Display for Error {
             Error::OAuth { source } => write!(f, "{source}"),
             Error::InternalError => write!(f, "Unexpected internal error"),
             Error::ResourceConflict => write!(f, "User already exists"),
+            Error::InvalidUri { field } => write!(f, "Invalid URI in field: {field}"),
+            Error::Webauthn { source } => write!(f, "Passkey rejected: {source}"),
+            Error::Mail { source } => write!(f, "{source}"),
+            Error::WrongPassword => write!(f, "Wrong password"),
+            Error::Validation { errors } => {
+                let fields = errors.iter().map(|e| e.field).collect::<Vec<_>>();
+                write!(f, "Invalid fields: {}", fields.join(", "))
+            }
+            Error::TooManyAttempts => write!(f, "Too many failed attempts"),
+            Error::Csrf => write!(f, "Missing or invalid CSRF token"),
+            Error::Unauthorized => write!(f, "Unauthorized"),
+            Error::FirstPartyOnly => write!(f, "Only first-party clients may do this"),
         }
     }
 }
@@ -51,6 +94,15 @@ impl std::error::Error for Error {
             Error::OAuth { source } => Some(source),
             Error::InternalError => None,
             Error::ResourceConflict => None,
+            Error::InvalidUri { .. } => None,
+            Error::Webauthn { source } => Some(source),
+            Error::Mail { source } => Some(source),
+            Error::WrongPassword => None,
+            Error::Validation { .. } => None,
+            Error::TooManyAttempts => None,
+            Error::Csrf => None,
+            Error::Unauthorized => None,
+            Error::FirstPartyOnly => None,
         }
     }
 }
@@ -61,8 +113,58 @@ impl IntoResponse for Error {
             source.into_response()
         } else if let Self::ResourceConflict = self {
             (StatusCode::CONFLICT, "User already exists").into_response()
+        } else if let Self::Validation { errors } = self {
+            // Taking a username that exists is a conflict, anything else is invalid
+            let status = if errors.iter().all(|e| e.code == Violation::Taken.code()) {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::UNPROCESSABLE_ENTITY
+            };
+            (status, Json(serde_json::json!({ "errors": errors }))).into_response()
+        } else if let Self::InvalidUri { .. } = self {
+            (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()).into_response()
+        } else if let Self::Webauthn { .. } = self {
+            (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+        } else if let Self::TooManyAttempts = self {
+            (StatusCode::TOO_MANY_REQUESTS, self.to_string()).into_response()
+        } else if let Self::Csrf | Self::WrongPassword | Self::FirstPartyOnly = self {
+            (StatusCode::FORBIDDEN, self.to_string()).into_response()
+        } else if let Self::Unauthorized = self {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                self.to_string(),
+            )
+                .into_response()
+        } else if let Self::NotFound = self {
+            (StatusCode::NOT_FOUND, self.to_string()).into_response()
         } else {
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
+
+/// An error that is rendered as a localized page, for handlers that serve a browser.
+pub struct HtmlError {
+    error: Error,
+    pages: Pages,
+}
+
+impl HtmlError {
+    pub fn new(error: Error, pages: Pages) -> Self {
+        Self { error, pages }
+    }
+}
+
+impl IntoResponse for HtmlError {
+    fn into_response(self) -> Response {
+        let response = self.error.into_response();
+        let status = response.status();
+        if !status.is_client_error() && !status.is_server_error() {
+            return response;
+        }
+
+        let page = ErrorPage::new(self.pages.locale(), status);
+        (status, self.pages.render(&page)).into_response()
+    }
+}
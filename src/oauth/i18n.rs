@@ -0,0 +1,215 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use fluent_langneg::{accepted_languages, negotiate_languages, NegotiationStrategy};
+use once_cell::sync::Lazy;
+use unic_langid::LanguageIdentifier;
+
+/// The language used when none of the requested languages are available.
+pub const DEFAULT_LOCALE: &str = "en";
+
+const RESOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../../locales/en/oauth.ftl")),
+    ("de", include_str!("../../locales/de/oauth.ftl")),
+    ("fr", include_str!("../../locales/fr/oauth.ftl")),
+];
+
+static CATALOG: Lazy<Catalog> = Lazy::new(Catalog::load);
+
+struct Catalog {
+    default: LanguageIdentifier,
+    available: Vec<LanguageIdentifier>,
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Catalog {
+    fn load() -> Self {
+        let mut available = Vec::new();
+        let mut bundles = Vec::new();
+        for (lang, source) in RESOURCES {
+            let lang: LanguageIdentifier = lang.parse().expect("invalid locale identifier");
+            let resource = FluentResource::try_new(source.to_string())
+                .unwrap_or_else(|(_, errors)| panic!("invalid catalog {lang}: {errors:?}"));
+            let mut bundle = FluentBundle::new_concurrent(vec![lang.clone()]);
+            // The Unicode isolation marks around arguments end up verbatim in the HTML
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .unwrap_or_else(|errors| panic!("duplicate messages in {lang}: {errors:?}"));
+            available.push(lang);
+            bundles.push(bundle);
+        }
+
+        Self {
+            default: DEFAULT_LOCALE.parse().unwrap(),
+            available,
+            bundles,
+        }
+    }
+
+    fn bundle(&self, lang: &LanguageIdentifier) -> Option<&FluentBundle<FluentResource>> {
+        self.available
+            .iter()
+            .position(|available| available == lang)
+            .map(|i| &self.bundles[i])
+    }
+}
+
+/// The languages a response is rendered in, most preferred first.
+///
+/// Extracting a `Locale` negotiates it from the OIDC `ui_locales` parameter (which takes
+/// precedence) and the `Accept-Language` header. Messages missing from a language fall back to the
+/// next one in the chain, which always ends with the default locale.
+#[derive(Clone, Debug)]
+pub struct Locale {
+    chain: Vec<LanguageIdentifier>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::negotiate(&[])
+    }
+}
+
+impl Locale {
+    pub fn negotiate(requested: &[LanguageIdentifier]) -> Self {
+        let chain = negotiate_languages(
+            requested,
+            &CATALOG.available,
+            Some(&CATALOG.default),
+            NegotiationStrategy::Filtering,
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+
+        Self { chain }
+    }
+
+    /// The primary language, for the `lang` attribute of a page.
+    pub fn lang(&self) -> String {
+        self.chain.first().unwrap_or(&CATALOG.default).to_string()
+    }
+
+    /// Look up a message. Returns `None` if no language in the chain has it.
+    pub fn get(&self, id: &str, args: &[(&str, &str)]) -> Option<String> {
+        let args = args
+            .iter()
+            .fold(FluentArgs::new(), |mut fluent_args, (name, value)| {
+                fluent_args.set(*name, *value);
+                fluent_args
+            });
+
+        self.chain
+            .iter()
+            .filter_map(|lang| CATALOG.bundle(lang))
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = Vec::new();
+                let message = bundle.format_pattern(pattern, Some(&args), &mut errors);
+                if !errors.is_empty() {
+                    tracing::warn!("error formatting message {}: {:?}", id, errors);
+                }
+
+                Some(message.into_owned())
+            })
+    }
+
+    /// A message without arguments. Missing messages are rendered as their id.
+    pub fn t(&self, id: &str) -> String {
+        self.fmt(id, &[])
+    }
+
+    /// A message with arguments. Missing messages are rendered as their id.
+    pub fn fmt(&self, id: &str, args: &[(&str, &str)]) -> String {
+        self.get(id, args).unwrap_or_else(|| {
+            tracing::warn!("missing message: {}", id);
+            id.to_owned()
+        })
+    }
+
+    /// A message that contains markup. The arguments are HTML-escaped, so the result can be
+    /// rendered with the `safe` filter.
+    pub fn html(&self, id: &str, args: &[(&str, &str)]) -> String {
+        let escaped = args
+            .iter()
+            .map(|(name, value)| (*name, escape_html(value)))
+            .collect::<Vec<_>>();
+        let args = escaped
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect::<Vec<_>>();
+
+        self.get(id, &args).unwrap_or_else(|| id.to_owned())
+    }
+
+    /// The translated description of a scope, or of its sensitivity warning.
+    pub fn scope(&self, scope: &str, warning: bool) -> Option<String> {
+        let mut id = format!("scope-{}", scope.replace(':', "-"));
+        if warning {
+            id.push_str("-warning");
+        }
+
+        self.get(&id, &[])
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .chars()
+        .fold(String::with_capacity(value.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#x27;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+/// Languages from a `ui_locales` query parameter, which is a space-separated list of language
+/// tags. A sign-in `callback` is searched too, so the preference survives the sign-in redirect.
+fn ui_locales(query: &str) -> Vec<LanguageIdentifier> {
+    let mut locales = Vec::new();
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "ui_locales" => locales.extend(value.split_whitespace().filter_map(|s| s.parse().ok())),
+            "callback" => {
+                if let Some((_, callback_query)) = value.split_once('?') {
+                    locales.extend(ui_locales(callback_query));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    locales
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for Locale
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let mut requested = parts.uri.query().map(ui_locales).unwrap_or_default();
+        if let Some(accept_language) = parts
+            .headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+        {
+            requested.extend(accepted_languages::parse(accept_language));
+        }
+
+        Ok(Self::negotiate(&requested))
+    }
+}
@@ -0,0 +1,184 @@
+//! Purging of expired authorization codes, tokens, sessions, email tokens and failed sign-ins,
+//! which would otherwise stay in memory for as long as the server runs.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_session::MemoryStore;
+use chrono::Utc;
+use serde::Serialize;
+use tokio::{
+    sync::oneshot,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::oauth::{state::State, throttle::SignInThrottle};
+
+/// How many expired entries were purged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeCounts {
+    pub codes: u64,
+    pub access_tokens: u64,
+    pub refresh_tokens: u64,
+    /// The session store doesn't say what it purged, so this is how much smaller it got, less the
+    /// sessions started meanwhile.
+    pub sessions_approx: u64,
+    pub email_tokens: u64,
+    pub sign_in_failures: u64,
+}
+
+impl PurgeCounts {
+    pub fn total(&self) -> u64 {
+        self.codes
+            + self.access_tokens
+            + self.refresh_tokens
+            + self.sessions_approx
+            + self.email_tokens
+            + self.sign_in_failures
+    }
+}
+
+/// What the maintenance task did since the server started.
+#[derive(Default)]
+pub struct MaintenanceMetrics {
+    runs: AtomicU64,
+    /// Seconds since the Unix epoch, 0 before the first run.
+    last_run_at: AtomicU64,
+    codes: AtomicU64,
+    access_tokens: AtomicU64,
+    refresh_tokens: AtomicU64,
+    sessions_approx: AtomicU64,
+    email_tokens: AtomicU64,
+    sign_in_failures: AtomicU64,
+}
+
+/// The metrics as reported by the admin API.
+#[derive(Debug, Serialize)]
+pub struct MaintenanceReport {
+    pub runs: u64,
+    /// Seconds since the Unix epoch.
+    pub last_run_at: Option<u64>,
+    /// The entries purged by all runs.
+    pub purged: PurgeCounts,
+}
+
+impl MaintenanceMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, purged: PurgeCounts, now: u64) {
+        self.codes.fetch_add(purged.codes, Ordering::Relaxed);
+        self.access_tokens
+            .fetch_add(purged.access_tokens, Ordering::Relaxed);
+        self.refresh_tokens
+            .fetch_add(purged.refresh_tokens, Ordering::Relaxed);
+        self.sessions_approx
+            .fetch_add(purged.sessions_approx, Ordering::Relaxed);
+        self.email_tokens
+            .fetch_add(purged.email_tokens, Ordering::Relaxed);
+        self.sign_in_failures
+            .fetch_add(purged.sign_in_failures, Ordering::Relaxed);
+        self.last_run_at.store(now, Ordering::Relaxed);
+        self.runs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> MaintenanceReport {
+        MaintenanceReport {
+            runs: self.runs.load(Ordering::Relaxed),
+            last_run_at: Some(self.last_run_at.load(Ordering::Relaxed)).filter(|&at| at > 0),
+            purged: PurgeCounts {
+                codes: self.codes.load(Ordering::Relaxed),
+                access_tokens: self.access_tokens.load(Ordering::Relaxed),
+                refresh_tokens: self.refresh_tokens.load(Ordering::Relaxed),
+                sessions_approx: self.sessions_approx.load(Ordering::Relaxed),
+                email_tokens: self.email_tokens.load(Ordering::Relaxed),
+                sign_in_failures: self.sign_in_failures.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// The background task that purges expired entries every interval.
+///
+/// The task stops when it's shut down, or when this handle is dropped.
+pub struct Maintenance {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Maintenance {
+    pub fn spawn(
+        state: State,
+        sessions: MemoryStore,
+        throttle: SignInThrottle,
+        interval: Duration,
+        metrics: Arc<MaintenanceMetrics>,
+    ) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let mut ticks = time::interval_at(Instant::now() + interval, interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = &mut stopped => break,
+                }
+
+                let purged = purge(&state, &sessions, &throttle).await;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default();
+                metrics.record(purged, now);
+                if purged.total() > 0 {
+                    tracing::info!(
+                        "purged {} codes, {} access tokens, {} refresh tokens, about {} sessions, {} email tokens and {} failed sign-ins",
+                        purged.codes,
+                        purged.access_tokens,
+                        purged.refresh_tokens,
+                        purged.sessions_approx,
+                        purged.email_tokens,
+                        purged.sign_in_failures
+                    );
+                }
+            }
+        });
+
+        Self { stop, task }
+    }
+
+    /// Stop the task. A purge under way is finished first.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        if let Err(e) = self.task.await {
+            tracing::error!("the maintenance task failed: {}", e);
+        }
+    }
+}
+
+/// Purge the codes, tokens, sessions and email tokens that have expired, and the failed sign-ins
+/// that are past the lockout window.
+pub async fn purge(
+    state: &State,
+    sessions: &MemoryStore,
+    throttle: &SignInThrottle,
+) -> PurgeCounts {
+    let mut purged = state.purge_expired(Utc::now()).await;
+    purged.sign_in_failures = throttle.purge_expired().await as u64;
+
+    // The store doesn't say what it cleaned up, so sessions started meanwhile are counted off it
+    let before = sessions.count().await;
+    if let Err(e) = sessions.cleanup().await {
+        tracing::error!("unable to purge expired sessions: {}", e);
+    }
+    purged.sessions_approx = before.saturating_sub(sessions.count().await) as u64;
+
+    purged
+}
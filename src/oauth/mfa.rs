@@ -0,0 +1,165 @@
+//! Second factors for signing in: TOTP authenticators (RFC 6238) and recovery codes.
+
+use qrcode::{render::svg, QrCode};
+use secrecy::{ExposeSecret, Secret};
+use totp_rs::{Algorithm, TOTP};
+
+use crate::oauth::{constant_time_eq, hash_token};
+
+/// The issuer shown next to the account in authenticator apps.
+pub const ISSUER: &str = "Axum OAuth";
+const DIGITS: usize = 6;
+/// Seconds per time step.
+const STEP: u64 = 30;
+/// Codes of the previous and the next time step are accepted too, for clocks that are a bit off.
+const SKEW: u64 = 1;
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+/// Digits and lowercase letters, without the ones that are easily confused.
+const RECOVERY_CODE_ALPHABET: [char; 31] = [
+    '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'j', 'k', 'm',
+    'n', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+/// A TOTP authenticator enrolled by a user.
+#[derive(Clone, Debug)]
+pub struct TotpFactor {
+    /// The shared secret, base32 encoded.
+    secret: Secret<String>,
+    /// The last time step a code was accepted for. Codes of that step and earlier are rejected,
+    /// so an observed code can't be replayed.
+    last_step: Option<u64>,
+}
+
+impl TotpFactor {
+    /// An authenticator with a new random secret.
+    pub fn generate() -> Self {
+        let secret = totp_rs::Secret::generate_secret().to_encoded().to_string();
+        Self {
+            secret: Secret::new(secret),
+            last_step: None,
+        }
+    }
+
+    /// An authenticator with a base32 encoded secret, e.g. one generated earlier by
+    /// [`TotpFactor::generate`].
+    pub fn from_secret(secret: &str) -> Option<Self> {
+        totp_rs::Secret::Encoded(secret.to_owned())
+            .to_bytes()
+            .ok()?;
+        Some(Self {
+            secret: Secret::new(secret.to_owned()),
+            last_step: None,
+        })
+    }
+
+    pub fn secret(&self) -> &Secret<String> {
+        &self.secret
+    }
+
+    /// The `otpauth://` URI to enroll the authenticator with.
+    pub fn url(&self, username: &str) -> String {
+        self.totp(username).get_url()
+    }
+
+    /// The enrollment URI as a QR code, in SVG.
+    pub fn qr_code(&self, username: &str) -> Option<String> {
+        let code = QrCode::new(self.url(username)).ok()?;
+        Some(code.render::<svg::Color>().min_dimensions(200, 200).build())
+    }
+
+    /// The time step that `code` is valid for at `now`, seconds since the Unix epoch. `None` if
+    /// the code is wrong or its step has been used already.
+    pub fn verify(&self, code: &str, now: u64) -> Option<u64> {
+        let code = code.trim();
+        let totp = self.totp("");
+        let current = now / STEP;
+
+        (current.saturating_sub(SKEW)..=current + SKEW)
+            .filter(|step| self.last_step.is_none_or(|last| *step > last))
+            .find(|step| {
+                let expected = totp.generate(step * STEP);
+                constant_time_eq(expected.as_bytes(), code.as_bytes())
+            })
+    }
+
+    pub fn last_step(&self) -> Option<u64> {
+        self.last_step
+    }
+
+    pub(crate) fn set_last_step(&mut self, step: u64) {
+        self.last_step = Some(step);
+    }
+
+    fn totp(&self, username: &str) -> TOTP {
+        let secret = totp_rs::Secret::Encoded(self.secret.expose_secret().clone())
+            .to_bytes()
+            .unwrap_or_default();
+        // The account name can't contain a colon, which separates it from the issuer in the label
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            DIGITS,
+            SKEW as u8,
+            STEP,
+            secret,
+            Some(ISSUER.to_owned()),
+            username.replace(':', "_"),
+        )
+    }
+}
+
+/// One-time codes that stand in for the authenticator when it's lost.
+///
+/// Only hashes of the codes are stored. The codes themselves are shown to the user once, when
+/// they are generated.
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryCodes {
+    hashes: Vec<String>,
+}
+
+impl RecoveryCodes {
+    /// New codes, and the hashes to store.
+    pub fn generate() -> (Vec<String>, Self) {
+        let codes = (0..RECOVERY_CODES)
+            .map(|_| {
+                let code = nanoid::nanoid!(RECOVERY_CODE_LENGTH, &RECOVERY_CODE_ALPHABET);
+                let (head, tail) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+                format!("{head}-{tail}")
+            })
+            .collect::<Vec<_>>();
+        let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+        (codes, Self { hashes })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Use up a code. Returns whether it was one of the remaining codes.
+    pub fn consume(&mut self, code: &str) -> bool {
+        let hash = hash_recovery_code(code);
+        let Some(position) = self
+            .hashes
+            .iter()
+            .position(|stored| constant_time_eq(stored.as_bytes(), hash.as_bytes()))
+        else {
+            return false;
+        };
+        self.hashes.remove(position);
+
+        true
+    }
+}
+
+/// Case, dashes and spaces don't matter.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect::<String>();
+
+    hash_token(&normalized)
+}
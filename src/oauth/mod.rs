@@ -1,15 +1,26 @@
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
+pub mod audit;
 pub mod database;
 pub mod endpoint;
 pub mod error;
+pub mod i18n;
+pub mod mailer;
+pub mod maintenance;
+pub mod mfa;
 pub mod models;
+pub mod policy;
 pub mod primitives;
+pub mod rate_limit;
 pub mod routes;
 pub mod scopes;
+pub mod settings;
 pub mod solicitor;
 pub mod state;
 pub mod templates;
+pub mod throttle;
+pub mod webauthn;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "consent", rename_all = "lowercase")]
@@ -17,3 +28,17 @@ pub enum Consent {
     Allow,
     Deny,
 }
+
+/// Hash a random token for storage, so the stored hashes are no use to whoever reads them. The
+/// tokens are random enough that a fast hash does.
+pub(crate) fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Compare secrets without revealing through timing how much of them matched.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
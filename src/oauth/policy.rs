@@ -0,0 +1,247 @@
+//! What sign-up accepts: username rules, password strength and breached passwords.
+//!
+//! Violations are reported per form field, so a client can show each one next to its field.
+
+use std::{collections::HashSet, sync::Arc};
+
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::oauth::{
+    i18n::Locale,
+    settings::{SignupSettings, UsernameCharset},
+};
+
+/// Characters allowed in usernames besides letters and digits.
+const USERNAME_SYMBOLS: &[char] = &['.', '_', '-'];
+/// Passwords are capped so hashing them can't be used to tie up the server.
+const PASSWORD_MAX_LENGTH: usize = 256;
+
+/// The username as stored: NFKC normalized, so look-alike compositions are the same name.
+pub fn normalize_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+/// The form of a username that uniqueness is decided by. Usernames that differ only in case or
+/// Unicode composition have the same key.
+pub fn username_key(username: &str) -> String {
+    normalize_username(username).to_lowercase().nfkc().collect()
+}
+
+/// How strong a password is estimated to be, from 0 (guessed right away) to 4 (very strong).
+///
+/// The estimate is the entropy of a brute force over the character classes the password uses,
+/// where characters that repeat or continue a sequence from the previous one don't count.
+pub fn password_strength(password: &str) -> u8 {
+    let mut pool = 0;
+    let classes = [
+        (char::is_ascii_lowercase as fn(&char) -> bool, 26),
+        (char::is_ascii_uppercase, 26),
+        (char::is_ascii_digit, 10),
+        (char::is_ascii_punctuation, 33),
+    ];
+    for (class, size) in classes {
+        if password.chars().any(|c| class(&c)) {
+            pool += size;
+        }
+    }
+    if password.chars().any(|c| c == ' ') {
+        pool += 1;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+
+    let mut effective = 0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        let continues = previous.is_some_and(|p| (c as i64 - p as i64).abs() <= 1);
+        if !continues {
+            effective += 1;
+        }
+        previous = Some(c);
+    }
+
+    let bits = effective as f64 * f64::from(pool.max(1)).log2();
+    match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+/// What sign-up, and setting a new password, accepts.
+#[derive(Clone, Debug)]
+pub struct SignupPolicy {
+    settings: SignupSettings,
+    /// Lowercased passwords known from breaches.
+    breached: Arc<HashSet<String>>,
+}
+
+impl SignupPolicy {
+    /// The policy of the settings, with the breached passwords read from their list.
+    pub fn new(settings: SignupSettings) -> Result<Self, std::io::Error> {
+        let breached = match &settings.breached_passwords {
+            Some(path) => std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_lowercase)
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Self {
+            settings,
+            breached: Arc::new(breached),
+        })
+    }
+
+    /// The normalized username, if it's allowed.
+    pub fn check_username(&self, username: &str) -> Result<String, Violation> {
+        let username = normalize_username(username);
+        let length = username.chars().count();
+        if length == 0 {
+            return Err(Violation::Required);
+        }
+        if length < self.settings.username_min_length {
+            return Err(Violation::TooShort {
+                min: self.settings.username_min_length,
+            });
+        }
+        if length > self.settings.username_max_length {
+            return Err(Violation::TooLong {
+                max: self.settings.username_max_length,
+            });
+        }
+        let allowed = |c: char| {
+            USERNAME_SYMBOLS.contains(&c)
+                || match self.settings.username_charset {
+                    UsernameCharset::Ascii => c.is_ascii_alphanumeric(),
+                    UsernameCharset::Unicode => c.is_alphanumeric(),
+                }
+        };
+        if !username.chars().all(allowed) {
+            return Err(Violation::InvalidCharacters);
+        }
+
+        Ok(username)
+    }
+
+    /// Whether the password is allowed for the owner with `username`.
+    pub fn check_password(&self, password: &str, username: &str) -> Result<(), Violation> {
+        let length = password.chars().count();
+        if length == 0 {
+            return Err(Violation::Required);
+        }
+        if length < self.settings.password_min_length {
+            return Err(Violation::TooShort {
+                min: self.settings.password_min_length,
+            });
+        }
+        if length > PASSWORD_MAX_LENGTH {
+            return Err(Violation::TooLong {
+                max: PASSWORD_MAX_LENGTH,
+            });
+        }
+        let lowercase = password.to_lowercase();
+        if self.breached.contains(&lowercase) {
+            return Err(Violation::Breached);
+        }
+        let key = username_key(username);
+        if key.chars().count() >= 3 && lowercase.contains(&key) {
+            return Err(Violation::ContainsUsername);
+        }
+        if password_strength(password) < self.settings.password_min_strength {
+            return Err(Violation::TooWeak);
+        }
+
+        Ok(())
+    }
+
+    /// The trimmed name, if it's allowed.
+    pub fn check_given_name(&self, given_name: &str) -> Result<String, Violation> {
+        let given_name = given_name.trim();
+        let length = given_name.chars().count();
+        if length == 0 {
+            return Err(Violation::Required);
+        }
+        if length > self.settings.given_name_max_length {
+            return Err(Violation::TooLong {
+                max: self.settings.given_name_max_length,
+            });
+        }
+        if given_name.chars().any(char::is_control) {
+            return Err(Violation::InvalidCharacters);
+        }
+
+        Ok(given_name.to_owned())
+    }
+}
+
+/// Why the value of a field isn't accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Required,
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    InvalidCharacters,
+    /// Not a valid value at all, e.g. an email address that mail can't be sent to.
+    Invalid,
+    /// Another account has it.
+    Taken,
+    TooWeak,
+    /// The password is known from a breach.
+    Breached,
+    ContainsUsername,
+}
+
+impl Violation {
+    /// A stable identifier for clients to tell violations apart.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Violation::Required => "required",
+            Violation::TooShort { .. } => "too-short",
+            Violation::TooLong { .. } => "too-long",
+            Violation::InvalidCharacters => "invalid-characters",
+            Violation::Invalid => "invalid",
+            Violation::Taken => "taken",
+            Violation::TooWeak => "too-weak",
+            Violation::Breached => "breached",
+            Violation::ContainsUsername => "contains-username",
+        }
+    }
+
+    pub fn message(&self, i18n: &Locale) -> String {
+        let id = format!("field-error-{}", self.code());
+        match self {
+            Violation::TooShort { min } => i18n.fmt(&id, &[("min", &min.to_string())]),
+            Violation::TooLong { max } => i18n.fmt(&id, &[("max", &max.to_string())]),
+            _ => i18n.t(&id),
+        }
+    }
+}
+
+/// A violation as reported to the client.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, violation: Violation, i18n: &Locale) -> Self {
+        Self {
+            field,
+            code: violation.code(),
+            message: violation.message(i18n),
+        }
+    }
+}
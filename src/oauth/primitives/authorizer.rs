@@ -1,17 +1,95 @@
-use super::Guard;
-use oxide_auth::primitives::{authorizer::Authorizer, grant::Grant};
+use super::{
+    lifetimes::{expiry, Lifetimes},
+    shards::ShardedMap,
+};
+use chrono::{DateTime, Utc};
+use oxide_auth::primitives::{
+    generator::{RandomGenerator, TagGrant},
+    grant::Grant,
+};
 use oxide_auth_async::primitives::Authorizer as AuthorizerAsync;
 
+/// Keeps the authorization codes until clients exchange them for tokens.
+///
+/// Codes are kept in a [`ShardedMap`], so authorizing doesn't wait on other requests.
+pub struct CodeMap {
+    generator: RandomGenerator,
+    codes: ShardedMap<String, Grant>,
+}
+
+impl CodeMap {
+    pub fn new(generator: RandomGenerator) -> Self {
+        Self {
+            generator,
+            codes: ShardedMap::new(),
+        }
+    }
+
+    /// Remove the codes that expired by `now`, which clients never exchanged. Returns how many.
+    pub fn purge_expired(&self, now: DateTime<Utc>) -> usize {
+        self.codes.retain(|_, grant| grant.until > now)
+    }
+
+    /// Revoke the codes of the owner that weren't exchanged yet.
+    pub fn revoke_owner(&self, owner_id: &str) {
+        self.codes.retain(|_, grant| grant.owner_id != owner_id);
+    }
+
+    /// Revoke the codes of the owner for the client that weren't exchanged yet.
+    pub fn revoke_client(&self, owner_id: &str, client_id: &str) {
+        self.codes
+            .retain(|_, grant| grant.owner_id != owner_id || grant.client_id != client_id);
+    }
+}
+
 #[async_trait::async_trait]
-impl<T> AuthorizerAsync for Guard<'_, T>
-where
-    T: Authorizer + Send,
-{
+impl AuthorizerAsync for &CodeMap {
     async fn authorize(&mut self, grant: Grant) -> Result<String, ()> {
-        Authorizer::authorize(&mut *self.inner, grant)
+        // Random codes don't depend on the usage counter
+        let code = (&self.generator).tag(0, &grant)?;
+        self.codes.write(&code).insert(code.clone(), grant);
+
+        Ok(code)
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        Ok(self.codes.write(code).remove(code))
     }
+}
+
+/// Authorizes grants with the code lifetime of their client.
+pub struct CodeAuthorizer<'a> {
+    codes: &'a CodeMap,
+    lifetimes: Lifetimes<'a>,
+    /// Whether the request presented a code that was already exchanged, purged or revoked.
+    unknown_code: bool,
+}
+
+impl<'a> CodeAuthorizer<'a> {
+    pub fn new(codes: &'a CodeMap, lifetimes: Lifetimes<'a>) -> Self {
+        Self {
+            codes,
+            lifetimes,
+            unknown_code: false,
+        }
+    }
+
+    pub fn unknown_code(&self) -> bool {
+        self.unknown_code
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizerAsync for CodeAuthorizer<'_> {
+    async fn authorize(&mut self, mut grant: Grant) -> Result<String, ()> {
+        grant.until = expiry(self.lifetimes.of(&grant.client_id).await.code);
+        self.codes.authorize(grant).await
+    }
+
+    async fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        let grant = self.codes.extract(code).await?;
+        self.unknown_code = grant.is_none();
 
-    async fn extract(&mut self, token: &str) -> Result<Option<Grant>, ()> {
-        Authorizer::extract(&mut *self.inner, token)
+        Ok(grant)
     }
 }
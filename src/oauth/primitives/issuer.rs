@@ -1,28 +1,444 @@
-use super::Guard;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    lifetimes::{expiry, Lifetimes},
+    shards::{ShardedLock, ShardedMap},
+};
+use crate::oauth::{
+    audit::{self, AuditEvent},
+    database::{resource::user::AuthUser, Database},
+    models::ClientId,
+    scopes::OFFLINE_ACCESS,
+};
+use chrono::{DateTime, Utc};
 use oxide_auth::primitives::{
+    generator::{RandomGenerator, TagGrant},
     grant::Grant,
-    issuer::{IssuedToken, Issuer, RefreshedToken},
+    issuer::{IssuedToken, RefreshedToken, TokenType},
 };
 use oxide_auth_async::primitives::Issuer as IssuerAsync;
+use tokio::sync::MutexGuard;
+
+/// Issues tokens from the [`OwnerTokenMap`] with the lifetimes of their client, and notes when a
+/// client uses an owner's authorization: when it gets tokens with it, and when it presents them
+/// for a resource.
+pub struct UsageIssuer<'a> {
+    tokens: &'a OwnerTokenMap,
+    db: &'a Database,
+    lifetimes: Lifetimes<'a>,
+}
+
+impl<'a> UsageIssuer<'a> {
+    pub fn new(tokens: &'a OwnerTokenMap, db: &'a Database, lifetimes: Lifetimes<'a>) -> Self {
+        Self {
+            tokens,
+            db,
+            lifetimes,
+        }
+    }
+
+    async fn record_use(&self, owner_id: &str, client_id: &str) {
+        let (Ok(owner), Ok(client_id), Ok(now)) = (
+            AuthUser::from_str(owner_id),
+            client_id.parse::<ClientId>(),
+            SystemTime::now().duration_since(UNIX_EPOCH),
+        ) else {
+            return;
+        };
+        self.db
+            .record_client_use(owner.user_id, client_id, now.as_secs())
+            .await;
+    }
+
+    /// Whether the grant gets a refresh token: when the owner granted `offline_access`, or when
+    /// the client gets them without asking.
+    async fn offline_access(&self, grant: &Grant) -> bool {
+        if grant.scope.iter().any(|scope| scope == OFFLINE_ACCESS) {
+            return true;
+        }
+        let Ok(client_id) = grant.client_id.parse::<ClientId>() else {
+            return false;
+        };
+        self.db
+            .get_client_metadata(client_id)
+            .await
+            .is_ok_and(|metadata| metadata.offline_access)
+    }
+}
 
 #[async_trait::async_trait]
-impl<T> IssuerAsync for Guard<'_, T>
-where
-    T: Issuer + Send,
-{
-    async fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
-        Issuer::issue(&mut **self, grant)
+impl IssuerAsync for UsageIssuer<'_> {
+    async fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+        let (owner_id, client_id) = (grant.owner_id.clone(), grant.client_id.clone());
+        let lifetimes = self.lifetimes.of(&client_id).await;
+        grant.until = expiry(lifetimes.access_token);
+        let refresh = self.offline_access(&grant).await.then(|| RefreshExpiry {
+            idle: expiry(lifetimes.refresh_token),
+            family: expiry(lifetimes.refresh_token_max),
+        });
+        let issued = self.tokens.issue(grant, refresh)?;
+        self.record_use(&owner_id, &client_id).await;
+
+        Ok(issued)
     }
 
-    async fn refresh(&mut self, token: &str, grant: Grant) -> Result<RefreshedToken, ()> {
-        Issuer::refresh(&mut **self, token, grant)
+    async fn refresh(&mut self, token: &str, mut grant: Grant) -> Result<RefreshedToken, ()> {
+        let (owner_id, client_id) = (grant.owner_id.clone(), grant.client_id.clone());
+        let lifetimes = self.lifetimes.of(&client_id).await;
+        grant.until = expiry(lifetimes.access_token);
+        let refreshed = self
+            .tokens
+            .refresh(token, grant, expiry(lifetimes.refresh_token))?;
+        self.record_use(&owner_id, &client_id).await;
+
+        Ok(refreshed)
     }
 
     async fn recover_token(&mut self, token: &str) -> Result<Option<Grant>, ()> {
-        Issuer::recover_token(&**self, token)
+        let grant = self.tokens.recover_token(token);
+        if let Some(grant) = &grant {
+            self.record_use(&grant.owner_id, &grant.client_id).await;
+        }
+
+        Ok(grant)
     }
 
     async fn recover_refresh(&mut self, token: &str) -> Result<Option<Grant>, ()> {
-        Issuer::recover_refresh(&**self, token)
+        Ok(self.tokens.recover_refresh(token))
+    }
+}
+
+/// Keeps the tokens issued to clients, and remembers which tokens it issued to which owner, so
+/// that all of them can be revoked when the owner changes their password or deletes their
+/// account, or those of a client when the owner disconnects it.
+///
+/// Refresh tokens are only issued with grants for offline access, and are rotated: refreshing
+/// replaces the refresh token with a new one of the same family, which expires later unless the
+/// family reaches its maximum lifetime first. A refresh token that was already rotated is proof
+/// that someone else has a copy, so
+/// presenting it revokes the whole family and is recorded as an [`AuditEvent`].
+///
+/// The tokens are kept in [`ShardedMap`]s, so validating a token only waits for requests that
+/// change a token in the same shard. Changes for an owner hold the lock of their shard of
+/// `owners` until they're done, so a revocation can't miss tokens issued at the same time.
+pub struct OwnerTokenMap {
+    generator: RandomGenerator,
+    access: ShardedMap<String, Arc<Token>>,
+    refresh: ShardedMap<String, Arc<Token>>,
+    /// The tokens of every grant by owner ID.
+    owners: ShardedMap<String, Vec<IssuedTokens>>,
+    /// Refresh tokens that were rotated, until their family is revoked or expires.
+    rotated: ShardedMap<String, RotatedToken>,
+    /// Taken by refreshes for their refresh token, see [`OwnerTokenMap::lock_refresh`].
+    refreshing: ShardedLock,
+}
+
+/// A grant, and the tokens it was issued with.
+struct Token {
+    access: String,
+    refresh: Option<RefreshToken>,
+    /// The refresh tokens that descend from the same issued one.
+    family: String,
+    grant: Grant,
+}
+
+struct RefreshToken {
+    token: String,
+    expiry: RefreshExpiry,
+}
+
+/// When a refresh token expires: once it's gone unused for its idle lifetime, or when its family
+/// reaches its maximum lifetime, whichever comes first.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RefreshExpiry {
+    pub(crate) idle: DateTime<Utc>,
+    pub(crate) family: DateTime<Utc>,
+}
+
+impl Token {
+    fn refresh_token(&self) -> Option<String> {
+        self.refresh.as_ref().map(|refresh| refresh.token.clone())
+    }
+
+    fn refresh_expired(&self, now: DateTime<Utc>) -> bool {
+        self.refresh
+            .as_ref()
+            .is_some_and(|refresh| refresh.expiry.at() <= now)
+    }
+}
+
+impl RefreshExpiry {
+    fn at(&self) -> DateTime<Utc> {
+        self.idle.min(self.family)
+    }
+}
+
+/// The access token, and its refresh token if it has one, of a grant to a client.
+struct IssuedTokens {
+    client_id: String,
+    access: String,
+    refresh: Option<String>,
+    family: String,
+}
+
+/// A refresh token that was replaced by a new one.
+#[derive(Clone)]
+struct RotatedToken {
+    owner_id: String,
+    client_id: String,
+    family: String,
+}
+
+impl OwnerTokenMap {
+    pub fn new(generator: RandomGenerator) -> Self {
+        Self {
+            generator,
+            access: ShardedMap::new(),
+            refresh: ShardedMap::new(),
+            owners: ShardedMap::new(),
+            rotated: ShardedMap::new(),
+            refreshing: ShardedLock::new(),
+        }
+    }
+
+    /// Revoke the access and refresh tokens of every grant of the owner, except for the grant
+    /// whose access token is `except`.
+    pub fn revoke_owner(&self, owner_id: &str, except: Option<&str>) {
+        self.revoke_where(owner_id, |issued| except != Some(issued.access.as_str()));
+    }
+
+    /// Revoke the access and refresh tokens of every grant of the owner to the client.
+    pub fn revoke_client(&self, owner_id: &str, client_id: &str) {
+        self.revoke_where(owner_id, |issued| issued.client_id == client_id);
+    }
+
+    /// Remove the access tokens that expired by `now`, and the refresh tokens that did. Returns
+    /// how many access and refresh tokens were removed. Rotated refresh tokens whose family is
+    /// gone are forgotten too, but aren't counted, as they were already replaced.
+    pub fn purge_expired(&self, now: DateTime<Utc>) -> (usize, usize) {
+        let (mut access, mut refresh) = (0, 0);
+        self.owners.retain(|_, issued| {
+            issued.retain(|tokens| {
+                let mut access_shard = self.access.write(&tokens.access);
+                let live_access = match access_shard.get(&tokens.access) {
+                    Some(token) if token.grant.until <= now => {
+                        access_shard.remove(&tokens.access);
+                        access += 1;
+                        false
+                    }
+                    Some(_) => true,
+                    None => false,
+                };
+                drop(access_shard);
+
+                let Some(refresh_token) = &tokens.refresh else {
+                    return live_access;
+                };
+                let mut refresh_shard = self.refresh.write(refresh_token);
+                let live_refresh = match refresh_shard.get(refresh_token) {
+                    Some(token) if token.refresh_expired(now) => {
+                        refresh_shard.remove(refresh_token);
+                        refresh += 1;
+                        false
+                    }
+                    Some(_) => true,
+                    None => false,
+                };
+
+                live_access || live_refresh
+            });
+            !issued.is_empty()
+        });
+        self.rotated
+            .retain(|_, rotated| self.is_live(&rotated.owner_id, &rotated.family));
+
+        (access, refresh)
+    }
+
+    /// Whether the owner still has tokens of the family.
+    fn is_live(&self, owner_id: &str, family: &str) -> bool {
+        self.owners
+            .read(owner_id)
+            .get(owner_id)
+            .is_some_and(|issued| issued.iter().any(|tokens| tokens.family == family))
+    }
+
+    fn revoke_where(&self, owner_id: &str, revoke: impl Fn(&IssuedTokens) -> bool) {
+        let mut owners = self.owners.write(owner_id);
+        let Some(issued) = owners.remove(owner_id) else {
+            return;
+        };
+
+        let mut kept = Vec::new();
+        for tokens in issued {
+            if !revoke(&tokens) {
+                kept.push(tokens);
+                continue;
+            }
+            self.access.write(&tokens.access).remove(&tokens.access);
+            if let Some(refresh) = &tokens.refresh {
+                self.refresh.write(refresh).remove(refresh);
+            }
+        }
+        if !kept.is_empty() {
+            owners.insert(owner_id.to_owned(), kept);
+        }
+    }
+
+    /// Generate the tokens of the grant and make them valid.
+    fn insert(
+        &self,
+        grant: Grant,
+        refresh: Option<RefreshExpiry>,
+        family: String,
+    ) -> Result<Arc<Token>, ()> {
+        // Random tokens don't depend on the usage counter
+        let access = (&self.generator).tag(0, &grant)?;
+        let refresh = match refresh {
+            Some(expiry) => Some(RefreshToken {
+                token: (&self.generator).tag(1, &grant)?,
+                expiry,
+            }),
+            None => None,
+        };
+        let token = Arc::new(Token {
+            access,
+            refresh,
+            family,
+            grant,
+        });
+        self.access
+            .write(&token.access)
+            .insert(token.access.clone(), token.clone());
+        if let Some(refresh) = &token.refresh {
+            self.refresh
+                .write(&refresh.token)
+                .insert(refresh.token.clone(), token.clone());
+        }
+
+        Ok(token)
+    }
+}
+
+impl OwnerTokenMap {
+    /// Issue an access token for the grant, and a refresh token if it's given an expiry.
+    pub(crate) fn issue(
+        &self,
+        grant: Grant,
+        refresh: Option<RefreshExpiry>,
+    ) -> Result<IssuedToken, ()> {
+        let mut owners = self.owners.write(&grant.owner_id);
+        let owner_id = grant.owner_id.clone();
+        let client_id = grant.client_id.clone();
+        let until = grant.until;
+        let token = self.insert(grant, refresh, nanoid::nanoid!())?;
+        owners.entry(owner_id).or_default().push(IssuedTokens {
+            client_id,
+            access: token.access.clone(),
+            refresh: token.refresh_token(),
+            family: token.family.clone(),
+        });
+
+        Ok(IssuedToken {
+            token: token.access.clone(),
+            refresh: token.refresh_token(),
+            until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    /// Replace both tokens of the grant of the refresh token, which is remembered as rotated. The
+    /// new refresh token is valid until `idle_until`, or until its family expires.
+    pub(crate) fn refresh(
+        &self,
+        refresh: &str,
+        grant: Grant,
+        idle_until: DateTime<Utc>,
+    ) -> Result<RefreshedToken, ()> {
+        let mut owners = self.owners.write(&grant.owner_id);
+        // Should only be called with valid refresh tokens
+        let previous = self.refresh.write(refresh).remove(refresh).ok_or(())?;
+        self.access.write(&previous.access).remove(&previous.access);
+        let expiry = RefreshExpiry {
+            idle: idle_until,
+            family: previous.refresh.as_ref().ok_or(())?.expiry.family,
+        };
+
+        let owner_id = grant.owner_id.clone();
+        let client_id = grant.client_id.clone();
+        let until = grant.until;
+        let token = self.insert(grant, Some(expiry), previous.family.clone())?;
+        let issued = owners.entry(owner_id.clone()).or_default();
+        issued.retain(|issued| issued.refresh.as_deref() != Some(refresh));
+        issued.push(IssuedTokens {
+            client_id: client_id.clone(),
+            access: token.access.clone(),
+            refresh: token.refresh_token(),
+            family: token.family.clone(),
+        });
+        // The rotated tokens are only locked after the owners, never before
+        drop(owners);
+        self.rotated.write(refresh).insert(
+            refresh.to_owned(),
+            RotatedToken {
+                owner_id,
+                client_id,
+                family: previous.family.clone(),
+            },
+        );
+
+        Ok(RefreshedToken {
+            token: token.access.clone(),
+            refresh: token.refresh_token(),
+            until,
+            token_type: TokenType::Bearer,
+        })
+    }
+
+    /// Wait for other refreshes with the refresh token to finish. The grant of a refresh token is
+    /// recovered before it's refreshed, so without taking turns, concurrent refreshes could all
+    /// recover it, and all but one fail when they find it replaced. Taking turns, they find it
+    /// rotated instead, like any other reuse.
+    pub async fn lock_refresh(&self, refresh: &str) -> MutexGuard<'_, ()> {
+        self.refreshing.lock(refresh).await
+    }
+
+    /// The grant of the access token, unless it expired or was revoked.
+    pub fn recover_token(&self, token: &str) -> Option<Grant> {
+        self.access
+            .read(token)
+            .get(token)
+            .map(|token| token.grant.clone())
+    }
+
+    /// The grant of the refresh token, unless it expired, was used or was revoked. The grant is
+    /// valid for as long as the refresh token is. Presenting a rotated token revokes its family.
+    pub fn recover_refresh(&self, token: &str) -> Option<Grant> {
+        let current = self.refresh.read(token).get(token).cloned();
+        if let Some(current) = current {
+            return current
+                .refresh
+                .as_ref()
+                .map(|refresh| Grant {
+                    until: refresh.expiry.at(),
+                    ..current.grant.clone()
+                })
+                .filter(|grant| grant.until > Utc::now());
+        }
+
+        let rotated = self.rotated.read(token).get(token).cloned();
+        if let Some(rotated) = rotated {
+            self.revoke_where(&rotated.owner_id, |issued| issued.family == rotated.family);
+            audit::record(AuditEvent::RefreshTokenReused {
+                owner_id: &rotated.owner_id,
+                client_id: &rotated.client_id,
+            });
+        }
+
+        None
     }
 }
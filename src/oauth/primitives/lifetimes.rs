@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::oauth::{database::Database, models::ClientId, settings::TokenLifetimes};
+
+/// Looks up the lifetimes of a client's codes and tokens: its own where it has them, the defaults
+/// otherwise.
+#[derive(Clone, Copy)]
+pub struct Lifetimes<'a> {
+    defaults: &'a TokenLifetimes,
+    db: &'a Database,
+}
+
+impl<'a> Lifetimes<'a> {
+    pub fn new(defaults: &'a TokenLifetimes, db: &'a Database) -> Self {
+        Self { defaults, db }
+    }
+
+    pub async fn of(&self, client_id: &str) -> TokenLifetimes {
+        let own = match client_id.parse::<ClientId>() {
+            Ok(client_id) => self
+                .db
+                .get_client_lifetimes(client_id)
+                .await
+                .unwrap_or_default(),
+            Err(_) => Default::default(),
+        };
+
+        self.defaults.with_client(&own)
+    }
+}
+
+/// When something valid for `lifetime` from now expires.
+pub fn expiry(lifetime: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(lifetime)
+        .ok()
+        .and_then(|lifetime| Utc::now().checked_add_signed(lifetime))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
@@ -1,30 +1,11 @@
 mod authorizer;
 mod issuer;
+mod lifetimes;
 mod registrar;
 pub mod scopes;
+mod shards;
 
-use tokio::sync::MutexGuard;
-
-pub struct Guard<'a, T> {
-    inner: MutexGuard<'a, T>,
-}
-
-impl<'a, T> From<MutexGuard<'a, T>> for Guard<'a, T> {
-    fn from(guard: MutexGuard<'a, T>) -> Self {
-        Self { inner: guard }
-    }
-}
-
-impl<T> std::ops::Deref for Guard<'_, T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        &self.inner
-    }
-}
-
-impl<T> std::ops::DerefMut for Guard<'_, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.inner
-    }
-}
+pub use authorizer::{CodeAuthorizer, CodeMap};
+pub use issuer::{OwnerTokenMap, UsageIssuer};
+pub use lifetimes::Lifetimes;
+pub use registrar::{CheckedClient, CheckedRegistrar};
@@ -33,3 +33,67 @@ impl Registrar for Database {
         client_map_lock.check(client_id, passphrase).await
     }
 }
+
+/// Client credentials that were checked for a request already, by its rate limit, and are passed
+/// on in the request's extensions. Checking them derives a key from the secret, which is slow on
+/// purpose, so it's done once.
+#[derive(Clone)]
+pub struct CheckedClient {
+    client_id: String,
+    passphrase: Vec<u8>,
+}
+
+impl CheckedClient {
+    pub fn new(client_id: &str, passphrase: &[u8]) -> Self {
+        Self {
+            client_id: client_id.to_owned(),
+            passphrase: passphrase.to_vec(),
+        }
+    }
+}
+
+/// The registrar, for a request whose client credentials may have been checked already.
+pub struct CheckedRegistrar<'a> {
+    registrar: &'a Database,
+    checked: Option<CheckedClient>,
+}
+
+impl<'a> CheckedRegistrar<'a> {
+    pub fn new(registrar: &'a Database, checked: Option<CheckedClient>) -> Self {
+        Self { registrar, checked }
+    }
+}
+
+#[async_trait::async_trait]
+impl Registrar for CheckedRegistrar<'_> {
+    async fn bound_redirect<'a>(
+        &self,
+        bound: ClientUrl<'a>,
+    ) -> Result<BoundClient<'a>, RegistrarError> {
+        self.registrar.bound_redirect(bound).await
+    }
+
+    async fn negotiate<'a>(
+        &self,
+        bound: BoundClient<'a>,
+        scope: Option<Scope>,
+    ) -> Result<PreGrant, RegistrarError> {
+        self.registrar.negotiate(bound, scope).await
+    }
+
+    async fn check(
+        &self,
+        client_id: &str,
+        passphrase: Option<&[u8]>,
+    ) -> Result<(), RegistrarError> {
+        // Both come from the same request, so the comparison leaks nothing it doesn't know
+        let checked = self.checked.as_ref().is_some_and(|checked| {
+            checked.client_id == client_id && Some(checked.passphrase.as_slice()) == passphrase
+        });
+        if checked {
+            return Ok(());
+        }
+
+        self.registrar.check(client_id, passphrase).await
+    }
+}
@@ -1,10 +1,20 @@
-use crate::oauth::scopes;
+use crate::oauth::{
+    database::resource::user::Authentication, endpoint::extension::AuthenticationAddon, scopes,
+};
 
 pub struct Grant<S = ()> {
     pub grant: oxide_auth::primitives::grant::Grant,
     _type: std::marker::PhantomData<S>,
 }
 
+impl<S> Grant<S> {
+    /// How the owner signed in when they authorized the grant. `None` for grants authorized
+    /// before that was recorded.
+    pub fn authentication(&self) -> Option<Authentication> {
+        AuthenticationAddon::authentication(&self.grant)
+    }
+}
+
 use axum::{
     extract::{FromRef, FromRequestParts},
     http::request::Parts,
@@ -30,7 +40,6 @@ where
 
         let auth = state
             .endpoint()
-            .await
             .with_scopes(&[Scope::SCOPE.parse().unwrap()])
             .resource_flow()
             .execute(req.into())
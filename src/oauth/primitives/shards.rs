@@ -0,0 +1,109 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+/// How many shards a [`ShardedMap`] splits its entries over.
+const SHARDS: usize = 64;
+
+/// A map split over shards that are locked on their own, so that requests for different entries
+/// rarely wait for each other, and reads never wait for other reads.
+///
+/// The locks are held only while an entry is read or changed, never across an `.await`.
+pub struct ShardedMap<K, V> {
+    hasher: RandomState,
+    shards: Box<[RwLock<HashMap<K, V>>]>,
+}
+
+impl<K: Hash + Eq, V> ShardedMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    /// The shard that holds the key, locked for reading.
+    pub fn read<Q>(&self, key: &Q) -> RwLockReadGuard<'_, HashMap<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        // The maps are changed by single inserts and removes, so they are whole after a panic
+        self.shard(key)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The shard that holds the key, locked for writing.
+    pub fn write<Q>(&self, key: &Q) -> RwLockWriteGuard<'_, HashMap<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.shard(key)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Keep only the entries for which `keep` returns true, locking one shard at a time. Returns
+    /// how many were removed.
+    pub fn retain(&self, mut keep: impl FnMut(&K, &mut V) -> bool) -> usize {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let mut shard = shard.write().unwrap_or_else(PoisonError::into_inner);
+            let before = shard.len();
+            shard.retain(&mut keep);
+            removed += before - shard.len();
+        }
+
+        removed
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &RwLock<HashMap<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+impl<K: Hash + Eq, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Locks for keys, split over shards like a [`ShardedMap`], so keys in the same shard share a
+/// lock. Unlike the shards of a map, a lock may be held across an `.await`.
+pub struct ShardedLock {
+    hasher: RandomState,
+    shards: Box<[Mutex<()>]>,
+}
+
+impl ShardedLock {
+    pub fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Wait for the lock of the key's shard.
+    pub async fn lock<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, ()> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        self.shards[index].lock().await
+    }
+}
+
+impl Default for ShardedLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
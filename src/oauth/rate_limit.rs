@@ -0,0 +1,291 @@
+//! Token-bucket rate limits for the endpoints that clients call directly.
+//!
+//! Requests with valid client credentials (HTTP Basic, as sent to the token endpoint) are counted
+//! against the client, anything else against the client address. Valid credentials are passed on
+//! as a [`CheckedClient`] extension, so the endpoint doesn't check them again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    headers::{authorization::Basic, Authorization, HeaderMapExt},
+    http::{header, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::future::BoxFuture;
+use oxide_auth_async::primitives::Registrar;
+use tower::{Layer, Service};
+
+use crate::oauth::{
+    database::Database,
+    primitives::CheckedClient,
+    settings::{Limit, RouteLimits},
+    throttle::client_address,
+};
+
+/// Buckets kept per route before the least recently used ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Applies the limits of one route.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(db: Database, limits: RouteLimits, trust_forwarded_for: bool) -> Self {
+        Self {
+            limiter: Arc::new(Limiter {
+                db,
+                limits,
+                trust_forwarded_for,
+                buckets: Mutex::new(Buckets::default()),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S, B> Service<Request<B>> for RateLimit<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // The clone might not be ready, so the ready service is taken for this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let address = client_address(
+            request.headers(),
+            request.extensions(),
+            limiter.trust_forwarded_for,
+        );
+        let credentials = request
+            .headers()
+            .typed_get::<Authorization<Basic>>()
+            .map(|Authorization(credentials)| credentials);
+
+        Box::pin(async move {
+            match limiter.acquire(address, credentials).await {
+                Ok(checked) => {
+                    if let Some(checked) = checked {
+                        request.extensions_mut().insert(checked);
+                    }
+                    inner.call(request).await
+                }
+                Err(retry_after) => Ok(too_many_requests(retry_after)),
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Client(String),
+    Address(IpAddr),
+}
+
+struct Limiter {
+    db: Database,
+    limits: RouteLimits,
+    trust_forwarded_for: bool,
+    buckets: Mutex<Buckets>,
+}
+
+impl Limiter {
+    /// Take a token for the request, or return how long until one is available. Returns the
+    /// credentials if they were checked and are valid.
+    async fn acquire(
+        &self,
+        address: Option<IpAddr>,
+        credentials: Option<Basic>,
+    ) -> Result<Option<CheckedClient>, Duration> {
+        let address = address.map(Key::Address);
+        if let Some(credentials) = credentials {
+            // Checking the credentials hashes the secret, so it's skipped for addresses that
+            // are out of requests already
+            if let Some(address) = &address {
+                self.available(address)?;
+            }
+            let authenticated = self
+                .db
+                .check(
+                    credentials.username(),
+                    Some(credentials.password().as_bytes()),
+                )
+                .await
+                .is_ok();
+            if authenticated {
+                self.take(Key::Client(credentials.username().to_owned()))?;
+                return Ok(Some(CheckedClient::new(
+                    credentials.username(),
+                    credentials.password().as_bytes(),
+                )));
+            }
+        }
+
+        match address {
+            Some(address) => self.take(address).map(|()| None),
+            None => Ok(None),
+        }
+    }
+
+    fn limit(&self, key: &Key) -> Option<Limit> {
+        match key {
+            Key::Client(client_id) => self.limits.clients.get(client_id).copied(),
+            Key::Address(_) => None,
+        }
+        .or(self.limits.default)
+    }
+
+    fn available(&self, key: &Key) -> Result<(), Duration> {
+        let Some(limit) = self.limit(key) else {
+            return Ok(());
+        };
+        let buckets = self.buckets.lock().unwrap();
+        match buckets.buckets.get(key) {
+            Some(bucket) => bucket.available(&limit, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    fn take(&self, key: Key) -> Result<(), Duration> {
+        let Some(limit) = self.limit(&key) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.get_or_insert(key, &limit, now).take(&limit, now)
+    }
+}
+
+/// The buckets of a route, and the order they were last used in, so that the least recently
+/// used one is dropped for a new one once there are [`MAX_BUCKETS`]. It has been refilling the
+/// longest, so it's the most likely to be full, which is as good as new.
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<Key, Bucket>,
+    /// The keys of the buckets by when they were last used.
+    used: BTreeMap<u64, Key>,
+    /// Counts up with every use.
+    uses: u64,
+}
+
+impl Buckets {
+    /// The key's bucket, marked as the most recently used one.
+    fn get_or_insert(&mut self, key: Key, limit: &Limit, now: Instant) -> &mut Bucket {
+        self.uses += 1;
+        if let Some(bucket) = self.buckets.get(&key) {
+            self.used.remove(&bucket.used);
+        } else if self.buckets.len() >= MAX_BUCKETS {
+            if let Some((_, evicted)) = self.used.pop_first() {
+                self.buckets.remove(&evicted);
+            }
+        }
+        self.used.insert(self.uses, key.clone());
+
+        let bucket = self
+            .buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::full(limit, now));
+        bucket.used = self.uses;
+        bucket
+    }
+}
+
+/// A token bucket, refilled lazily whenever it's used.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When it was last used, in [`Buckets::uses`].
+    used: u64,
+}
+
+impl Bucket {
+    fn full(limit: &Limit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated: now,
+            used: 0,
+        }
+    }
+
+    fn available_tokens(&self, limit: &Limit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * rate(limit)).min(limit.burst as f64)
+    }
+
+    fn available(&self, limit: &Limit, now: Instant) -> Result<(), Duration> {
+        let tokens = self.available_tokens(limit, now);
+        if tokens >= 1.0 {
+            Ok(())
+        } else {
+            // A bucket without any burst never refills
+            Err(Duration::try_from_secs_f64((1.0 - tokens) / rate(limit)).unwrap_or(limit.period))
+        }
+    }
+
+    fn take(&mut self, limit: &Limit, now: Instant) -> Result<(), Duration> {
+        self.tokens = self.available_tokens(limit, now);
+        self.updated = now;
+        self.available(limit, now)?;
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Tokens per second.
+fn rate(limit: &Limit) -> f64 {
+    limit.burst as f64 / limit.period.as_secs_f64().max(f64::EPSILON)
+}
+
+/// A `429` with an error body in the style of RFC 6749, section 5.2.
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let body = serde_json::json!({
+        "error": "temporarily_unavailable",
+        "error_description": format!("Too many requests, retry after {seconds} seconds"),
+    });
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [
+            (header::RETRY_AFTER, HeaderValue::from(seconds)),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+        ],
+        Json(body),
+    )
+        .into_response()
+}
@@ -0,0 +1,345 @@
+//! Pages where signed in owners manage their account.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Form, FromRef, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use axum_sessions::extractors::WritableSession;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+
+use super::{
+    csrf,
+    session::{self, Session},
+    CodeForm, CsrfForm,
+};
+use crate::oauth::{
+    database::{Database, StoreError},
+    error::{Error, HtmlError},
+    mfa::{RecoveryCodes, TotpFactor},
+    models::ClientId,
+    settings::Settings,
+    templates::{
+        ConnectedApp, ConnectedApps, Pages, PasskeyInfo, PasskeyList, TemplateProvider,
+        TotpEnrollment,
+    },
+    webauthn::{self, Registration, RegistrationCredential, RelyingParty},
+};
+
+/// The secret of the authenticator being enrolled, until the owner confirms it with a code.
+const ENROLLMENT_KEY: &str = "totp_enrollment";
+/// The registration ceremony of a passkey, until the browser responds.
+const REGISTRATION_KEY: &str = "passkey_registration";
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Send + Sync + 'static + Clone,
+    crate::oauth::state::State: FromRef<S>,
+    Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+{
+    Router::new()
+        .route("/apps", get(get_apps))
+        .route("/apps/:id/revoke", post(post_revoke_app))
+        .route("/totp", get(get_totp).post(post_totp))
+        .route("/passkeys", get(get_passkeys).post(post_passkeys))
+        .route("/passkeys/options", post(post_passkey_options))
+        .route("/passkeys/:id", delete(delete_passkey))
+}
+
+async fn get_totp(
+    State(db): State<Database>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+) -> Result<Response, HtmlError> {
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    let csrf_token = csrf::issue(&mut session);
+    if record.totp().is_some() {
+        return Ok(pages.render(&TotpEnrollment {
+            i18n: pages.locale(),
+            csrf_token: &csrf_token,
+            enrolled: true,
+            qr_code: None,
+            url: None,
+            secret: None,
+            recovery_codes: Vec::new(),
+            remaining_recovery_codes: record.recovery_codes().remaining(),
+            error: None,
+        }));
+    }
+
+    // Reloading the page shows the same secret, in case it was scanned already
+    let totp = match session
+        .get::<String>(ENROLLMENT_KEY)
+        .and_then(|secret| TotpFactor::from_secret(&secret))
+    {
+        Some(totp) => totp,
+        None => {
+            let totp = TotpFactor::generate();
+            session
+                .insert(ENROLLMENT_KEY, totp.secret().expose_secret())
+                .map_err(|_| HtmlError::new(Error::InternalError, pages.clone()))?;
+            totp
+        }
+    };
+
+    Ok(enrollment_page(
+        &pages,
+        &totp,
+        &user.username,
+        &csrf_token,
+        None,
+    ))
+}
+
+async fn post_totp(
+    State(db): State<Database>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, HtmlError> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    if record.totp().is_some() {
+        return Err(HtmlError::new(Error::ResourceConflict, pages));
+    }
+    let Some(mut totp) = session
+        .get::<String>(ENROLLMENT_KEY)
+        .and_then(|secret| TotpFactor::from_secret(&secret))
+    else {
+        return Err(HtmlError::new(Error::NotFound, pages));
+    };
+
+    // The owner proves that the authenticator was set up before it's needed to sign in
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| HtmlError::new(Error::InternalError, pages.clone()))?
+        .as_secs();
+    let Some(step) = totp.verify(&form.code, now) else {
+        let csrf_token = csrf::issue(&mut session);
+        let error = pages.locale().t("totp-failed");
+        let page = enrollment_page(&pages, &totp, &user.username, &csrf_token, Some(error));
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+    };
+    totp.set_last_step(step);
+
+    let (codes, recovery_codes) = RecoveryCodes::generate();
+    db.enroll_totp(&user, totp, recovery_codes)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    session.remove(ENROLLMENT_KEY);
+    tracing::info!("user {} enrolled an authenticator", user.username);
+
+    Ok(pages.render(&TotpEnrollment {
+        i18n: pages.locale(),
+        csrf_token: "",
+        enrolled: true,
+        qr_code: None,
+        url: None,
+        secret: None,
+        remaining_recovery_codes: codes.len(),
+        recovery_codes: codes,
+        error: None,
+    }))
+}
+
+fn enrollment_page(
+    pages: &Pages,
+    totp: &TotpFactor,
+    username: &str,
+    csrf_token: &str,
+    error: Option<String>,
+) -> Response {
+    pages.render(&TotpEnrollment {
+        i18n: pages.locale(),
+        csrf_token,
+        enrolled: false,
+        qr_code: totp.qr_code(username),
+        url: Some(totp.url(username)),
+        secret: Some(totp.secret().expose_secret().clone()),
+        recovery_codes: Vec::new(),
+        remaining_recovery_codes: 0,
+        error,
+    })
+}
+
+async fn get_passkeys(
+    State(db): State<Database>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+) -> Result<Response, HtmlError> {
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+
+    Ok(pages.render(&PasskeyList {
+        i18n: pages.locale(),
+        passkeys: record.passkeys().iter().map(PasskeyInfo::from).collect(),
+        csrf_token: &csrf::issue(&mut session),
+    }))
+}
+
+/// Start registering a passkey. Responds with the options for `navigator.credentials.create()`.
+async fn post_passkey_options(
+    State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    Session { user }: Session,
+    mut session: WritableSession,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let csrf_token = csrf::rotate(&mut session, &headers)?;
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| Error::Database { source: e })?;
+    let (options, ceremony) = RelyingParty::new(&settings.webauthn)
+        .start_registration(&user, record.passkeys(), session::now()?)
+        .map_err(|e| Error::Webauthn { source: e })?;
+    session
+        .insert(REGISTRATION_KEY, ceremony)
+        .map_err(|_| Error::InternalError)?;
+
+    Ok(([(csrf::HEADER, csrf_token)], Json(options)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyRegistration {
+    /// What the owner calls the passkey, to tell it apart from their others.
+    #[serde(default)]
+    pub name: String,
+    pub credential: RegistrationCredential,
+}
+
+/// Finish registering a passkey with the browser's response.
+async fn post_passkeys(
+    State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    Session { user }: Session,
+    mut session: WritableSession,
+    headers: HeaderMap,
+    Json(registration): Json<PasskeyRegistration>,
+) -> Result<impl IntoResponse, Error> {
+    let csrf_token = csrf::rotate(&mut session, &headers)?;
+    // A ceremony is only good for one response
+    let ceremony = session.get::<Registration>(REGISTRATION_KEY);
+    session.remove(REGISTRATION_KEY);
+    let now = session::now()?;
+    let ceremony = ceremony
+        .filter(|ceremony| !ceremony.is_expired(now))
+        .ok_or(Error::NotFound)?;
+
+    let name = match registration.name.trim() {
+        "" => "Passkey",
+        name => name,
+    };
+    let passkey = RelyingParty::new(&settings.webauthn)
+        .register(&ceremony, &registration.credential, name, now)
+        .map_err(|e| {
+            tracing::debug!("passkey registration of {} failed: {}", user.username, e);
+            Error::Webauthn { source: e }
+        })?;
+    let info = PasskeyInfo::from(&passkey);
+    db.add_passkey(&user, passkey).await.map_err(|e| match e {
+        StoreError::DuplicateRecord => Error::ResourceConflict,
+        e => Error::Database { source: e },
+    })?;
+    tracing::info!("user {} registered a passkey", user.username);
+
+    Ok((
+        StatusCode::CREATED,
+        [(csrf::HEADER, csrf_token)],
+        Json(info),
+    ))
+}
+
+async fn delete_passkey(
+    State(db): State<Database>,
+    Session { user }: Session,
+    mut session: WritableSession,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let csrf_token = csrf::rotate(&mut session, &headers)?;
+    let credential_id = webauthn::decode(&id).map_err(|_| Error::NotFound)?;
+    db.remove_passkey(&user, &credential_id)
+        .await
+        .map_err(|e| match e {
+            StoreError::DoesNotExist => Error::NotFound,
+            e => Error::Database { source: e },
+        })?;
+    tracing::info!("user {} removed a passkey", user.username);
+
+    Ok((StatusCode::NO_CONTENT, [(csrf::HEADER, csrf_token)]))
+}
+
+async fn get_apps(
+    State(db): State<Database>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+) -> Result<Response, HtmlError> {
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    let mut apps = Vec::new();
+    for authorization in record.get_authorized_clients() {
+        let name = db
+            .get_client_name(authorization.client_id)
+            .await
+            .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+        apps.push(ConnectedApp::new(pages.locale(), name.inner, authorization));
+    }
+
+    Ok(pages.render(&ConnectedApps {
+        i18n: pages.locale(),
+        csrf_token: &csrf::issue(&mut session),
+        apps,
+    }))
+}
+
+/// Disconnect an app: remove its authorization and revoke the tokens it has for the owner.
+async fn post_revoke_app(
+    State(state): State<crate::oauth::state::State>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+    Path(id): Path<String>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, HtmlError> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let client_id = id
+        .parse::<ClientId>()
+        .map_err(|_| HtmlError::new(Error::NotFound, pages.clone()))?;
+    state
+        .revoke_authorization(&user, client_id)
+        .await
+        .map_err(|e| match e {
+            StoreError::DoesNotExist => HtmlError::new(Error::NotFound, pages.clone()),
+            e => HtmlError::new(Error::Database { source: e }, pages.clone()),
+        })?;
+    tracing::info!("user {} disconnected client {}", user.username, client_id);
+
+    Ok(Redirect::to("/oauth/account/apps").into_response())
+}
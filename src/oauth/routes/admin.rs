@@ -0,0 +1,216 @@
+//! The admin API. It's only available when an admin token is configured, and every request must
+//! present it as a bearer token.
+
+use std::{sync::Arc, time::UNIX_EPOCH};
+
+use axum::{
+    extract::{FromRef, FromRequestParts, Path, State},
+    headers::{authorization::Bearer, Authorization},
+    http::{request::Parts, StatusCode},
+    routing::get,
+    Json, Router, TypedHeader,
+};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::oauth::{
+    constant_time_eq,
+    database::{
+        clientmap::{ClientLifetimes, ClientMetadata},
+        Database, StoreError,
+    },
+    error::Error,
+    maintenance::{MaintenanceMetrics, MaintenanceReport},
+    models::ClientId,
+    settings::Settings,
+    throttle::SignInThrottle,
+};
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Send + Sync + 'static + Clone,
+    SignInThrottle: FromRef<S>,
+    Database: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+    Arc<MaintenanceMetrics>: FromRef<S>,
+{
+    Router::new()
+        .route(
+            "/lockouts/:username",
+            get(get_lockout).delete(delete_lockout),
+        )
+        .route("/clients/:client_id", get(get_client).patch(patch_client))
+        .route(
+            "/clients/:client_id/lifetimes",
+            get(get_lifetimes).put(put_lifetimes),
+        )
+        .route("/maintenance", get(get_maintenance))
+}
+
+/// A request authenticated with the admin token.
+pub struct Admin;
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    S: Send + Sync,
+    Arc<Settings>: FromRef<S>,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = Arc::<Settings>::from_ref(state);
+        let expected = settings.admin_token.as_ref().ok_or(Error::NotFound)?;
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| Error::Unauthorized)?;
+
+        if constant_time_eq(
+            bearer.token().as_bytes(),
+            expected.expose_secret().as_bytes(),
+        ) {
+            Ok(Admin)
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LockoutInfo {
+    pub username: String,
+    /// Failed sign-ins within the throttling window.
+    pub failures: usize,
+    /// Seconds since the Unix epoch.
+    pub locked_until: Option<u64>,
+}
+
+async fn get_lockout(
+    _: Admin,
+    State(throttle): State<SignInThrottle>,
+    Path(username): Path<String>,
+) -> Json<LockoutInfo> {
+    let lockout = throttle.status(&username).await;
+    let locked_until = lockout.locked_until.map(|until| {
+        until
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    });
+
+    Json(LockoutInfo {
+        username,
+        failures: lockout.failures,
+        locked_until,
+    })
+}
+
+async fn delete_lockout(
+    _: Admin,
+    State(throttle): State<SignInThrottle>,
+    Path(username): Path<String>,
+) -> StatusCode {
+    if throttle.unlock(&username).await {
+        tracing::info!("unlocked sign-in for {}", username);
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+/// How far a client is trusted. Only admins can change it, since first-party clients are
+/// authorized without asking the owner, and clients with offline access keep it without asking.
+#[derive(Debug, Deserialize)]
+pub struct ClientTrust {
+    pub first_party: Option<bool>,
+    pub verified: Option<bool>,
+    pub offline_access: Option<bool>,
+}
+
+fn client_id(id: &str) -> Result<ClientId, Error> {
+    id.parse::<ClientId>().map_err(|_| Error::NotFound)
+}
+
+fn store_error(e: StoreError) -> Error {
+    match e {
+        StoreError::DoesNotExist => Error::NotFound,
+        e => Error::Database { source: e },
+    }
+}
+
+async fn get_client(
+    _: Admin,
+    State(db): State<Database>,
+    Path(id): Path<String>,
+) -> Result<Json<ClientMetadata>, Error> {
+    let metadata = db
+        .get_client_metadata(client_id(&id)?)
+        .await
+        .map_err(store_error)?;
+
+    Ok(Json(metadata))
+}
+
+async fn patch_client(
+    _: Admin,
+    State(db): State<Database>,
+    Path(id): Path<String>,
+    Json(trust): Json<ClientTrust>,
+) -> Result<Json<ClientMetadata>, Error> {
+    let metadata = db
+        .set_client_trust(
+            client_id(&id)?,
+            trust.first_party,
+            trust.verified,
+            trust.offline_access,
+        )
+        .await
+        .map_err(store_error)?;
+    tracing::info!(
+        "client {} is now first-party: {}, verified: {}, offline access: {}",
+        id,
+        metadata.first_party,
+        metadata.verified,
+        metadata.offline_access
+    );
+
+    Ok(Json(metadata))
+}
+
+/// The lifetimes the client has of its own. Those left out are the server's defaults.
+async fn get_lifetimes(
+    _: Admin,
+    State(db): State<Database>,
+    Path(id): Path<String>,
+) -> Result<Json<ClientLifetimes>, Error> {
+    let lifetimes = db
+        .get_client_lifetimes(client_id(&id)?)
+        .await
+        .map_err(store_error)?;
+
+    Ok(Json(lifetimes))
+}
+
+/// Replace the lifetimes the client has of its own. They apply to codes and tokens issued from
+/// now on.
+async fn put_lifetimes(
+    _: Admin,
+    State(db): State<Database>,
+    Path(id): Path<String>,
+    Json(lifetimes): Json<ClientLifetimes>,
+) -> Result<Json<ClientLifetimes>, Error> {
+    db.set_client_lifetimes(client_id(&id)?, lifetimes)
+        .await
+        .map_err(store_error)?;
+    tracing::info!("client {} now has the lifetimes {:?}", id, lifetimes);
+
+    Ok(Json(lifetimes))
+}
+
+/// How many expired codes, tokens and sessions the maintenance task purged.
+async fn get_maintenance(
+    _: Admin,
+    State(metrics): State<Arc<MaintenanceMetrics>>,
+) -> Json<MaintenanceReport> {
+    Json(metrics.report())
+}
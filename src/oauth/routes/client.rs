@@ -1,6 +1,7 @@
 use crate::oauth::{
-    database::Database,
+    database::{clientmap::ClientMetadata, Database},
     error::{Error, Result},
+    rate_limit::RateLimitLayer,
 };
 
 use axum::{
@@ -10,13 +11,14 @@ use axum::{
     Router,
 };
 use serde::{Deserialize, Serialize};
+use url::Url;
 
-pub fn routes<S>() -> Router<S>
+pub fn routes<S>(rate_limit: RateLimitLayer) -> Router<S>
 where
     S: Send + Sync + 'static + Clone,
     Database: FromRef<S>,
 {
-    Router::new().route("/", post(post_client))
+    Router::new().route("/", post(post_client).layer(rate_limit))
 }
 
 #[derive(Deserialize)]
@@ -31,6 +33,21 @@ struct ClientForm {
     name: String,
     redirect_uri: String,
     r#type: ClientType,
+    logo_uri: Option<String>,
+    homepage_uri: Option<String>,
+    policy_uri: Option<String>,
+}
+
+/// Parse an optional URI that is displayed to users. Only http(s) links are accepted.
+fn parse_display_uri(field: &'static str, value: Option<String>) -> Result<Option<Url>> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => Url::parse(value)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(Some)
+            .ok_or(Error::InvalidUri { field }),
+    }
 }
 
 async fn post_client(
@@ -40,15 +57,21 @@ async fn post_client(
     tracing::debug!("POST Handler: post_client()");
 
     let client_name = client_form.name;
+    let metadata = ClientMetadata {
+        logo_uri: parse_display_uri("logo_uri", client_form.logo_uri)?,
+        homepage_uri: parse_display_uri("homepage_uri", client_form.homepage_uri)?,
+        policy_uri: parse_display_uri("policy_uri", client_form.policy_uri)?,
+        ..Default::default()
+    };
 
     let (client_id, client_secret) = match client_form.r#type {
         ClientType::Public => db
-            .register_public_client(&client_name, &client_form.redirect_uri, "")
+            .register_public_client(&client_name, &client_form.redirect_uri, "", metadata)
             .await
             .map_err(|e| Error::Database { source: (e) })?,
 
         ClientType::Confidential => db
-            .register_confidential_client(&client_name, &client_form.redirect_uri, "")
+            .register_confidential_client(&client_name, &client_form.redirect_uri, "", metadata)
             .await
             .map_err(|e| Error::Database { source: (e) })?,
     };
@@ -0,0 +1,57 @@
+//! Tokens that protect the forms of the authorization server against cross-site request forgery.
+//!
+//! A token is issued into the session each time a form is rendered and consumed when the form is
+//! posted, so it can't be replayed or used with another session. Scripts send it in the
+//! [`HEADER`] instead, and get the next one back in the same header.
+
+use axum::http::HeaderMap;
+use axum_sessions::extractors::WritableSession;
+
+use crate::oauth::{constant_time_eq, error::Error};
+
+const SESSION_KEY: &str = "csrf_tokens";
+/// Outstanding tokens per session. The oldest one is dropped when a form is rendered while this
+/// many are waiting, e.g. because the owner opened many tabs.
+const MAX_TOKENS: usize = 16;
+const TOKEN_LENGTH: usize = 32;
+/// The header that carries the token of requests made by scripts, and the next token in the
+/// response.
+pub const HEADER: &str = "x-csrf-token";
+
+/// Issue a token for a form rendered for this session.
+pub fn issue(session: &mut WritableSession) -> String {
+    let token = nanoid::nanoid!(TOKEN_LENGTH);
+    let mut tokens = session.get::<Vec<String>>(SESSION_KEY).unwrap_or_default();
+    if tokens.len() >= MAX_TOKENS {
+        tokens.drain(..=tokens.len() - MAX_TOKENS);
+    }
+    tokens.push(token.clone());
+    if let Err(e) = session.insert(SESSION_KEY, tokens) {
+        tracing::error!("unable to store CSRF token: {}", e);
+    }
+
+    token
+}
+
+/// Consume the token posted with a form. Fails if it's missing or wasn't issued to this session.
+pub fn verify(session: &mut WritableSession, token: Option<&str>) -> Result<(), Error> {
+    let token = token.filter(|token| !token.is_empty()).ok_or(Error::Csrf)?;
+    let mut tokens = session.get::<Vec<String>>(SESSION_KEY).unwrap_or_default();
+    let position = tokens
+        .iter()
+        .position(|issued| constant_time_eq(issued.as_bytes(), token.as_bytes()))
+        .ok_or(Error::Csrf)?;
+    tokens.remove(position);
+    session
+        .insert(SESSION_KEY, tokens)
+        .map_err(|_| Error::InternalError)
+}
+
+/// Consume the token in the header of a request made by a script, and issue the one for its next
+/// request.
+pub fn rotate(session: &mut WritableSession, headers: &HeaderMap) -> Result<String, Error> {
+    let token = headers.get(HEADER).and_then(|value| value.to_str().ok());
+    verify(session, token)?;
+
+    Ok(issue(session))
+}
@@ -0,0 +1,219 @@
+//! Verifying the email addresses of owners.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Form, FromRef, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use axum_sessions::extractors::WritableSession;
+use serde::Deserialize;
+
+use super::{
+    csrf,
+    session::{self, Session},
+    CsrfForm,
+};
+use crate::oauth::{
+    database::{resource::user::AuthUser, Database, EmailTokenPurpose, UserRecord},
+    error::{Error, HtmlError},
+    i18n::Locale,
+    mailer::{Email, Mailer},
+    settings::Settings,
+    templates::{Notice, NoticeLink, Pages, TemplateProvider},
+};
+
+/// Seconds that the link in a verification email works.
+const VERIFICATION_LIFETIME: u64 = 24 * 60 * 60;
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Send + Sync + 'static + Clone,
+    Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+    Arc<dyn Mailer>: FromRef<S>,
+{
+    Router::new().route("/verify", get(get_verify).post(post_verify))
+}
+
+/// The token from the link in an email.
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    pub token: String,
+}
+
+/// Send the owner a link that verifies `email` is theirs.
+pub async fn send_verification(
+    db: &Database,
+    mailer: &dyn Mailer,
+    settings: &Settings,
+    i18n: &Locale,
+    user: &AuthUser,
+    email: &str,
+) -> Result<(), Error> {
+    let expires = session::now()? + VERIFICATION_LIFETIME;
+    let token = db
+        .issue_email_token(user.user_id, EmailTokenPurpose::VerifyEmail, email, expires)
+        .await;
+    let link = link(settings, "oauth/email/verify", &token)?;
+    let email = Email {
+        to: email.to_owned(),
+        subject: i18n.t("email-verify-subject"),
+        body: i18n.fmt(
+            "email-verify-body",
+            &[("username", &user.username), ("link", &link)],
+        ),
+    };
+
+    mailer
+        .send(email)
+        .await
+        .map_err(|e| Error::Mail { source: e })
+}
+
+/// Tell the owner of a verified address that someone signed up with it. The one signing up isn't
+/// told, so that sign-up doesn't reveal which addresses have accounts.
+pub async fn send_account_exists(
+    mailer: &dyn Mailer,
+    settings: &Settings,
+    i18n: &Locale,
+    record: &UserRecord,
+) -> Result<(), Error> {
+    let address = record.email().ok_or(Error::InternalError)?;
+    let username = record.username().unwrap_or_default();
+    let page = |path| {
+        settings
+            .mail
+            .base_url
+            .join(path)
+            .map(String::from)
+            .map_err(|_| Error::InternalError)
+    };
+    let email = Email {
+        to: address.to_owned(),
+        subject: i18n.t("email-exists-subject"),
+        body: i18n.fmt(
+            "email-exists-body",
+            &[
+                ("username", &username),
+                ("signin", &page("oauth/signin")?),
+                ("forgot", &page("oauth/password/forgot")?),
+            ],
+        ),
+    };
+
+    mailer
+        .send(email)
+        .await
+        .map_err(|e| Error::Mail { source: e })
+}
+
+/// A link to `path` on this server with a token from an email.
+pub fn link(settings: &Settings, path: &str, token: &str) -> Result<String, Error> {
+    let mut url = settings
+        .mail
+        .base_url
+        .join(path)
+        .map_err(|_| Error::InternalError)?;
+    url.query_pairs_mut().append_pair("token", token);
+
+    Ok(url.into())
+}
+
+/// The page telling a signed in owner to verify their email address before they go on, with a
+/// form to send them another link.
+pub fn unverified(pages: &Pages, session: &mut WritableSession) -> Response {
+    let page = pages.render(&Notice {
+        i18n: pages.locale(),
+        heading: pages.locale().t("email-unverified-heading"),
+        message: pages.locale().t("email-unverified"),
+        link: None,
+        resend_csrf_token: Some(csrf::issue(session)),
+    });
+
+    (StatusCode::FORBIDDEN, page).into_response()
+}
+
+/// Follow the link from a verification email.
+async fn get_verify(
+    State(db): State<Database>,
+    pages: Pages,
+    Query(TokenQuery { token }): Query<TokenQuery>,
+) -> Result<Response, HtmlError> {
+    let now = session::now().map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let verified = match db
+        .consume_email_token(&token, EmailTokenPurpose::VerifyEmail, now)
+        .await
+    {
+        Some((user, email)) => {
+            let verified = db.verify_email(user.user_id, &email).await;
+            if verified {
+                tracing::info!("user {} verified their email address", user.username);
+            }
+            verified
+        }
+        None => false,
+    };
+
+    let (status, message) = if verified {
+        (StatusCode::OK, "email-verified")
+    } else {
+        (StatusCode::BAD_REQUEST, "email-link-invalid")
+    };
+    let page = pages.render(&Notice {
+        i18n: pages.locale(),
+        heading: pages.locale().t("email-verify-heading"),
+        message: pages.locale().t(message),
+        link: Some(NoticeLink {
+            href: "/oauth/signin".to_owned(),
+            text: pages.locale().t("signup-signin"),
+        }),
+        resend_csrf_token: None,
+    });
+
+    Ok((status, page).into_response())
+}
+
+/// Send the signed in owner another verification email.
+async fn post_verify(
+    State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, HtmlError> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let record = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    let email = record
+        .email()
+        .filter(|_| !record.email_verified())
+        .ok_or_else(|| HtmlError::new(Error::NotFound, pages.clone()))?;
+    send_verification(
+        &db,
+        mailer.as_ref(),
+        &settings,
+        pages.locale(),
+        &user,
+        email,
+    )
+    .await
+    .map_err(|e| HtmlError::new(e, pages.clone()))?;
+
+    Ok(pages.render(&Notice {
+        i18n: pages.locale(),
+        heading: pages.locale().t("email-verify-heading"),
+        message: pages.locale().t("email-verification-sent"),
+        link: None,
+        resend_csrf_token: None,
+    }))
+}
@@ -1,24 +1,129 @@
-use crate::oauth::database::Database;
+use crate::oauth::{
+    database::Database,
+    mailer::Mailer,
+    maintenance::MaintenanceMetrics,
+    policy::SignupPolicy,
+    rate_limit::RateLimitLayer,
+    settings::{RateLimitSettings, Settings},
+    templates::TemplateProvider,
+    throttle::SignInThrottle,
+};
 use axum::{extract::FromRef, Router};
 use axum_sessions::{async_session::MemoryStore, PersistencePolicy, SameSite, SessionLayer};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::Arc};
+use url::Url;
 
 mod session {
-    use crate::oauth::database::resource::user::AuthUser;
+    use std::time::{SystemTime, UNIX_EPOCH};
 
-    use super::Callback;
-    use axum::{extract::FromRequestParts, http::request::Parts, response::Redirect};
-    use axum_sessions::extractors::ReadableSession;
+    use crate::oauth::{
+        database::{
+            resource::user::{AuthUser, Authentication},
+            Database,
+        },
+        error::Error,
+    };
+
+    use super::{Callback, DEFAULT_CALLBACK};
+    use axum::{
+        extract::{FromRef, FromRequestParts, OriginalUri},
+        http::request::Parts,
+        response::Redirect,
+    };
+    use axum_sessions::{
+        async_session::{self, MemoryStore, SessionStore},
+        extractors::{ReadableSession, WritableSession},
+    };
+    use serde::{Deserialize, Serialize};
 
     pub struct Session {
         pub user: AuthUser,
     }
 
+    const PENDING_KEY: &str = "pending_sign_in";
+    /// The session generation of the owner when they signed in. Changing the password starts a
+    /// new one, which signs the owner out of their other sessions.
+    const GENERATION_KEY: &str = "generation";
+    /// Seconds the owner has to present the second factor after the password.
+    const PENDING_LIFETIME: u64 = 5 * 60;
+
+    /// An owner that entered the right password and still has to present a second factor.
+    #[derive(Deserialize, Serialize)]
+    struct PendingSignIn {
+        user: AuthUser,
+        /// Seconds since the Unix epoch.
+        expires: u64,
+    }
+
+    /// Remember that the owner's password checked out, until they present their second factor
+    /// and are signed in with [`establish`].
+    pub fn await_second_factor(session: &mut WritableSession, user: AuthUser) -> Result<(), Error> {
+        let pending = PendingSignIn {
+            user,
+            expires: now()? + PENDING_LIFETIME,
+        };
+        session
+            .insert(PENDING_KEY, pending)
+            .map_err(|_| Error::InternalError)
+    }
+
+    /// The owner that has to present a second factor, unless they took too long.
+    pub fn pending_second_factor(session: &WritableSession) -> Option<AuthUser> {
+        let pending = session.get::<PendingSignIn>(PENDING_KEY)?;
+        (pending.expires > now().ok()?).then_some(pending.user)
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn now() -> Result<u64, Error> {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .map_err(|_| Error::InternalError)
+    }
+
+    /// Sign the owner into the session once their credentials have been verified.
+    ///
+    /// The owner continues in a new session and the previous one is destroyed, so a session ID
+    /// planted before the owner signed in is worthless.
+    pub async fn establish(
+        store: &MemoryStore,
+        db: &Database,
+        session: &mut WritableSession,
+        user: AuthUser,
+        amr: &[&str],
+    ) -> Result<(), Error> {
+        let authentication = Authentication::new(now()?, amr);
+        let generation = db
+            .get_user_by_id(&user)
+            .await
+            .map_err(|e| Error::Database { source: e })?
+            .session_generation();
+
+        // Sessions share their data with clones, including the one in the store, so the data
+        // is replaced rather than the ID regenerated
+        let mut fresh = async_session::Session::new();
+        if let Some(expiry) = session.expiry() {
+            fresh.set_expiry(*expiry);
+        }
+        let previous = std::mem::replace(&mut ***session, fresh);
+        if let Err(e) = store.destroy_session(previous).await {
+            tracing::error!("unable to destroy the previous session: {}", e);
+        }
+
+        session
+            .insert("user", user)
+            .and_then(|_| session.insert("authentication", authentication))
+            .and_then(|_| session.insert(GENERATION_KEY, generation))
+            .map_err(|_| Error::InternalError)
+    }
+
     #[axum::async_trait]
     impl<S> FromRequestParts<S> for Session
     where
         S: Send + Sync + 'static,
+        Database: FromRef<S>,
     {
         type Rejection = Redirect;
 
@@ -27,16 +132,37 @@ mod session {
             let session = ReadableSession::from_request_parts(parts, state)
                 .await
                 .ok()
-                .and_then(|session| session.get("user"));
+                .and_then(|session| {
+                    let user = session.get::<AuthUser>("user")?;
+                    Some((user, session.get::<u64>(GENERATION_KEY).unwrap_or_default()))
+                });
+
+            // Sessions of deleted owners, and from before the password changed, are signed out
+            let mut signed_in = None;
+            if let Some((user, generation)) = session {
+                let db = Database::from_ref(state);
+                if db
+                    .get_user_by_id(&user)
+                    .await
+                    .is_ok_and(|record| record.session_generation() == generation)
+                {
+                    signed_in = Some(user);
+                }
+            }
 
-            if let Some(user) = session {
+            if let Some(user) = signed_in {
                 Ok(Self { user })
             } else {
-                let path_and_query = parts
-                    .uri
+                // Nested routers only see the rest of the path, so the callback is taken from the
+                // original one, relative to the sign in page
+                let uri = parts
+                    .extensions
+                    .get::<OriginalUri>()
+                    .map_or(&parts.uri, |original| &original.0);
+                let path_and_query = uri
                     .path_and_query()
                     .map(|x| x.as_str())
-                    .map(|x| x.trim_start_matches('/'))
+                    .map(|x| x.strip_prefix(DEFAULT_CALLBACK).unwrap_or(x))
                     .unwrap_or_default();
                 let callback = Callback::from_str(path_and_query);
 
@@ -51,60 +177,131 @@ mod session {
     }
 }
 
-pub fn routes<S>() -> Router<S>
+pub fn routes<S>(sessions: MemoryStore, db: Database, rate_limit: &RateLimitSettings) -> Router<S>
 where
     crate::oauth::state::State: FromRef<S>,
     Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    MemoryStore: FromRef<S>,
+    SignInThrottle: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+    Arc<dyn Mailer>: FromRef<S>,
+    SignupPolicy: FromRef<S>,
+    Arc<MaintenanceMetrics>: FromRef<S>,
     S: Send + Sync + 'static + Clone,
 {
-    let session_layer = SessionLayer::new(MemoryStore::new(), nanoid::nanoid!(128).as_bytes())
+    let session_layer = SessionLayer::new(sessions, nanoid::nanoid!(128).as_bytes())
         .with_cookie_name("axum_oauth")
         .with_secure(true)
         .with_persistence_policy(PersistencePolicy::ChangedOnly)
         .with_cookie_path("/oauth/")
         .with_same_site_policy(SameSite::Lax);
 
+    let limit = |limits: &crate::oauth::settings::RouteLimits| {
+        RateLimitLayer::new(db.clone(), limits.clone(), rate_limit.trust_forwarded_for)
+    };
+
     Router::new()
-        .merge(oauth::routes())
-        .nest("/client", client::routes())
+        .merge(oauth::routes(limit(&rate_limit.token)))
+        .nest("/account", account::routes())
+        .nest("/admin", admin::routes())
+        .nest("/client", client::routes(limit(&rate_limit.registration)))
+        .nest("/email", email::routes())
+        .nest("/password", password::routes())
         .nest("/signin", signin::routes())
-        .nest("/signout", signout::routes().with_state(()))
+        .nest("/signout", signout::routes())
         .nest("/signup", signup::routes())
         .layer(session_layer)
 }
 
+mod account;
+mod admin;
 mod client;
+mod csrf;
+mod email;
 mod oauth;
+mod password;
 mod signin;
 mod signout;
 mod signup;
 
+/// Where the owner is sent after signing in when there is no valid callback.
+const DEFAULT_CALLBACK: &str = "/oauth/";
+
+/// The sign in page, which relative callbacks are resolved against. Only the path matters, the
+/// origin stands in for this server.
+static SIGNIN_URL: Lazy<Url> = Lazy::new(|| Url::parse("http://localhost/oauth/signin").unwrap());
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Callback<'a> {
     callback: Cow<'a, str>,
 }
 
 impl<'a> Callback<'a> {
-    fn as_str(&self) -> &str {
-        self.callback.as_ref()
-    }
-
     fn from_str(callback: &'a str) -> Self {
         Self {
             callback: Cow::Borrowed(callback),
         }
     }
+
+    /// The location to continue at after signing in.
+    ///
+    /// Callbacks must stay on this server below `/oauth/`, or the sign in page could be used to
+    /// send owners anywhere. Relative callbacks are resolved against the sign in page and the
+    /// location is always returned as an absolute path. Anything else falls back to `/oauth/`.
+    fn location(&self) -> Cow<'static, str> {
+        let callback = self.callback.as_ref();
+        // Browsers treat backslashes like slashes, and the URL parser strips control characters
+        // that they might not
+        if callback.is_empty() || callback.contains(['\\', '\n', '\r', '\t']) {
+            return Cow::Borrowed(DEFAULT_CALLBACK);
+        }
+
+        match SIGNIN_URL.join(callback) {
+            Ok(url)
+                if url.origin() == SIGNIN_URL.origin()
+                    && url.path().starts_with(DEFAULT_CALLBACK) =>
+            {
+                let mut location = url.path().to_owned();
+                if let Some(query) = url.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                Cow::Owned(location)
+            }
+            _ => {
+                tracing::warn!("rejected sign in callback: {:?}", callback);
+                Cow::Borrowed(DEFAULT_CALLBACK)
+            }
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub struct LoginForm {
     pub username: String,
     pub password: String,
+    pub csrf_token: Option<String>,
+}
+
+/// A code from an authenticator, or a recovery code.
+#[derive(Deserialize, Clone)]
+pub struct CodeForm {
+    pub code: String,
+    pub csrf_token: Option<String>,
+}
+
+/// A form with nothing but the CSRF token, for actions like sending another email.
+#[derive(Deserialize, Clone)]
+pub struct CsrfForm {
+    pub csrf_token: Option<String>,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct SignUpForm {
     pub username: String,
+    pub email: String,
     pub password: String,
     pub given_name: String,
+    pub csrf_token: Option<String>,
 }
@@ -1,101 +1,182 @@
 use crate::oauth::{
-    database::Database, error::Error, models::ClientId, routes::session::Session,
-    solicitor::Solicitor, Consent,
+    database::{resource::user::AuthUser, Database},
+    error::{Error, HtmlError},
+    models::ClientId,
+    primitives::CheckedClient,
+    rate_limit::RateLimitLayer,
+    routes::{
+        csrf, email,
+        session::{self, Session},
+    },
+    settings::Settings,
+    solicitor::{ConsentSolicitor, Solicitor},
+    templates::{Pages, TemplateProvider},
+    Consent,
 };
 use axum::{
-    extract::{FromRef, Query, State},
+    extract::{Extension, FromRef, Query, State},
     response::IntoResponse,
     routing::{get, post},
     Router,
 };
-use oxide_auth::{
-    endpoint::{OwnerConsent, PreGrant, QueryParameter, Solicitation},
-    frontends::simple::endpoint::FnSolicitor,
-    primitives::scope::Scope,
-};
+use axum_sessions::extractors::WritableSession;
+use oxide_auth::{endpoint::QueryParameter, primitives::scope::Scope};
 use oxide_auth_axum::{OAuthRequest, OAuthResponse, WebError};
+use std::sync::Arc;
 
-pub fn routes<S>() -> Router<S>
+pub fn routes<S>(rate_limit: RateLimitLayer) -> Router<S>
 where
     S: Send + Sync + 'static + Clone,
     crate::oauth::state::State: FromRef<S>,
     crate::oauth::database::Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
 {
     Router::new()
         .route("/authorize", get(get_authorize).post(post_authorize))
-        .route("/refresh", get(refresh))
-        .route("/token", post(token))
+        .route("/refresh", get(refresh).layer(rate_limit.clone()))
+        .route("/token", post(token).layer(rate_limit))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn get_authorize(
     State(state): State<crate::oauth::state::State>,
     State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
     Session { user }: Session,
+    pages: Pages,
+    mut session: WritableSession,
     request: OAuthRequest,
-) -> Result<impl IntoResponse, Error> {
+) -> Result<impl IntoResponse, HtmlError> {
     tracing::debug!("in get_authorize()");
     tracing::debug!("OAuth Request:\n{:?}", request);
+    if !may_authorize(&db, &settings, &user).await {
+        return Ok(email::unverified(&pages, &mut session));
+    }
+    let csrf_token = csrf::issue(&mut session);
     state
         .endpoint()
-        .await
-        .with_solicitor(Solicitor::new(db, user))
+        .with_solicitor(Solicitor::new(
+            db,
+            user,
+            pages.clone(),
+            csrf_token,
+            settings.consent_lifetime,
+        ))
+        .with_authentication(session.get("authentication"))
         .authorization_flow()
         .execute(request)
         .await
         .map(IntoResponse::into_response)
-        .map_err(|e| Error::OAuth { source: e })
+        .map_err(|e| HtmlError::new(Error::OAuth { source: e }, pages))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn post_authorize(
     State(state): State<super::super::state::State>,
     State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
     Query(consent): Query<Consent>,
+    Query(params): Query<Vec<(String, String)>>,
     Session { user }: Session,
-    request: OAuthRequest,
-) -> Result<impl IntoResponse, Error> {
+    pages: Pages,
+    mut session: WritableSession,
+    mut request: OAuthRequest,
+) -> Result<impl IntoResponse, HtmlError> {
     tracing::debug!("in post_authorize()");
     tracing::debug!("request:\n{:?}", request);
     tracing::debug!("consent:\n{:?}", consent);
 
-    state
-        .endpoint()
-        .await
-        .with_solicitor(FnSolicitor(
-            move |_: &mut OAuthRequest, solicitation: Solicitation| {
-                if let Consent::Allow = consent {
-                    let PreGrant {
-                        client_id, scope, ..
-                    } = solicitation.pre_grant().clone();
+    let csrf_token = request.body().and_then(|body| {
+        body.unique_value("csrf_token")
+            .map(|token| token.into_owned())
+    });
+    csrf::verify(&mut session, csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    if !may_authorize(&db, &settings, &user).await {
+        return Ok(email::unverified(&pages, &mut session));
+    }
 
-                    let current_scope = futures::executor::block_on(get_current_authorization(
-                        &db,
-                        &user.username,
-                        &client_id,
-                    ));
-                    if current_scope.is_none() || current_scope.unwrap() < scope {
-                        futures::executor::block_on(update_authorization(
-                            &db,
-                            &user.username,
-                            &client_id,
-                            scope,
-                        ));
-                    }
+    // Narrow the grant to the scopes the owner left checked on the consent form
+    let authentication = session.get("authentication");
+    let now = session::now().map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let consent = match consent {
+        Consent::Allow => match approved_scope(&db, &request).await {
+            Some(scope) => {
+                replace_scope(&mut request, params, &scope);
+                Consent::Allow
+            }
+            None => Consent::Deny,
+        },
+        Consent::Deny => Consent::Deny,
+    };
 
-                    OwnerConsent::Authorized(user.to_string())
-                } else {
-                    OwnerConsent::Denied
-                }
-            },
-        ))
+    state
+        .endpoint()
+        .with_solicitor(ConsentSolicitor::new(db, user, consent, now))
+        .with_authentication(authentication)
         .authorization_flow()
         .execute(request)
         .await
         .map(IntoResponse::into_response)
-        .map_err(|e| Error::OAuth { source: e })
+        .map_err(|e| HtmlError::new(Error::OAuth { source: e }, pages))
+}
+
+/// Whether the policy for unverified accounts lets the owner authorize clients.
+async fn may_authorize(db: &Database, settings: &Settings, user: &AuthUser) -> bool {
+    let verified = db
+        .get_user_by_id(user)
+        .await
+        .is_ok_and(|record| record.email_verified());
+
+    settings.unverified_accounts.may_authorize(verified)
+}
+
+/// The requested scopes that the owner approved, plus any the client requires.
+///
+/// Each approved scope is submitted as a checked form field named after the scope. Returns `None`
+/// if nothing was approved.
+async fn approved_scope(db: &Database, request: &OAuthRequest) -> Option<Scope> {
+    let query = request.query()?;
+    let client_id = query.unique_value("client_id")?.parse::<ClientId>().ok()?;
+    let requested = query.unique_value("scope").unwrap_or_default();
+    let required = db.get_client_default_scope(client_id).await.ok()?;
+    let required = required.iter().collect::<Vec<_>>();
+
+    let approved = requested
+        .split_whitespace()
+        .filter(|scope| {
+            required.contains(scope)
+                || request
+                    .body()
+                    .and_then(|body| body.unique_value(scope))
+                    .is_some()
+        })
+        .collect::<Vec<_>>();
+    if approved.is_empty() {
+        return None;
+    }
+
+    approved.join(" ").parse().ok()
+}
+
+/// Rewrite the `scope` parameter of the authorization request. `params` are the pairs of its
+/// query, which are all kept, including ones the flow doesn't know about.
+fn replace_scope(request: &mut OAuthRequest, params: Vec<(String, String)>, scope: &Scope) {
+    if let Some(query) = request.query_mut() {
+        *query = params
+            .into_iter()
+            .map(|(key, value)| match key.as_str() {
+                "scope" => (key, scope.to_string()),
+                _ => (key, value),
+            })
+            .collect();
+    }
 }
 
 async fn token(
     State(state): State<super::super::state::State>,
+    checked: Option<Extension<CheckedClient>>,
     request: OAuthRequest,
 ) -> Result<OAuthResponse, WebError> {
     tracing::debug!("Endpoint: token(), Request:\n{:?}", request);
@@ -106,10 +187,9 @@ async fn token(
     tracing::debug!("Grant Type: {:?}", grant_type);
 
     match &*grant_type {
-        "refresh_token" => refresh(State(state), request).await,
+        "refresh_token" => refresh(State(state), checked, request).await,
         // "client_credentials" => state
         //     .endpoint()
-        //     .await
         //     .with_solicitor(FnSolicitor(
         //         move |_: &mut OAuthRequest, solicitation: Solicitation| {
         //             let PreGrant {
@@ -123,9 +203,11 @@ async fn token(
         //     .execute(request)
         //     .await,
         _ => {
+            let registrar = state.registrar(checked.map(|Extension(checked)| checked));
             state
                 .endpoint()
-                .await
+                .with_registrar(&registrar)
+                .with_extensions()
                 .access_token_flow()
                 .execute(request)
                 .await
@@ -135,36 +217,22 @@ async fn token(
 
 async fn refresh(
     State(state): State<super::super::state::State>,
+    checked: Option<Extension<CheckedClient>>,
     request: OAuthRequest,
 ) -> Result<OAuthResponse, WebError> {
-    state.endpoint().await.refresh_flow().execute(request).await
-}
-
-async fn get_current_authorization(
-    db: &Database,
-    username: &str,
-    client_str: &str,
-) -> Option<Scope> {
-    let user_record = db.get_user_by_name(username).await;
-    let client_id = client_str.parse::<ClientId>();
-    if user_record.is_err() || client_id.is_err() {
-        return None;
-    }
-    let user_record = user_record.unwrap();
-    let client_id = client_id.unwrap();
-
-    db.get_scope(user_record.id().unwrap(), client_id).await
-}
-
-async fn update_authorization(db: &Database, username: &str, client_str: &str, new_scope: Scope) {
-    let user_record = db.get_user_by_name(username).await;
-    let client_id = client_str.parse::<ClientId>();
-    if user_record.is_err() || client_id.is_err() {
-        return;
-    }
-    let user_record = user_record.unwrap();
-    let client_id = client_id.unwrap();
-    let _ = db
-        .update_client_scope(user_record.id().unwrap(), client_id, new_scope)
-        .await;
+    let token = request
+        .body()
+        .and_then(|body| body.unique_value("refresh_token"))
+        .map(|token| token.into_owned());
+    let _turn = match &token {
+        Some(token) => Some(state.lock_refresh(token).await),
+        None => None,
+    };
+    let registrar = state.registrar(checked.map(|Extension(checked)| checked));
+    state
+        .endpoint()
+        .with_registrar(&registrar)
+        .refresh_flow()
+        .execute(request)
+        .await
 }
@@ -0,0 +1,226 @@
+//! Resetting a forgotten password with a link sent by email.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Form, FromRef, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use axum_sessions::extractors::WritableSession;
+use secrecy::Secret;
+use serde::Deserialize;
+
+use super::{
+    csrf,
+    email::{self, TokenQuery},
+    session,
+};
+use crate::oauth::{
+    database::{resource::user::HashedPassword, Database, EmailTokenPurpose, UserRecord},
+    error::{Error, HtmlError},
+    mailer::{Email, Mailer},
+    policy::SignupPolicy,
+    settings::Settings,
+    templates::{ForgotPassword, Notice, NoticeLink, Pages, ResetPassword, TemplateProvider},
+    throttle::{ClientThrottle, SignInThrottle},
+};
+
+/// Seconds that the link in a password reset email works.
+const RESET_LIFETIME: u64 = 60 * 60;
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Send + Sync + 'static + Clone,
+    crate::oauth::state::State: FromRef<S>,
+    Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    SignInThrottle: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+    Arc<dyn Mailer>: FromRef<S>,
+    SignupPolicy: FromRef<S>,
+{
+    Router::new()
+        .route("/forgot", get(get_forgot).post(post_forgot))
+        .route("/reset", get(get_reset).post(post_reset))
+}
+
+#[derive(Deserialize)]
+pub struct ForgotForm {
+    pub email: String,
+    pub csrf_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ResetForm {
+    pub token: String,
+    pub password: String,
+    pub csrf_token: Option<String>,
+}
+
+async fn get_forgot(pages: Pages, mut session: WritableSession) -> Response {
+    pages.render(&ForgotPassword {
+        i18n: pages.locale(),
+        csrf_token: &csrf::issue(&mut session),
+        sent: false,
+    })
+}
+
+/// Send a reset link to the address, if an account has it.
+///
+/// The response is the same either way, so the form doesn't reveal which addresses have accounts.
+/// The link is sent in the background, or the time it takes would tell. Requests are throttled
+/// whether or not the address has an account, so that the form can't be used to flood an inbox.
+async fn post_forgot(
+    State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    throttle: ClientThrottle,
+    pages: Pages,
+    mut session: WritableSession,
+    Form(form): Form<ForgotForm>,
+) -> Result<Response, HtmlError> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+
+    let email = form.email.trim();
+    if throttle.request_reset(email).await.is_some() {
+        return Err(HtmlError::new(Error::TooManyAttempts, pages));
+    }
+    if let Ok(record) = db.get_user_by_email(email).await {
+        let pages = pages.clone();
+        tokio::spawn(async move {
+            // Failing to send is only logged, or it would tell that the account exists
+            if let Err(e) = send_reset(&db, mailer.as_ref(), &settings, &pages, &record).await {
+                tracing::error!("unable to send a password reset email: {}", e);
+            }
+        });
+    }
+
+    Ok(pages.render(&ForgotPassword {
+        i18n: pages.locale(),
+        csrf_token: &csrf::issue(&mut session),
+        sent: true,
+    }))
+}
+
+/// Send the owner of the record a link that resets their password.
+async fn send_reset(
+    db: &Database,
+    mailer: &dyn Mailer,
+    settings: &Settings,
+    pages: &Pages,
+    record: &UserRecord,
+) -> Result<(), Error> {
+    let user_id = record.id().ok_or(Error::InternalError)?;
+    let address = record.email().ok_or(Error::InternalError)?;
+    let expires = session::now()? + RESET_LIFETIME;
+    let token = db
+        .issue_email_token(user_id, EmailTokenPurpose::ResetPassword, address, expires)
+        .await;
+    let username = record.username().unwrap_or_default();
+    let link = email::link(settings, "oauth/password/reset", &token)?;
+    let email = Email {
+        to: address.to_owned(),
+        subject: pages.locale().t("email-reset-subject"),
+        body: pages.locale().fmt(
+            "email-reset-body",
+            &[("username", &username), ("link", &link)],
+        ),
+    };
+
+    mailer
+        .send(email)
+        .await
+        .map_err(|e| Error::Mail { source: e })
+}
+
+/// The form for a new password. The token is only checked, and used up, when it's posted.
+async fn get_reset(
+    pages: Pages,
+    mut session: WritableSession,
+    Query(TokenQuery { token }): Query<TokenQuery>,
+) -> Response {
+    pages.render(&ResetPassword {
+        i18n: pages.locale(),
+        csrf_token: &csrf::issue(&mut session),
+        token: &token,
+        error: None,
+    })
+}
+
+async fn post_reset(
+    State(state): State<crate::oauth::state::State>,
+    State(db): State<Database>,
+    State(policy): State<SignupPolicy>,
+    State(throttle): State<SignInThrottle>,
+    pages: Pages,
+    mut session: WritableSession,
+    Form(form): Form<ResetForm>,
+) -> Result<Response, HtmlError> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let now = session::now().map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let invalid_link = || {
+        let page = pages.render(&Notice {
+            i18n: pages.locale(),
+            heading: pages.locale().t("password-reset-heading"),
+            message: pages.locale().t("email-link-invalid"),
+            link: Some(NoticeLink {
+                href: "/oauth/password/forgot".to_owned(),
+                text: pages.locale().t("password-forgot-again"),
+            }),
+            resend_csrf_token: None,
+        });
+        Ok((StatusCode::BAD_REQUEST, page).into_response())
+    };
+
+    // The link is only used up once the new password is accepted
+    let Some(user) = db
+        .peek_email_token(&form.token, EmailTokenPurpose::ResetPassword, now)
+        .await
+    else {
+        return invalid_link();
+    };
+    if let Err(violation) = policy.check_password(&form.password, &user.username) {
+        let page = pages.render(&ResetPassword {
+            i18n: pages.locale(),
+            csrf_token: &csrf::issue(&mut session),
+            token: &form.token,
+            error: Some(violation.message(pages.locale())),
+        });
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+    }
+    let Some((user, address)) = db
+        .consume_email_token(&form.token, EmailTokenPurpose::ResetPassword, now)
+        .await
+    else {
+        return invalid_link();
+    };
+
+    let password = HashedPassword::new(Secret::new(form.password))
+        .await
+        .map_err(|e| HtmlError::new(Error::Hash { source: e }, pages.clone()))?;
+    db.set_password(user.user_id, password)
+        .await
+        .map_err(|e| HtmlError::new(Error::Database { source: e }, pages.clone()))?;
+    // Whoever knew the old password may have signed in or authorized clients with it
+    state.revoke_tokens(&user, None);
+    // The link proves that the owner reads mail sent to the address, and lifts any lockout
+    db.verify_email(user.user_id, &address).await;
+    throttle.unlock(&user.username).await;
+    tracing::info!("user {} reset their password", user.username);
+
+    Ok(pages.render(&Notice {
+        i18n: pages.locale(),
+        heading: pages.locale().t("password-reset-heading"),
+        message: pages.locale().t("password-reset-done"),
+        link: Some(NoticeLink {
+            href: "/oauth/signin".to_owned(),
+            text: pages.locale().t("signup-signin"),
+        }),
+        resend_csrf_token: None,
+    }))
+}
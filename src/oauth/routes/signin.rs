@@ -1,89 +1,405 @@
-use super::{Callback, LoginForm};
+use super::{csrf, session, Callback, CodeForm, LoginForm};
 use crate::oauth::{
     database::{resource::user::AuthUser, Database},
-    error::{Error, Result},
-    templates::SignIn,
+    error::{Error, HtmlError},
+    i18n::Locale,
+    settings::Settings,
+    templates::{Pages, SignIn, SignInTotp, TemplateProvider},
+    throttle::{ClientThrottle, SignInThrottle},
+    webauthn::{Authentication, AuthenticationCredential, RelyingParty, UserVerification},
+};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use axum::{
     extract::{Form, FromRef, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Redirect},
-    routing::get,
-    Router,
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
 };
-use axum_sessions::extractors::WritableSession;
+use axum_sessions::{async_session::MemoryStore, extractors::WritableSession};
 
 pub fn routes<S>() -> Router<S>
 where
     S: Send + Sync + 'static + Clone,
     crate::oauth::state::State: FromRef<S>,
     Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    MemoryStore: FromRef<S>,
+    SignInThrottle: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
 {
-    Router::new().route("/", get(get_signin).post(post_signin))
+    Router::new()
+        .route("/", get(get_signin).post(post_signin))
+        .route("/totp", get(get_signin_totp).post(post_signin_totp))
+        .route("/passkey", post(post_signin_passkey))
+        .route("/passkey/options", post(post_signin_passkey_options))
 }
 
-async fn get_signin(query: Option<Query<Callback<'_>>>) -> impl IntoResponse {
+/// The authentication ceremony of a passkey, until the browser responds.
+const AUTHENTICATION_KEY: &str = "passkey_authentication";
+
+async fn get_signin(
+    pages: Pages,
+    query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
+) -> impl IntoResponse {
     let query = &query
         .as_ref()
         .and_then(|Query(x)| serde_urlencoded::to_string(x).ok())
         .unwrap_or_default();
-    SignIn { query }.into_response()
+    pages.render(&SignIn {
+        i18n: pages.locale(),
+        query,
+        csrf_token: &csrf::issue(&mut session),
+        error: None,
+        locked: false,
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn post_signin(
     State(db): State<Database>,
+    State(sessions): State<MemoryStore>,
+    State(settings): State<Arc<Settings>>,
+    throttle: ClientThrottle,
+    pages: Pages,
     query: Option<Query<Callback<'_>>>,
     mut session: WritableSession,
     Form(user_form): Form<LoginForm>,
-) -> Result<impl IntoResponse> {
-    let query = query.as_ref().map(|x| x.as_str());
+) -> Result<impl IntoResponse, HtmlError> {
+    let Query(callback) = query.unwrap_or_default();
+    let query = serde_urlencoded::to_string(&callback).unwrap_or_default();
 
     tracing::debug!("entered -> post_signin()");
-    let user_record = db.get_user_by_name(&user_form.username).await;
-    if user_record.is_err() {
-        tracing::debug!("        user DOES NOT exist");
+    csrf::verify(&mut session, user_form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+
+    // Locked out sign-ins are rejected before the password is checked, so guessing on doesn't
+    // reveal whether a guess was right
+    if let Some(until) = throttle.locked_until(&user_form.username).await {
+        tracing::debug!("        LOCKED OUT");
+        return Ok(locked_out(&pages, &query, &mut session, until));
+    }
+
+    // Nothing is written to the session before the credentials check out
+    let Some(user_record) = db
+        .authenticate(&user_form.username, &user_form.password)
+        .await
+    else {
+        tracing::debug!("        NOT authorized");
+        throttle.record_failure(&user_form.username).await;
+        if let Some(until) = throttle.locked_until(&user_form.username).await {
+            return Ok(locked_out(&pages, &query, &mut session, until));
+        }
+
         return Ok((
             StatusCode::UNAUTHORIZED,
-            SignIn {
-                query: query.unwrap_or_default(),
-            },
+            pages.render(&SignIn {
+                i18n: pages.locale(),
+                query: &query,
+                csrf_token: &csrf::issue(&mut session),
+                error: Some(pages.locale().t("signin-failed")),
+                locked: false,
+            }),
+        )
+            .into_response());
+    };
+    if !settings
+        .unverified_accounts
+        .may_sign_in(user_record.email_verified())
+    {
+        tracing::debug!("        email NOT verified");
+        return Ok((
+            StatusCode::FORBIDDEN,
+            pages.render(&SignIn {
+                i18n: pages.locale(),
+                query: &query,
+                csrf_token: &csrf::issue(&mut session),
+                error: Some(pages.locale().t("signin-unverified")),
+                locked: false,
+            }),
         )
             .into_response());
     }
-    let user_record = user_record.unwrap();
-    let authorized = db
-        .verify_password(&user_form.username, &user_form.password)
+    // The username as registered, however it was written in the form
+    let user = AuthUser {
+        user_id: user_record.id().unwrap(),
+        username: user_record.username().unwrap_or(user_form.username),
+    };
+
+    // Owners with an authenticator aren't signed in, or let off the throttle, before they
+    // present a code from it
+    if user_record.totp().is_some() {
+        tracing::debug!("    second factor required");
+        session::await_second_factor(&mut session, user)
+            .map_err(|e| HtmlError::new(e, pages.clone()))?;
+        return Ok(Redirect::to(&format!("/oauth/signin/totp?{query}")).into_response());
+    }
+
+    throttle.record_success(&user.username).await;
+    session::establish(&sessions, &db, &mut session, user, &["pwd"])
         .await
-        .map_err(|e| Error::Database { source: (e) })?;
-    let _ = session.insert(
-        "user",
-        AuthUser {
-            user_id: user_record.id().unwrap(),
-            username: user_form.username,
-        },
-    );
-
-    tracing::debug!("    checking authorization");
-    if !authorized {
-        tracing::debug!("        NOT authorized");
-        Ok((
-            StatusCode::UNAUTHORIZED,
-            SignIn {
-                query: query.unwrap_or_default(),
-            },
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+
+    let location = callback.location();
+    tracing::debug!("    redirect to callback: {}", location);
+    Ok(Redirect::to(&location).into_response())
+}
+
+/// The sign in page telling the owner to wait until `until`.
+fn locked_out(
+    pages: &Pages,
+    query: &str,
+    session: &mut WritableSession,
+    until: SystemTime,
+) -> Response {
+    let (wait, message) = lockout_message(pages.locale(), until);
+    let page = pages.render(&SignIn {
+        i18n: pages.locale(),
+        query,
+        csrf_token: &csrf::issue(session),
+        error: Some(message),
+        locked: true,
+    });
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, wait.to_string())],
+        page,
+    )
+        .into_response()
+}
+
+/// Seconds until `until`, and the message telling the owner to wait that long.
+fn lockout_message(i18n: &Locale, until: SystemTime) -> (u64, String) {
+    let wait = until
+        .duration_since(SystemTime::now())
+        .unwrap_or_default()
+        .as_secs()
+        + 1;
+    let minutes = wait.div_ceil(60).to_string();
+    let message = i18n.fmt("signin-locked", &[("minutes", &minutes)]);
+
+    (wait, message)
+}
+
+async fn get_signin_totp(
+    State(db): State<Database>,
+    pages: Pages,
+    query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
+) -> Response {
+    let query = query
+        .as_ref()
+        .and_then(|Query(x)| serde_urlencoded::to_string(x).ok())
+        .unwrap_or_default();
+    let Some(user) = session::pending_second_factor(&session) else {
+        return Redirect::to(&format!("/oauth/signin?{query}")).into_response();
+    };
+
+    pages.render(&SignInTotp {
+        i18n: pages.locale(),
+        query: &query,
+        csrf_token: &csrf::issue(&mut session),
+        error: None,
+        locked: false,
+        passkeys: has_passkeys(&db, &user).await,
+    })
+}
+
+async fn has_passkeys(db: &Database, user: &AuthUser) -> bool {
+    db.get_user_by_id(user)
+        .await
+        .is_ok_and(|record| !record.passkeys().is_empty())
+}
+
+async fn post_signin_totp(
+    State(db): State<Database>,
+    State(sessions): State<MemoryStore>,
+    throttle: ClientThrottle,
+    pages: Pages,
+    query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, HtmlError> {
+    let Query(callback) = query.unwrap_or_default();
+    let query = serde_urlencoded::to_string(&callback).unwrap_or_default();
+
+    csrf::verify(&mut session, form.csrf_token.as_deref())
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+    let Some(user) = session::pending_second_factor(&session) else {
+        return Ok(Redirect::to(&format!("/oauth/signin?{query}")).into_response());
+    };
+
+    // Wrong codes count against the account like wrong passwords, or the code could be guessed
+    // once the password is known
+    let passkeys = has_passkeys(&db, &user).await;
+    let page = |session: &mut WritableSession, status, error, locked| {
+        let page = pages.render(&SignInTotp {
+            i18n: pages.locale(),
+            query: &query,
+            csrf_token: &csrf::issue(session),
+            error: Some(error),
+            locked,
+            passkeys,
+        });
+        (status, page).into_response()
+    };
+    let locked_out = |session: &mut WritableSession, until| {
+        let (wait, message) = lockout_message(pages.locale(), until);
+        let mut response = page(session, StatusCode::TOO_MANY_REQUESTS, message, true);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, wait.into());
+        response
+    };
+    if let Some(until) = throttle.locked_until(&user.username).await {
+        return Ok(locked_out(&mut session, until));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| HtmlError::new(Error::InternalError, pages.clone()))?
+        .as_secs();
+    let amr: &[&str] = if db.verify_totp(user.user_id, &form.code, now).await {
+        &["pwd", "otp", "mfa"]
+    } else if db.use_recovery_code(user.user_id, &form.code).await {
+        tracing::info!("user {} signed in with a recovery code", user.username);
+        &["pwd", "mfa"]
+    } else {
+        throttle.record_failure(&user.username).await;
+        if let Some(until) = throttle.locked_until(&user.username).await {
+            return Ok(locked_out(&mut session, until));
+        }
+        let error = pages.locale().t("signin-totp-failed");
+        return Ok(page(&mut session, StatusCode::UNAUTHORIZED, error, false));
+    };
+
+    throttle.record_success(&user.username).await;
+    session::establish(&sessions, &db, &mut session, user, amr)
+        .await
+        .map_err(|e| HtmlError::new(e, pages.clone()))?;
+
+    Ok(Redirect::to(&callback.location()).into_response())
+}
+
+/// Start signing in with a passkey. Responds with the options for `navigator.credentials.get()`.
+///
+/// On its own, any passkey of the relying party will do, and it has to verify the owner, as it
+/// stands in for both password and second factor. After the password, it has to be one of the
+/// owner's passkeys and presence is enough.
+async fn post_signin_passkey_options(
+    State(db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    mut session: WritableSession,
+) -> Result<Json<serde_json::Value>, Error> {
+    let relying_party = RelyingParty::new(&settings.webauthn);
+    let now = session::now()?;
+    let started = match session::pending_second_factor(&session) {
+        Some(user) => {
+            let record = db
+                .get_user_by_id(&user)
+                .await
+                .map_err(|e| Error::Database { source: e })?;
+            relying_party.start_authentication(record.passkeys(), UserVerification::Preferred, now)
+        }
+        None => relying_party.start_authentication(&[], UserVerification::Required, now),
+    };
+    let (options, ceremony) = started.map_err(|e| Error::Webauthn { source: e })?;
+    session
+        .insert(AUTHENTICATION_KEY, ceremony)
+        .map_err(|_| Error::InternalError)?;
+
+    Ok(Json(options))
+}
+
+/// Finish signing in with a passkey. Responds with where to continue, as the ceremony is run by
+/// a script rather than a form.
+#[allow(clippy::too_many_arguments)]
+async fn post_signin_passkey(
+    State(db): State<Database>,
+    State(sessions): State<MemoryStore>,
+    State(settings): State<Arc<Settings>>,
+    throttle: ClientThrottle,
+    i18n: Locale,
+    query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
+    Json(credential): Json<AuthenticationCredential>,
+) -> Result<Response, Error> {
+    let Query(callback) = query.unwrap_or_default();
+    let failed = || {
+        let body = serde_json::json!({ "error": i18n.t("signin-passkey-failed") });
+        (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+    };
+
+    // A ceremony is only good for one response
+    let ceremony = session.get::<Authentication>(AUTHENTICATION_KEY);
+    session.remove(AUTHENTICATION_KEY);
+    let now = session::now()?;
+    let Some(ceremony) = ceremony.filter(|ceremony| !ceremony.is_expired(now)) else {
+        return Ok(failed());
+    };
+    let pending = session::pending_second_factor(&session);
+    let Some((user, passkey)) =
+        db.find_passkey(credential.raw_id.as_slice())
+            .await
+            .filter(|(user, _)| {
+                pending
+                    .as_ref()
+                    .is_none_or(|pending| pending.user_id == user.user_id)
+            })
+    else {
+        tracing::debug!("unknown passkey");
+        return Ok(failed());
+    };
+
+    if let Some(until) = throttle.locked_until(&user.username).await {
+        let (wait, message) = lockout_message(&i18n, until);
+        let body = serde_json::json!({ "error": message });
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, wait.to_string())],
+            Json(body),
         )
-            .into_response())
-    } else if let Some(query) = query {
-        if !query.is_empty() {
-            tracing::debug!("    redirect to callback: {}", query);
-            Ok(Redirect::to(query).into_response())
-        } else {
-            tracing::debug!("    redirect to /oauth/");
-            Ok(Redirect::to("/oauth/").into_response())
+            .into_response());
+    }
+
+    let assertion =
+        RelyingParty::new(&settings.webauthn).authenticate(&ceremony, &credential, &user, &passkey);
+    let verified = match assertion {
+        Ok(assertion) => {
+            db.use_passkey(user.user_id, passkey.credential_id(), assertion.sign_count)
+                .await
         }
-    } else {
-        tracing::debug!("    redirect to /oauth/");
-        Ok(Redirect::to("/oauth/").into_response())
+        Err(e) => {
+            tracing::info!("passkey of user {} rejected: {}", user.username, e);
+            false
+        }
+    };
+    if !verified {
+        throttle.record_failure(&user.username).await;
+        return Ok(failed());
     }
+    let email_verified = db
+        .get_user_by_id(&user)
+        .await
+        .map_err(|e| Error::Database { source: e })?
+        .email_verified();
+    if !settings.unverified_accounts.may_sign_in(email_verified) {
+        let body = serde_json::json!({ "error": i18n.t("signin-unverified") });
+        return Ok((StatusCode::FORBIDDEN, Json(body)).into_response());
+    }
+
+    let amr: &[&str] = match pending {
+        Some(_) => &["pwd", "hwk", "mfa"],
+        None => &["hwk", "mfa"],
+    };
+    throttle.record_success(&user.username).await;
+    session::establish(&sessions, &db, &mut session, user, amr).await?;
+
+    Ok(Json(serde_json::json!({ "location": callback.location() })).into_response())
 }
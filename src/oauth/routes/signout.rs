@@ -1,12 +1,40 @@
-use axum::{http::StatusCode, response::IntoResponse, routing::post, Router};
+use axum::{
+    extract::{Form, FromRef},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
 use axum_sessions::extractors::WritableSession;
 
-pub fn routes() -> Router {
-    Router::new().route("/", post(post_signout))
+use super::{csrf, CsrfForm};
+use crate::oauth::{
+    error::Error,
+    templates::{Pages, SignOut, TemplateProvider},
+};
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Send + Sync + 'static + Clone,
+    TemplateProvider: FromRef<S>,
+{
+    Router::new().route("/", get(get_signout).post(post_signout))
+}
+
+async fn get_signout(pages: Pages, mut session: WritableSession) -> impl IntoResponse {
+    pages.render(&SignOut {
+        i18n: pages.locale(),
+        csrf_token: &csrf::issue(&mut session),
+    })
 }
 
-async fn post_signout(mut session: WritableSession) -> impl IntoResponse {
+/// Sign out, with the token of the form, so that other sites can't sign the owner out.
+async fn post_signout(
+    mut session: WritableSession,
+    Form(form): Form<CsrfForm>,
+) -> Result<StatusCode, Error> {
+    csrf::verify(&mut session, form.csrf_token.as_deref())?;
     session.destroy();
 
-    StatusCode::OK
+    Ok(StatusCode::OK)
 }
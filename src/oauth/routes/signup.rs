@@ -1,39 +1,146 @@
-use super::{Callback, SignUpForm};
+use super::{csrf, email, Callback, SignUpForm};
 use crate::oauth::{
-    database::Database,
+    database::{
+        resource::user::{AuthUser, HashedPassword},
+        Database, StoreError,
+    },
     error::{Error, Result},
+    i18n::Locale,
+    mailer::Mailer,
+    policy::{FieldError, SignupPolicy, Violation},
+    settings::Settings,
+    templates::{Pages, SignUp, TemplateProvider},
 };
 use axum::{
     extract::{Form, FromRef, Query, State},
     http::StatusCode,
-    routing::post,
+    response::IntoResponse,
+    routing::get,
     Router,
 };
+use axum_sessions::extractors::WritableSession;
 use secrecy::Secret;
+use std::sync::Arc;
 
 pub fn routes<S>() -> Router<S>
 where
     S: Send + Sync + 'static + Clone,
     Database: FromRef<S>,
+    TemplateProvider: FromRef<S>,
+    Arc<Settings>: FromRef<S>,
+    Arc<dyn Mailer>: FromRef<S>,
+    SignupPolicy: FromRef<S>,
 {
-    Router::new().route("/", post(post_signup))
+    Router::new().route("/", get(get_signup).post(post_signup))
 }
 
+async fn get_signup(
+    pages: Pages,
+    query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
+) -> impl IntoResponse {
+    let query = &query
+        .as_ref()
+        .and_then(|Query(x)| serde_urlencoded::to_string(x).ok())
+        .unwrap_or_default();
+    pages.render(&SignUp {
+        i18n: pages.locale(),
+        query,
+        csrf_token: &csrf::issue(&mut session),
+    })
+}
+
+/// Register the owner and send them a link to verify their email address.
+///
+/// Fields the sign-up policy doesn't accept are reported together, each with the reason. An
+/// address someone verified is answered like any other, and its owner gets an email instead.
+#[allow(clippy::too_many_arguments)]
 async fn post_signup(
     State(mut db): State<Database>,
+    State(settings): State<Arc<Settings>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    State(policy): State<SignupPolicy>,
+    i18n: Locale,
     _query: Option<Query<Callback<'_>>>,
+    mut session: WritableSession,
     Form(user): Form<SignUpForm>,
 ) -> Result<StatusCode, Error> {
-    if db.contains_user_name(&user.username).await {
-        return Err(Error::ResourceConflict);
+    csrf::verify(&mut session, user.csrf_token.as_deref())?;
+    let mut errors = Vec::new();
+    let mut accept = |field, result: Result<String, Violation>| match result {
+        Ok(value) => Some(value),
+        Err(violation) => {
+            errors.push(FieldError::new(field, violation, &i18n));
+            None
+        }
+    };
+    let username = accept("username", policy.check_username(&user.username));
+    let address = user.email.trim();
+    let email = accept(
+        "email",
+        match address {
+            "" => Err(Violation::Required),
+            _ => address
+                .parse::<lettre::Address>()
+                .map(|_| address.to_owned())
+                .map_err(|_| Violation::Invalid),
+        },
+    );
+    let password = accept(
+        "password",
+        policy
+            .check_password(&user.password, &user.username)
+            .map(|_| user.password),
+    );
+    let given_name = accept("given_name", policy.check_given_name(&user.given_name));
+    let (Some(username), Some(email), Some(password), Some(given_name)) =
+        (username, email, password, given_name)
+    else {
+        return Err(Error::Validation { errors });
+    };
+
+    if db.contains_user_name(&username).await {
+        let errors = vec![FieldError::new("username", Violation::Taken, &i18n)];
+        return Err(Error::Validation { errors });
     }
 
-    db.register_user(
-        &user.username,
-        Secret::from(user.password),
-        &user.given_name,
-    )
-    .await;
+    // Hashed either way, or how long the answer takes would tell whether the address is taken
+    let password = HashedPassword::new(Secret::from(password))
+        .await
+        .map_err(|e| Error::Hash { source: e })?;
+    if let Some(holder) = db
+        .get_user_by_email(&email)
+        .await
+        .ok()
+        .filter(|holder| holder.email_verified())
+    {
+        if let Err(e) = email::send_account_exists(mailer.as_ref(), &settings, &i18n, &holder).await
+        {
+            tracing::error!("unable to send an account exists email: {}", e);
+        }
+        return Ok(StatusCode::CREATED);
+    }
+    let user_id = match db
+        .register_user_with_email(&username, password, &given_name, &email)
+        .await
+    {
+        Ok(user_id) => user_id,
+        // Someone may have taken the username since it was checked, or verified the address
+        Err(StoreError::DuplicateRecord) if db.contains_user_name(&username).await => {
+            let errors = vec![FieldError::new("username", Violation::Taken, &i18n)];
+            return Err(Error::Validation { errors });
+        }
+        Err(StoreError::DuplicateRecord) => return Ok(StatusCode::CREATED),
+        Err(e) => return Err(Error::Database { source: e }),
+    };
+
+    // The account exists either way, and the owner can ask for another link
+    let owner = AuthUser { user_id, username };
+    if let Err(e) =
+        email::send_verification(&db, mailer.as_ref(), &settings, &i18n, &owner, &email).await
+    {
+        tracing::error!("unable to send a verification email: {}", e);
+    }
 
     Ok(StatusCode::CREATED)
 }
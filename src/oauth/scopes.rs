@@ -1,33 +1,75 @@
-pub const SCOPES: &[&str] = &[Account::READ, Account::WRITE];
+use axum_oauth_macros::Resource;
 
+#[doc(hidden)]
+pub use inventory;
+
+/// A protected resource and the scopes guarding it.
+///
+/// Implement it with `#[derive(Resource)]` so that the scopes are added to the registry as well.
 pub trait Resource {
+    const NAME: &'static str;
     const READ: &'static str;
     const WRITE: &'static str;
+    const SCOPES: &'static [&'static str];
 }
 
+#[derive(Resource)]
+#[resource(name = "account", actions(disconnect))]
+#[scope(
+    read,
+    description = "View your username, your name and the apps connected to your account"
+)]
+#[scope(
+    write,
+    description = "Change the name on your account",
+    warning = "The app will be able to change your profile without asking you again."
+)]
+#[scope(
+    disconnect,
+    description = "Disconnect the other apps connected to your account",
+    warning = "The app will be able to take access away from your other apps."
+)]
 pub struct Account;
 
-impl Resource for Account {
-    const READ: &'static str = "account:read";
-    const WRITE: &'static str = "account:write";
+/// Lets the client get refresh tokens, so that it keeps access while the owner isn't using it.
+pub const OFFLINE_ACCESS: &str = "offline_access";
+
+// It guards no resource, only how long the client may keep access to the others
+inventory::submit! {
+    ScopeInfo {
+        scope: OFFLINE_ACCESS,
+        resource: "",
+        action: OFFLINE_ACCESS,
+        description: "Keep access to your account while you're not using the app",
+        warning: None,
+    }
 }
 
+/// An entry in the scope registry.
 #[derive(Debug)]
-pub enum Scopes {
-    AccountRead,
-    AccountWrite,
+pub struct ScopeInfo {
+    pub scope: &'static str,
+    pub resource: &'static str,
+    pub action: &'static str,
+    /// What the scope allows, in terms the resource owner understands.
+    pub description: &'static str,
+    /// Shown on the consent page for sensitive scopes.
+    pub warning: Option<&'static str>,
 }
 
-impl std::str::FromStr for Scopes {
-    type Err = ();
+inventory::collect!(ScopeInfo);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            Account::READ => Self::AccountRead,
-            Account::WRITE => Self::AccountWrite,
-            _ => return Err(()),
-        })
-    }
+/// All scopes declared by resources in this binary.
+pub fn registry() -> impl Iterator<Item = &'static ScopeInfo> {
+    inventory::iter::<ScopeInfo>.into_iter()
+}
+
+pub fn lookup(scope: &str) -> Option<&'static ScopeInfo> {
+    registry().find(|info| info.scope == scope)
+}
+
+pub fn is_registered(scope: &str) -> bool {
+    lookup(scope).is_some()
 }
 
 pub struct Read<S>(pub S);
@@ -4,24 +4,51 @@ use crate::oauth::{
             client::AuthClient,
             user::{AuthUser, Authorization},
         },
-        Database,
+        ConsentSource, Database,
     },
-    templates::Authorize,
+    templates::{Authorize, Pages},
+    Consent,
 };
-use askama::Template;
 use oxide_auth::endpoint::{OwnerConsent, Solicitation, WebRequest};
 use oxide_auth_async::endpoint::OwnerSolicitor;
 use oxide_auth_axum::{OAuthRequest, OAuthResponse, WebError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn map_err<E: std::error::Error>(err: E) -> OwnerConsent<<OAuthRequest as WebRequest>::Response> {
+    OwnerConsent::Error(WebError::InternalError(Some(err.to_string())))
+}
 
 pub struct Solicitor {
     db: Database,
     user: AuthUser,
+    pages: Pages,
+    csrf_token: String,
+    /// How long a consent is remembered for, if not for good.
+    consent_lifetime: Option<Duration>,
 }
 
 impl Solicitor {
-    pub fn new(db: Database, user: AuthUser) -> Self {
+    pub fn new(
+        db: Database,
+        user: AuthUser,
+        pages: Pages,
+        csrf_token: String,
+        consent_lifetime: Option<Duration>,
+    ) -> Self {
         tracing::debug!("db: XXXX, user: {:?}", user);
-        Self { db, user }
+        Self {
+            db,
+            user,
+            pages,
+            csrf_token,
+            consent_lifetime,
+        }
+    }
+
+    /// Whether the consent given at `updated_at` has to be asked for again at `now`.
+    fn is_expired(&self, updated_at: u64, now: u64) -> bool {
+        self.consent_lifetime
+            .is_some_and(|lifetime| now >= updated_at.saturating_add(lifetime.as_secs()))
     }
 }
 
@@ -34,12 +61,6 @@ impl OwnerSolicitor<OAuthRequest> for Solicitor {
     ) -> OwnerConsent<<OAuthRequest as WebRequest>::Response> {
         tracing::debug!("in check_consent()");
         tracing::debug!("Request: {:?}", req);
-        fn map_err<E: std::error::Error>(
-            err: E,
-        ) -> OwnerConsent<<OAuthRequest as WebRequest>::Response> {
-            OwnerConsent::Error(WebError::InternalError(Some(err.to_string())))
-        }
-
         let pre_g = solicitation.pre_grant();
         tracing::debug!("PreGrant: {:?}", pre_g);
 
@@ -53,10 +74,24 @@ impl OwnerSolicitor<OAuthRequest> for Solicitor {
             Err(err) => return err,
         };
 
+        let now = match SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(map_err)
+        {
+            Ok(elapsed) => elapsed.as_secs(),
+            Err(err) => return err,
+        };
+
         // Is there already an authorization (user:client pair) ?
         //
-        let previous_scope = self.db.get_scope(self.user.user_id, client_id.id).await;
-        let authorization = previous_scope.map(|scope| Authorization { scope });
+        let authorization = self
+            .db
+            .get_authorization(self.user.user_id, client_id.id)
+            .await
+            .filter(|authorization| !self.is_expired(authorization.updated_at, now))
+            .map(|authorization| Authorization {
+                scope: authorization.scope,
+            });
 
         tracing::debug!("Current scope of client: {:?}", authorization);
         tracing::debug!(
@@ -73,6 +108,40 @@ impl OwnerSolicitor<OAuthRequest> for Solicitor {
             _ => (),
         }
 
+        let res = self
+            .db
+            .get_client_metadata(client_id.id)
+            .await
+            .map_err(map_err);
+        let metadata = match res {
+            Ok(metadata) => metadata,
+            Err(err) => return err,
+        };
+
+        // Clients of this service don't ask their owners. The approval is recorded like a consent.
+        if metadata.first_party {
+            let scope = solicitation.pre_grant().scope.clone();
+            let res = self
+                .db
+                .update_client_scope(
+                    self.user.user_id,
+                    client_id.id,
+                    scope,
+                    ConsentSource::FirstParty,
+                    now,
+                )
+                .await;
+            if let Err(err) = res {
+                return map_err(err);
+            }
+            tracing::info!(
+                "user {} authorized first-party client {} without consent",
+                self.user.username,
+                client_id.id
+            );
+            return OwnerConsent::Authorized(self.user.to_string());
+        }
+
         // Attempt to get user and encoded client records
         let res = self.db.get_client_name(client_id.id).await.map_err(map_err);
         let client = match res {
@@ -84,14 +153,32 @@ impl OwnerSolicitor<OAuthRequest> for Solicitor {
             Ok(user) => user,
             Err(err) => return err,
         };
+        let res = self
+            .db
+            .get_client_default_scope(client_id.id)
+            .await
+            .map_err(map_err);
+        let required = match res {
+            Ok(scope) => scope,
+            Err(err) => return err,
+        };
 
         // create parameters for consent form and display it to the owner
         if let Some((client, user)) = Some(client).zip(Some(user)) {
             // username() is guaranteed to return a value because user was returned from the db
             let username = user.username().unwrap();
-            let body = Authorize::new(req, &solicitation, &username, &client.inner);
+            let body = Authorize::new(
+                self.pages.locale(),
+                req,
+                &solicitation,
+                &username,
+                &client.inner,
+                &metadata,
+                &required,
+                &self.csrf_token,
+            );
 
-            match body.render().map_err(map_err) {
+            match self.pages.render_string(&body).map_err(map_err) {
                 Ok(inner) => OwnerConsent::InProgress(
                     OAuthResponse::default()
                         .content_type("text/html")
@@ -105,3 +192,62 @@ impl OwnerSolicitor<OAuthRequest> for Solicitor {
         }
     }
 }
+
+/// Answers the consent form with the owner's choice, and records the scopes they consented to.
+pub struct ConsentSolicitor {
+    db: Database,
+    user: AuthUser,
+    consent: Consent,
+    now: u64,
+}
+
+impl ConsentSolicitor {
+    pub fn new(db: Database, user: AuthUser, consent: Consent, now: u64) -> Self {
+        Self {
+            db,
+            user,
+            consent,
+            now,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl OwnerSolicitor<OAuthRequest> for ConsentSolicitor {
+    async fn check_consent(
+        &mut self,
+        _: &mut OAuthRequest,
+        solicitation: Solicitation<'_>,
+    ) -> OwnerConsent<<OAuthRequest as WebRequest>::Response> {
+        if let Consent::Deny = self.consent {
+            return OwnerConsent::Denied;
+        }
+
+        let pre_grant = solicitation.pre_grant();
+        let client_id = match pre_grant.client_id.parse::<AuthClient>() {
+            Ok(client) => client.id,
+            Err(err) => return map_err(err),
+        };
+        let res = self
+            .db
+            .update_client_scope(
+                self.user.user_id,
+                client_id,
+                pre_grant.scope.clone(),
+                ConsentSource::User,
+                self.now,
+            )
+            .await;
+        if let Err(err) = res {
+            tracing::error!(
+                "failed to record consent of user {} to client {}: {}",
+                self.user.username,
+                client_id,
+                err
+            );
+            return map_err(err);
+        }
+
+        OwnerConsent::Authorized(self.user.to_string())
+    }
+}
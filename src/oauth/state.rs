@@ -1,40 +1,106 @@
-use oxide_auth::{
-    frontends::simple::endpoint::Vacant,
-    primitives::{authorizer::AuthMap, generator::RandomGenerator, issuer::TokenMap},
-};
+use chrono::{DateTime, Utc};
+use oxide_auth::{frontends::simple::endpoint::Vacant, primitives::generator::RandomGenerator};
 use oxide_auth_async::primitives;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::MutexGuard;
 
 use super::endpoint::{extension::Empty, Endpoint};
-use crate::oauth::database::Database;
+use crate::oauth::{
+    database::{resource::user::AuthUser, Database, StoreError},
+    maintenance::PurgeCounts,
+    models::ClientId,
+    primitives::{
+        CheckedClient, CheckedRegistrar, CodeAuthorizer, CodeMap, Lifetimes, OwnerTokenMap,
+        UsageIssuer,
+    },
+    settings::TokenLifetimes,
+};
 
 #[derive(Clone, axum_macros::FromRef)]
 pub struct State {
     registrar: Database,
-    authorizer: Arc<Mutex<AuthMap<RandomGenerator>>>,
-    issuer: Arc<Mutex<TokenMap<RandomGenerator>>>,
+    authorizer: Arc<CodeMap>,
+    issuer: Arc<OwnerTokenMap>,
+    lifetimes: Arc<TokenLifetimes>,
 }
 
 impl State {
-    pub fn new(registrar: Database) -> Self {
+    pub fn new(registrar: Database, lifetimes: TokenLifetimes) -> Self {
         State {
             registrar,
-            authorizer: Arc::new(Mutex::new(AuthMap::new(RandomGenerator::new(16)))),
-            issuer: Arc::new(Mutex::new(TokenMap::new(RandomGenerator::new(16)))),
+            lifetimes: Arc::new(lifetimes),
+            authorizer: Arc::new(CodeMap::new(RandomGenerator::new(16))),
+            issuer: Arc::new(OwnerTokenMap::new(RandomGenerator::new(16))),
         }
     }
 
-    pub async fn endpoint(
-        &self,
-    ) -> Endpoint<'_, impl primitives::Registrar, Empty, Vacant, Vacant> {
+    /// An endpoint for a request. The authorizer and issuer are shared by every endpoint, and
+    /// lock only the entries a request uses. Codes and tokens get the lifetimes of their client.
+    pub fn endpoint(&self) -> Endpoint<'_, impl primitives::Registrar, Empty, Vacant, Vacant> {
         Endpoint {
             registrar: &self.registrar,
-            authorizer: self.authorizer.lock().await.into(),
-            issuer: self.issuer.lock().await.into(),
+            authorizer: CodeAuthorizer::new(&self.authorizer, self.lifetimes()),
+            issuer: UsageIssuer::new(&self.issuer, &self.registrar, self.lifetimes()),
             extension: Empty,
             solicitor: Vacant,
             scopes: Vacant,
         }
     }
+
+    /// The registrar for a request whose client credentials were `checked` already.
+    pub fn registrar(&self, checked: Option<CheckedClient>) -> CheckedRegistrar<'_> {
+        CheckedRegistrar::new(&self.registrar, checked)
+    }
+
+    fn lifetimes(&self) -> Lifetimes<'_> {
+        Lifetimes::new(&self.lifetimes, &self.registrar)
+    }
+
+    /// Revoke the owner's codes that weren't exchanged yet, and the access and refresh tokens
+    /// issued for them, except for the grant of the access token `except`.
+    pub fn revoke_tokens(&self, owner: &AuthUser, except: Option<&str>) {
+        let owner_id = owner.to_string();
+        self.authorizer.revoke_owner(&owner_id);
+        self.issuer.revoke_owner(&owner_id, except);
+    }
+
+    /// Remove the owner's authorization of the client and revoke the codes and tokens issued to it
+    /// for them.
+    pub async fn revoke_authorization(
+        &self,
+        owner: &AuthUser,
+        client_id: ClientId,
+    ) -> Result<(), StoreError> {
+        self.registrar
+            .revoke_client_authorization(owner.user_id, client_id)
+            .await?;
+        let (owner_id, client_id) = (owner.to_string(), client_id.to_string());
+        self.authorizer.revoke_client(&owner_id, &client_id);
+        self.issuer.revoke_client(&owner_id, &client_id);
+
+        Ok(())
+    }
+
+    /// Wait for other refreshes with the refresh token to finish, and keep them waiting until the
+    /// guard is dropped.
+    pub async fn lock_refresh(&self, refresh: &str) -> MutexGuard<'_, ()> {
+        self.issuer.lock_refresh(refresh).await
+    }
+
+    /// Remove the authorization codes, tokens and email tokens that expired by `now`.
+    pub async fn purge_expired(&self, now: DateTime<Utc>) -> PurgeCounts {
+        let (access_tokens, refresh_tokens) = self.issuer.purge_expired(now);
+        let email_tokens = self
+            .registrar
+            .purge_email_tokens(now.timestamp().max(0) as u64)
+            .await;
+
+        PurgeCounts {
+            codes: self.authorizer.purge_expired(now) as u64,
+            access_tokens: access_tokens as u64,
+            refresh_tokens: refresh_tokens as u64,
+            email_tokens: email_tokens as u64,
+            ..Default::default()
+        }
+    }
 }
@@ -1,28 +1,405 @@
 use askama::Template;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{request::Parts, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use oxide_auth::{endpoint::WebRequest, primitives::scope::Scope};
+use serde::Serialize;
 
-use oxide_auth::endpoint::WebRequest;
+use crate::oauth::{
+    database::{clientmap::ClientMetadata, ClientAuthorization, ConsentSource},
+    i18n::Locale,
+    scopes,
+    webauthn::Passkey,
+};
 
-#[derive(Template)]
+mod provider;
+
+pub use provider::{TemplateError, TemplateProvider};
+
+/// A page that can be replaced by a template in the configured template directory.
+pub trait Page: Template + Serialize {
+    /// The name of the template overriding this page.
+    const NAME: &'static str;
+}
+
+/// Renders pages in the locale negotiated for the request.
+#[derive(Clone)]
+pub struct Pages {
+    templates: TemplateProvider,
+    locale: Locale,
+}
+
+impl Pages {
+    pub fn locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    pub fn render_string<P: Page>(&self, page: &P) -> askama::Result<String> {
+        self.templates.render(&self.locale, page)
+    }
+
+    pub fn render<P: Page>(&self, page: &P) -> Response {
+        match self.render_string(page) {
+            Ok(body) => Html(body).into_response(),
+            Err(e) => {
+                tracing::error!("unable to render {}: {}", P::NAME, e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for Pages
+where
+    S: Send + Sync,
+    TemplateProvider: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            templates: TemplateProvider::from_ref(state),
+            locale: Locale::from_request_parts(parts, state).await?,
+        })
+    }
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "signin.html")]
 pub struct SignIn<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub query: &'a str,
+    pub csrf_token: &'a str,
+    /// Why the previous attempt failed.
+    pub error: Option<String>,
+    /// Sign-in is locked out after too many failed attempts.
+    pub locked: bool,
+}
+
+impl Page for SignIn<'_> {
+    const NAME: &'static str = "signin.html";
+}
+
+/// The second step of signing in, for owners with an authenticator.
+#[derive(Template, Serialize)]
+#[template(path = "signin_totp.html")]
+pub struct SignInTotp<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub query: &'a str,
+    pub csrf_token: &'a str,
+    pub error: Option<String>,
+    pub locked: bool,
+    /// The owner has passkeys, which can be used instead of a code.
+    pub passkeys: bool,
+}
+
+impl Page for SignInTotp<'_> {
+    const NAME: &'static str = "signin_totp.html";
+}
+
+/// Enrolling an authenticator, and the recovery codes once it's enrolled.
+#[derive(Template, Serialize)]
+#[template(path = "totp.html")]
+pub struct TotpEnrollment<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub csrf_token: &'a str,
+    pub enrolled: bool,
+    /// The enrollment URI as an SVG image.
+    pub qr_code: Option<String>,
+    /// The `otpauth://` enrollment URI.
+    pub url: Option<String>,
+    /// The base32 encoded secret, for entering it by hand.
+    pub secret: Option<String>,
+    /// Recovery codes that were just generated. They're only shown this once.
+    pub recovery_codes: Vec<String>,
+    pub remaining_recovery_codes: usize,
+    pub error: Option<String>,
+}
+
+impl Page for TotpEnrollment<'_> {
+    const NAME: &'static str = "totp.html";
+}
+
+/// The owner's passkeys, and registering another one.
+#[derive(Template, Serialize)]
+#[template(path = "passkeys.html")]
+pub struct PasskeyList<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub passkeys: Vec<PasskeyInfo>,
+    /// For the first request of the script, which gets the next token with each response.
+    pub csrf_token: &'a str,
+}
+
+impl Page for PasskeyList<'_> {
+    const NAME: &'static str = "passkeys.html";
+}
+
+/// A passkey as listed to its owner.
+#[derive(Debug, Serialize)]
+pub struct PasskeyInfo {
+    /// The credential ID, base64url encoded.
+    pub id: String,
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+}
+
+impl From<&Passkey> for PasskeyInfo {
+    fn from(passkey: &Passkey) -> Self {
+        Self {
+            id: passkey.id(),
+            name: passkey.name().to_owned(),
+            created: passkey.created(),
+        }
+    }
+}
+
+/// The clients the owner authorized, and disconnecting them.
+#[derive(Template, Serialize)]
+#[template(path = "apps.html")]
+pub struct ConnectedApps<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub csrf_token: &'a str,
+    pub apps: Vec<ConnectedApp>,
+}
+
+impl Page for ConnectedApps<'_> {
+    const NAME: &'static str = "apps.html";
+}
+
+/// An authorized client as listed to the owner.
+#[derive(Debug, Serialize)]
+pub struct ConnectedApp {
+    pub client_id: String,
+    pub name: String,
+    pub scopes: Vec<ConsentScope>,
+    /// The date the owner first authorized the client.
+    pub granted: String,
+    /// The date the client last used a token, if it did.
+    pub last_used: Option<String>,
+    /// The client is part of this service and was approved without asking the owner.
+    pub first_party: bool,
+}
+
+impl ConnectedApp {
+    pub fn new(i18n: &Locale, name: String, authorization: &ClientAuthorization) -> Self {
+        let mut scopes = authorization
+            .scope
+            .iter()
+            .map(|name| ConsentScope::new(i18n, name, false))
+            .collect::<Vec<_>>();
+        scopes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            client_id: authorization.client_id.to_string(),
+            name,
+            scopes,
+            granted: date(authorization.granted_at),
+            last_used: authorization.last_used_at.map(date),
+            first_party: authorization.source == ConsentSource::FirstParty,
+        }
+    }
+}
+
+/// The UTC date of a time in seconds since the Unix epoch, as `YYYY-MM-DD`.
+fn date(secs: u64) -> String {
+    // Civil from days, as in http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "signup.html")]
+pub struct SignUp<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
     pub query: &'a str,
+    pub csrf_token: &'a str,
+}
+
+impl Page for SignUp<'_> {
+    const NAME: &'static str = "signup.html";
+}
+
+/// A message with a heading, e.g. after following the link from an email.
+#[derive(Template, Serialize)]
+#[template(path = "notice.html")]
+pub struct Notice<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub heading: String,
+    pub message: String,
+    /// Where to go on from here.
+    pub link: Option<NoticeLink>,
+    /// Offer to send another verification email, with this CSRF token.
+    pub resend_csrf_token: Option<String>,
+}
+
+impl Page for Notice<'_> {
+    const NAME: &'static str = "notice.html";
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoticeLink {
+    pub href: String,
+    pub text: String,
+}
+
+/// Asking for a password reset link, and confirming that it was sent.
+#[derive(Template, Serialize)]
+#[template(path = "forgot_password.html")]
+pub struct ForgotPassword<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub csrf_token: &'a str,
+    pub sent: bool,
+}
+
+impl Page for ForgotPassword<'_> {
+    const NAME: &'static str = "forgot_password.html";
+}
+
+/// Choosing a new password with the token from a reset link.
+#[derive(Template, Serialize)]
+#[template(path = "reset_password.html")]
+pub struct ResetPassword<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub csrf_token: &'a str,
+    pub token: &'a str,
+    pub error: Option<String>,
+}
+
+impl Page for ResetPassword<'_> {
+    const NAME: &'static str = "reset_password.html";
 }
 
-#[derive(Template, Debug)]
+#[derive(Template, Serialize)]
+#[template(path = "signout.html")]
+pub struct SignOut<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub csrf_token: &'a str,
+}
+
+impl Page for SignOut<'_> {
+    const NAME: &'static str = "signout.html";
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "error.html")]
+pub struct ErrorPage<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
+    pub status: u16,
+    pub message: String,
+}
+
+impl Page for ErrorPage<'_> {
+    const NAME: &'static str = "error.html";
+}
+
+impl<'a> ErrorPage<'a> {
+    pub fn new(i18n: &'a Locale, status: StatusCode) -> Self {
+        let id = match status {
+            StatusCode::BAD_REQUEST => "error-bad-request",
+            StatusCode::UNAUTHORIZED => "error-unauthorized",
+            StatusCode::FORBIDDEN => "error-forbidden",
+            StatusCode::NOT_FOUND => "error-not-found",
+            StatusCode::CONFLICT => "error-conflict",
+            StatusCode::UNPROCESSABLE_ENTITY => "error-invalid-input",
+            status if status.is_client_error() => "error-bad-request",
+            _ => "error-internal",
+        };
+
+        Self {
+            i18n,
+            status: status.as_u16(),
+            message: i18n.t(id),
+        }
+    }
+}
+
+#[derive(Template, Serialize, Debug)]
 #[template(path = "authorize.html")]
 pub struct Authorize<'a> {
+    #[serde(skip)]
+    pub i18n: &'a Locale,
     pub query: String,
     pub client_name: &'a str,
+    pub client: &'a ClientMetadata,
     pub username: &'a str,
-    pub scopes: String,
+    pub scopes: Vec<ConsentScope>,
+    pub csrf_token: &'a str,
+}
+
+impl Page for Authorize<'_> {
+    const NAME: &'static str = "authorize.html";
+}
+
+/// A requested scope as presented on the consent form.
+#[derive(Serialize, Debug)]
+pub struct ConsentScope {
+    pub name: String,
+    pub description: String,
+    pub warning: Option<String>,
+    /// Required scopes can't be deselected by the owner.
+    pub required: bool,
+}
+
+impl ConsentScope {
+    fn new(i18n: &Locale, name: &str, required: bool) -> Self {
+        let info = scopes::lookup(name);
+        let description = i18n
+            .scope(name, false)
+            .unwrap_or_else(|| info.map_or(name, |info| info.description).to_owned());
+        // Only scopes marked sensitive in the registry get a warning
+        let warning = info
+            .and_then(|info| info.warning)
+            .map(|warning| i18n.scope(name, true).unwrap_or_else(|| warning.to_owned()));
+
+        Self {
+            name: name.to_owned(),
+            description,
+            warning,
+            required,
+        }
+    }
 }
 
 impl<'a> Authorize<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        i18n: &'a Locale,
         req: &mut oxide_auth_axum::OAuthRequest,
         solicitation: &oxide_auth::endpoint::Solicitation<'a>,
         username: &'a str,
         client_name: &'a str,
+        client: &'a ClientMetadata,
+        required: &Scope,
+        csrf_token: &'a str,
     ) -> Self {
         tracing::debug!(
             "in Authorize::new()\nusername: {:?}, client name: {:?}\nRequest: {:?}",
@@ -57,12 +434,22 @@ impl<'a> Authorize<'a> {
         }
 
         let query = serde_urlencoded::to_string(extra).unwrap();
+        let required = required.iter().collect::<Vec<_>>();
+        let mut scopes = grant
+            .scope
+            .iter()
+            .map(|name| ConsentScope::new(i18n, name, required.contains(&name)))
+            .collect::<Vec<_>>();
+        scopes.sort_by(|a, b| a.name.cmp(&b.name));
 
         Self {
+            i18n,
             query,
             client_name,
+            client,
             username,
-            scopes: grant.scope.iter().collect::<Vec<_>>().join(", "),
+            scopes,
+            csrf_token,
         }
     }
 }
@@ -0,0 +1,229 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use minijinja::{
+    path_loader,
+    value::{from_args, Kwargs, Object},
+    Environment, ErrorKind, Value,
+};
+
+use super::Page;
+use crate::oauth::{i18n::Locale, settings::TemplateSettings};
+
+/// Renders the pages of the authorization server.
+///
+/// Pages are rendered with the templates compiled into the binary unless the configured template
+/// directory has a Jinja template of the same name. The override is rendered with the same fields
+/// as the built-in template, and with an `i18n` object whose `lang()`, `t(id)`, `fmt(id, **args)`
+/// and `html(id, **args)` methods work like those of [`Locale`].
+#[derive(Clone, Debug, Default)]
+pub struct TemplateProvider {
+    source: Arc<Source>,
+}
+
+#[derive(Debug, Default)]
+enum Source {
+    #[default]
+    Compiled,
+    /// Overrides read and parsed at startup.
+    Loaded(Environment<'static>),
+    /// Overrides read from the directory for every page.
+    Reloading(PathBuf),
+}
+
+impl TemplateProvider {
+    pub fn new(settings: &TemplateSettings) -> Result<Self, TemplateError> {
+        let source = match &settings.directory {
+            None => Source::Compiled,
+            Some(directory) if settings.hot_reload => {
+                if !directory.is_dir() {
+                    return Err(TemplateError::NotADirectory(directory.clone()));
+                }
+                Source::Reloading(directory.clone())
+            }
+            Some(directory) => {
+                let mut env = Environment::new();
+                load_directory(&mut env, directory, directory)?;
+                Source::Loaded(env)
+            }
+        };
+
+        Ok(Self {
+            source: Arc::new(source),
+        })
+    }
+
+    /// Render a page. Overrides that fail to render are logged and replaced by the built-in
+    /// template.
+    pub fn render<P: Page>(&self, locale: &Locale, page: &P) -> askama::Result<String> {
+        match self.render_override(locale, page) {
+            Some(Ok(body)) => return Ok(body),
+            Some(Err(e)) => tracing::error!("unable to render template {}: {:#}", P::NAME, e),
+            None => (),
+        }
+
+        page.render()
+    }
+
+    fn render_override<P: Page>(
+        &self,
+        locale: &Locale,
+        page: &P,
+    ) -> Option<Result<String, minijinja::Error>> {
+        let reloaded;
+        let env = match &*self.source {
+            Source::Compiled => return None,
+            Source::Loaded(env) => env,
+            Source::Reloading(directory) => {
+                if !directory.join(P::NAME).is_file() {
+                    return None;
+                }
+                let mut env = Environment::new();
+                env.set_loader(path_loader(directory));
+                reloaded = env;
+                &reloaded
+            }
+        };
+
+        let template = match env.get_template(P::NAME) {
+            Ok(template) => template,
+            Err(e) if e.kind() == ErrorKind::TemplateNotFound => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        let context = minijinja::context! {
+            i18n => Value::from_object(LocaleObject(locale.clone())),
+            ..Value::from_serialize(page)
+        };
+
+        Some(template.render(context))
+    }
+}
+
+/// Extensions of the files that are loaded as templates. A theme may keep other files, such as
+/// images or notes, next to them.
+const TEMPLATE_EXTENSIONS: &[&str] = &["html", "txt"];
+
+/// Add every template below `directory` to the environment, named by its path relative to `root`.
+fn load_directory(
+    env: &mut Environment<'static>,
+    root: &Path,
+    directory: &Path,
+) -> Result<(), TemplateError> {
+    let io_error = |source| TemplateError::Io {
+        path: directory.to_owned(),
+        source,
+    };
+    for entry in std::fs::read_dir(directory).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_dir() {
+            load_directory(env, root, &path)?;
+            continue;
+        }
+        let is_template = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| TEMPLATE_EXTENSIONS.contains(&extension));
+        if !is_template {
+            tracing::debug!("skipping {}, which isn't a template", path.display());
+            continue;
+        }
+
+        let source = std::fs::read_to_string(&path).map_err(|source| TemplateError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let name = path
+            .strip_prefix(root)
+            .expect("path is below the template directory")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        tracing::debug!("loading template {}", name);
+        env.add_template_owned(name, source)
+            .map_err(|source| TemplateError::Syntax { path, source })?;
+    }
+
+    Ok(())
+}
+
+/// Exposes a [`Locale`] to Jinja templates.
+#[derive(Debug)]
+struct LocaleObject(Locale);
+
+impl Object for LocaleObject {
+    fn call_method(
+        self: &Arc<Self>,
+        _state: &minijinja::State<'_, '_>,
+        method: &str,
+        args: &[Value],
+    ) -> Result<Value, minijinja::Error> {
+        match method {
+            "lang" => {
+                let () = from_args(args)?;
+                Ok(Value::from(self.0.lang()))
+            }
+            "t" => {
+                let (id,): (&str,) = from_args(args)?;
+                Ok(Value::from(self.0.t(id)))
+            }
+            "fmt" | "html" => {
+                let (id, kwargs): (&str, Kwargs) = from_args(args)?;
+                let values = kwargs
+                    .args()
+                    .map(|name| Ok((name, kwargs.get::<Value>(name)?.to_string())))
+                    .collect::<Result<Vec<_>, minijinja::Error>>()?;
+                let values = values
+                    .iter()
+                    .map(|(name, value)| (*name, value.as_str()))
+                    .collect::<Vec<_>>();
+
+                if method == "fmt" {
+                    Ok(Value::from(self.0.fmt(id, &values)))
+                } else {
+                    Ok(Value::from_safe_string(self.0.html(id, &values)))
+                }
+            }
+            _ => Err(minijinja::Error::from(ErrorKind::UnknownMethod)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    NotADirectory(PathBuf),
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Syntax {
+        path: PathBuf,
+        source: minijinja::Error,
+    },
+}
+
+impl std::error::Error for TemplateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TemplateError::NotADirectory(_) => None,
+            TemplateError::Io { source, .. } => Some(source),
+            TemplateError::Syntax { source, .. } => Some(source),
+        }
+    }
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::NotADirectory(path) => {
+                write!(f, "{} is not a directory", path.display())
+            }
+            TemplateError::Io { path, .. } => write!(f, "unable to read {}", path.display()),
+            TemplateError::Syntax { path, .. } => {
+                write!(f, "invalid template {}", path.display())
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{request::Parts, Extensions, HeaderMap},
+};
+
+use crate::oauth::{
+    database::{Database, ThrottleKey},
+    settings::LockoutSettings,
+};
+
+/// Locks out sign-ins for accounts and client addresses with too many recent failures.
+///
+/// The failures are kept in the [`Database`], so every instance sharing it enforces the same
+/// lockouts.
+#[derive(Clone)]
+pub struct SignInThrottle {
+    db: Database,
+    settings: Arc<LockoutSettings>,
+}
+
+/// The failed sign-ins of an account.
+#[derive(Debug)]
+pub struct Lockout {
+    /// Failures within the window.
+    pub failures: usize,
+    pub locked_until: Option<SystemTime>,
+}
+
+impl SignInThrottle {
+    pub fn new(db: Database, settings: LockoutSettings) -> Self {
+        Self {
+            db,
+            settings: Arc::new(settings),
+        }
+    }
+
+    /// When sign-ins for this account, or from this address, are allowed again. `None` if they
+    /// aren't locked out.
+    pub async fn locked_until(
+        &self,
+        username: &str,
+        address: Option<IpAddr>,
+    ) -> Option<SystemTime> {
+        let account = self.lockout(&ThrottleKey::account(username)).await;
+        let address = match address {
+            Some(address) => self.lockout(&ThrottleKey::Address(address)).await,
+            None => None,
+        };
+
+        account.max(address)
+    }
+
+    pub async fn record_failure(&self, username: &str, address: Option<IpAddr>) {
+        let now = millis(SystemTime::now());
+        let account = ThrottleKey::account(username);
+        let keep = self.kept_failures(&account);
+        self.db.add_sign_in_failure(account, now, keep).await;
+        if let Some(address) = address {
+            let address = ThrottleKey::Address(address);
+            let keep = self.kept_failures(&address);
+            self.db.add_sign_in_failure(address, now, keep).await;
+        }
+    }
+
+    /// Count a request for a password reset link to `email`. Each one sends an email, so they're
+    /// throttled like failed sign-ins, per address and together with the sign-ins of the client.
+    /// Returns when another may be made if there were too many, and doesn't count it then.
+    pub async fn request_reset(&self, email: &str, address: Option<IpAddr>) -> Option<SystemTime> {
+        let key = ThrottleKey::email(email);
+        let address = address.map(ThrottleKey::Address);
+        let mut until = self.lockout(&key).await;
+        if let Some(address) = &address {
+            until = until.max(self.lockout(address).await);
+        }
+        if until.is_some() {
+            return until;
+        }
+
+        let now = millis(SystemTime::now());
+        let keep = self.kept_failures(&key);
+        self.db.add_sign_in_failure(key, now, keep).await;
+        if let Some(address) = address {
+            let keep = self.kept_failures(&address);
+            self.db.add_sign_in_failure(address, now, keep).await;
+        }
+
+        None
+    }
+
+    /// Forget the failures that are past the window, for accounts and addresses that weren't
+    /// looked up since. Returns how many were forgotten.
+    pub async fn purge_expired(&self) -> usize {
+        let before = SystemTime::now()
+            .checked_sub(self.settings.window)
+            .unwrap_or(UNIX_EPOCH);
+        self.db.purge_sign_in_failures(millis(before)).await
+    }
+
+    /// Forget the failures of an account after the owner signed in. The failures of the address
+    /// are kept, or signing into one account would reset the count for guessing at others.
+    pub async fn record_success(&self, username: &str) {
+        self.unlock(username).await;
+    }
+
+    pub async fn status(&self, username: &str) -> Lockout {
+        let key = ThrottleKey::account(username);
+        Lockout {
+            failures: self.failures(&key).await.len(),
+            locked_until: self.lockout(&key).await,
+        }
+    }
+
+    /// Lift the lockout of an account. Returns whether it had any failures.
+    pub async fn unlock(&self, username: &str) -> bool {
+        self.db
+            .clear_sign_in_failures(&ThrottleKey::account(username))
+            .await
+    }
+
+    async fn failures(&self, key: &ThrottleKey) -> Vec<u64> {
+        let since = SystemTime::now()
+            .checked_sub(self.settings.window)
+            .unwrap_or(UNIX_EPOCH);
+        self.db.get_sign_in_failures(key, millis(since)).await
+    }
+
+    fn threshold(&self, key: &ThrottleKey) -> usize {
+        match key {
+            ThrottleKey::Account(_) | ThrottleKey::Email(_) => self.settings.account_threshold,
+            ThrottleKey::Address(_) => self.settings.address_threshold,
+        }
+    }
+
+    /// How many failures of the key are kept: enough to reach the longest lockout, as more
+    /// wouldn't lock it out for longer. Usernames are the client's choice, so the failures of
+    /// a key mustn't grow without limit.
+    fn kept_failures(&self, key: &ThrottleKey) -> usize {
+        let mut doublings = 0;
+        let mut lockout = self.settings.base_lockout;
+        while lockout < self.settings.max_lockout && doublings < 32 {
+            lockout = lockout.saturating_mul(2);
+            doublings += 1;
+        }
+
+        self.threshold(key) + doublings + 1
+    }
+
+    async fn lockout(&self, key: &ThrottleKey) -> Option<SystemTime> {
+        let threshold = self.threshold(key);
+        let failures = self.failures(key).await;
+        let last = *failures.last()?;
+        let excess = failures.len().checked_sub(threshold)?;
+
+        // The lockout doubles with every failure past the threshold
+        let lockout = u32::try_from(excess)
+            .ok()
+            .and_then(|excess| 2u32.checked_pow(excess))
+            .and_then(|factor| self.settings.base_lockout.checked_mul(factor))
+            .map_or(self.settings.max_lockout, |lockout| {
+                lockout.min(self.settings.max_lockout)
+            });
+        let until = UNIX_EPOCH + Duration::from_millis(last) + lockout;
+
+        (until > SystemTime::now()).then_some(until)
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// The throttle for the account a client signs into and the address of the client.
+///
+/// The address is the peer address of the connection, or the first address in the
+/// `X-Forwarded-For` header if the server is configured to trust it.
+pub struct ClientThrottle {
+    throttle: SignInThrottle,
+    address: Option<IpAddr>,
+}
+
+impl ClientThrottle {
+    pub async fn locked_until(&self, username: &str) -> Option<SystemTime> {
+        self.throttle.locked_until(username, self.address).await
+    }
+
+    pub async fn record_failure(&self, username: &str) {
+        self.throttle.record_failure(username, self.address).await
+    }
+
+    pub async fn record_success(&self, username: &str) {
+        self.throttle.record_success(username).await
+    }
+
+    pub async fn request_reset(&self, email: &str) -> Option<SystemTime> {
+        self.throttle.request_reset(email, self.address).await
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ClientThrottle
+where
+    S: Send + Sync,
+    SignInThrottle: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let throttle = SignInThrottle::from_ref(state);
+        let address = client_address(
+            &parts.headers,
+            &parts.extensions,
+            throttle.settings.trust_forwarded_for,
+        );
+
+        Ok(Self { throttle, address })
+    }
+}
+
+/// The address of the client: the first address in the `X-Forwarded-For` header if it's trusted,
+/// or else the peer address of the connection.
+pub(crate) fn client_address(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_forwarded_for: bool,
+) -> Option<IpAddr> {
+    let forwarded = trust_forwarded_for
+        .then(|| headers.get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .and_then(|address| address.trim().parse().ok());
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+
+    forwarded.or(peer)
+}
@@ -0,0 +1,323 @@
+//! Passkeys: the registration and authentication ceremonies of WebAuthn, verified by
+//! `webauthn-rs`.
+//!
+//! Attestation isn't verified: registration asks for `none` attestation, so a passkey is trusted
+//! for the key it holds rather than for the authenticator that made it.
+
+use std::time::Duration;
+
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use url::Url;
+use webauthn_rs_core::{
+    proto::{
+        AuthenticationState, Credential, RegistrationState, ResidentKeyRequirement,
+        UserVerificationPolicy,
+    },
+    WebauthnCore,
+};
+
+use crate::oauth::{database::resource::user::AuthUser, settings::WebauthnSettings};
+
+/// What `navigator.credentials.get()` resolved with, as serialized by
+/// `PublicKeyCredential.toJSON()`.
+pub use webauthn_rs_core::proto::PublicKeyCredential as AuthenticationCredential;
+/// What `navigator.credentials.create()` resolved with, as serialized by
+/// `PublicKeyCredential.toJSON()`.
+pub use webauthn_rs_core::proto::RegisterPublicKeyCredential as RegistrationCredential;
+
+/// Seconds the owner has to complete a ceremony once it was started.
+pub const CEREMONY_LIFETIME: u64 = 5 * 60;
+
+/// Base64url as used by WebAuthn, which browsers may or may not pad.
+const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+pub fn encode(bytes: &[u8]) -> String {
+    BASE64URL.encode(bytes)
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    BASE64URL
+        .decode(value)
+        .map_err(|_| WebauthnError::Malformed("base64url"))
+}
+
+/// A credential registered by a user.
+#[derive(Clone, Debug)]
+pub struct Passkey {
+    credential: Credential,
+    name: String,
+    /// Seconds since the Unix epoch.
+    created: u64,
+}
+
+impl Passkey {
+    pub fn credential_id(&self) -> &[u8] {
+        self.credential.cred_id.as_slice()
+    }
+
+    /// The credential ID, base64url encoded.
+    pub fn id(&self) -> String {
+        encode(self.credential_id())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// The signature counter of the last assertion. Authenticators that don't count keep it at 0.
+    pub fn sign_count(&self) -> u32 {
+        self.credential.counter
+    }
+
+    pub(crate) fn set_sign_count(&mut self, sign_count: u32) {
+        self.credential.counter = sign_count;
+    }
+
+    /// Whether `sign_count` may follow the counter of the last assertion. A counter that didn't
+    /// go up hints at a cloned authenticator, unless the authenticator doesn't count at all.
+    pub fn accepts_sign_count(&self, sign_count: u32) -> bool {
+        (self.sign_count() == 0 && sign_count == 0) || sign_count > self.sign_count()
+    }
+}
+
+/// A ceremony that was started and waits for the browser's response.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Ceremony<S> {
+    state: S,
+    /// Seconds since the Unix epoch.
+    expires: u64,
+}
+
+impl<S> Ceremony<S> {
+    fn new(state: S, now: u64) -> Self {
+        Self {
+            state,
+            expires: now + CEREMONY_LIFETIME,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires <= now
+    }
+}
+
+pub type Registration = Ceremony<RegistrationState>;
+pub type Authentication = Ceremony<AuthenticationState>;
+
+/// Whether the authenticator has to verify the user, e.g. with a PIN or a fingerprint, in
+/// addition to checking that they are present.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UserVerification {
+    Required,
+    Preferred,
+}
+
+impl From<UserVerification> for UserVerificationPolicy {
+    fn from(user_verification: UserVerification) -> Self {
+        match user_verification {
+            UserVerification::Required => UserVerificationPolicy::Required,
+            UserVerification::Preferred => UserVerificationPolicy::Preferred,
+        }
+    }
+}
+
+/// The outcome of an authentication ceremony.
+#[derive(Debug)]
+pub struct Assertion {
+    pub sign_count: u32,
+    pub user_verified: bool,
+}
+
+/// Runs the ceremonies for the configured relying party.
+pub struct RelyingParty {
+    core: WebauthnCore,
+}
+
+impl RelyingParty {
+    /// Without configured origins, any https origin on the relying party ID or its subdomains is
+    /// allowed, and plain http for `localhost`.
+    pub fn new(settings: &WebauthnSettings) -> Self {
+        let relying_party = settings.relying_party_id.as_str();
+        let (origins, any) = match settings.origins.as_slice() {
+            [] => {
+                let schemes: &[&str] = match relying_party {
+                    "localhost" => &["https", "http"],
+                    _ => &["https"],
+                };
+                let origins = schemes
+                    .iter()
+                    .filter_map(|scheme| Url::parse(&format!("{scheme}://{relying_party}")).ok())
+                    .collect();
+                (origins, true)
+            }
+            origins => (origins.to_vec(), false),
+        };
+        let core = WebauthnCore::new_unsafe_experts_only(
+            &settings.relying_party_name,
+            relying_party,
+            origins,
+            Duration::from_secs(CEREMONY_LIFETIME),
+            Some(any),
+            Some(any),
+        );
+
+        Self { core }
+    }
+
+    /// Start registering a passkey for the user. Returns the `publicKey` options for
+    /// `navigator.credentials.create()`. The user's passkeys are excluded, so an authenticator
+    /// isn't registered twice.
+    pub fn start_registration(
+        &self,
+        user: &AuthUser,
+        existing: &[Passkey],
+        now: u64,
+    ) -> Result<(Json, Registration), WebauthnError> {
+        let excluded = existing
+            .iter()
+            .map(|passkey| passkey.credential.cred_id.clone())
+            .collect();
+        let builder = self
+            .core
+            .new_challenge_register_builder(
+                user_handle(user).as_bytes(),
+                &user.username,
+                &user.username,
+            )?
+            .user_verification_policy(UserVerification::Preferred.into())
+            .exclude_credentials(Some(excluded));
+        let (mut options, state) = self.core.generate_challenge_register(builder)?;
+        // Ask for a discoverable credential, which can sign in without a username, but don't
+        // insist on it
+        if let Some(selection) = options.public_key.authenticator_selection.as_mut() {
+            selection.resident_key = Some(ResidentKeyRequirement::Preferred);
+        }
+
+        Ok((to_json(&options.public_key)?, Ceremony::new(state, now)))
+    }
+
+    /// Verify the response to a registration ceremony and return the new passkey.
+    pub fn register(
+        &self,
+        ceremony: &Registration,
+        credential: &RegistrationCredential,
+        name: &str,
+        now: u64,
+    ) -> Result<Passkey, WebauthnError> {
+        let credential = self
+            .core
+            .register_credential(credential, &ceremony.state, None)?;
+
+        Ok(Passkey {
+            credential,
+            name: name.to_owned(),
+            created: now,
+        })
+    }
+
+    /// Start an authentication ceremony. Returns the `publicKey` options for
+    /// `navigator.credentials.get()`. Without `allowed` passkeys, the authenticator offers the
+    /// ones it has for this relying party.
+    pub fn start_authentication(
+        &self,
+        allowed: &[Passkey],
+        user_verification: UserVerification,
+        now: u64,
+    ) -> Result<(Json, Authentication), WebauthnError> {
+        let allowed = allowed
+            .iter()
+            .map(|passkey| passkey.credential.clone())
+            .collect();
+        let builder = self
+            .core
+            .new_challenge_authenticate_builder(allowed, Some(user_verification.into()))?;
+        let (options, state) = self.core.generate_challenge_authenticate(builder)?;
+
+        Ok((to_json(&options.public_key)?, Ceremony::new(state, now)))
+    }
+
+    /// Verify the response to an authentication ceremony with the passkey it names, which was
+    /// looked up by its credential ID.
+    pub fn authenticate(
+        &self,
+        ceremony: &Authentication,
+        credential: &AuthenticationCredential,
+        user: &AuthUser,
+        passkey: &Passkey,
+    ) -> Result<Assertion, WebauthnError> {
+        if let Some(user_handle) = &credential.response.user_handle {
+            if user_handle.as_slice() != self::user_handle(user).as_bytes() {
+                return Err(WebauthnError::UnknownCredential);
+            }
+        }
+        // Only the passkey it names can verify the response. A discoverable ceremony allowed any
+        // passkey, as the user wasn't known when it started
+        let mut state = ceremony.state.clone();
+        state.set_allowed_credentials(vec![passkey.credential.clone()]);
+        let result = self.core.authenticate_credential(credential, &state)?;
+
+        Ok(Assertion {
+            sign_count: result.counter(),
+            user_verified: result.user_verified(),
+        })
+    }
+}
+
+/// The opaque handle that authenticators store with a discoverable credential. It's the user ID,
+/// which unlike the username never changes.
+fn user_handle(user: &AuthUser) -> String {
+    user.user_id.to_string()
+}
+
+fn to_json<T: Serialize>(options: &T) -> Result<Json, WebauthnError> {
+    serde_json::to_value(options).map_err(|_| WebauthnError::Malformed("options"))
+}
+
+#[derive(Debug)]
+pub enum WebauthnError {
+    /// A field of the response couldn't be read, or has the wrong value.
+    Malformed(&'static str),
+    UnknownCredential,
+    /// `webauthn-rs` refused to start the ceremony, or rejected the response.
+    Ceremony(webauthn_rs_core::error::WebauthnError),
+}
+
+impl From<webauthn_rs_core::error::WebauthnError> for WebauthnError {
+    fn from(error: webauthn_rs_core::error::WebauthnError) -> Self {
+        WebauthnError::Ceremony(error)
+    }
+}
+
+impl std::fmt::Display for WebauthnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebauthnError::Malformed(field) => write!(f, "Malformed or invalid {field}"),
+            WebauthnError::UnknownCredential => write!(f, "Unknown credential"),
+            WebauthnError::Ceremony(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for WebauthnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WebauthnError::Ceremony(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -1,17 +1,26 @@
 use std::str::FromStr;
 
 use crate::oauth::{
-    database::{resource::user::AuthUser, Database},
+    database::{
+        resource::user::{AuthUser, HashedPassword},
+        ConsentSource, Database, StoreError, UserRecord,
+    },
     error::Error,
+    i18n::Locale,
     models::{ClientId, UserId},
+    policy::{FieldError, SignupPolicy},
     primitives::scopes::Grant,
-    scopes::{Account, Read, Write},
+    scopes::{Account, AccountDisconnect, Read, Write},
+    throttle::{ClientThrottle, SignInThrottle},
 };
 use axum::{
-    extract::{FromRef, State},
-    routing::get,
-    Json, Router,
+    extract::{FromRef, Path, State},
+    headers::{authorization::Bearer, Authorization},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router, TypedHeader,
 };
+use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 
 pub fn routes<S>() -> Router<S>
@@ -19,8 +28,17 @@ where
     crate::oauth::state::State: FromRef<S>,
     S: Send + Sync + 'static + Clone,
     Database: FromRef<S>,
+    SignInThrottle: FromRef<S>,
+    SignupPolicy: FromRef<S>,
 {
-    Router::new().route("/user", get(user).post(update_account_name))
+    Router::new()
+        .route(
+            "/user",
+            get(user).post(update_account_name).delete(delete_account),
+        )
+        .route("/user/password", post(change_password))
+        .route("/user/apps", get(connected_apps))
+        .route("/user/apps/:client_id", delete(disconnect_app))
 }
 
 #[derive(Debug, Serialize)]
@@ -35,6 +53,11 @@ pub struct UserInfo {
     pub login: String,
     pub name: String,
     pub authorized_clients: Vec<ClientInfo>,
+    /// How the owner signed in when they authorized the token (RFC 8176 methods).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amr: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
 }
 
 pub async fn user(
@@ -42,6 +65,7 @@ pub async fn user(
     grant: Grant<Read<Account>>,
 ) -> Result<Json<UserInfo>, Error> {
     tracing::debug!("enter -> user()");
+    let authentication = grant.authentication();
     let u = grant.grant.owner_id;
     let user_record = db
         .get_user_by_id(&AuthUser::from_str(&u).unwrap())
@@ -65,6 +89,8 @@ pub async fn user(
         login: user_record.username().unwrap(),
         name: user_record.given_name().unwrap(),
         authorized_clients: clients,
+        amr: authentication.as_ref().map(|a| a.amr.clone()),
+        acr: authentication.map(|a| a.acr),
     };
 
     Ok(Json(user_info))
@@ -96,3 +122,176 @@ async fn update_account_name(
 
     Ok(Json(res))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmPassword {
+    pub password: String,
+}
+
+/// The owner's record, if `password` is theirs. Wrong passwords count against the sign-in
+/// throttle, or a stolen token could be used to guess the password.
+async fn confirm_password(
+    db: &Database,
+    throttle: &ClientThrottle,
+    owner: &AuthUser,
+    password: &str,
+) -> Result<UserRecord, Error> {
+    if throttle.locked_until(&owner.username).await.is_some() {
+        return Err(Error::TooManyAttempts);
+    }
+    match db.authenticate(&owner.username, password).await {
+        Some(record) => Ok(record),
+        None => {
+            throttle.record_failure(&owner.username).await;
+            Err(Error::WrongPassword)
+        }
+    }
+}
+
+/// Refuse the request unless it comes from a first-party client. Credentials and the account itself
+/// are out of reach of other clients, even with account:write.
+async fn require_first_party(db: &Database, client_id: &str) -> Result<(), Error> {
+    let client_id = client_id
+        .parse::<ClientId>()
+        .map_err(|_| Error::InternalError)?;
+    let metadata = db
+        .get_client_metadata(client_id)
+        .await
+        .map_err(|e| Error::Database { source: e })?;
+    if !metadata.first_party {
+        return Err(Error::FirstPartyOnly);
+    }
+
+    Ok(())
+}
+
+/// Change the password. The owner is signed out of every session, and every token issued for
+/// them is revoked except for the one making the request.
+#[allow(clippy::too_many_arguments)]
+async fn change_password(
+    State(state): State<crate::oauth::state::State>,
+    State(db): State<Database>,
+    State(policy): State<SignupPolicy>,
+    throttle: ClientThrottle,
+    i18n: Locale,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    grant: Grant<Write<Account>>,
+    Json(form): Json<ChangePassword>,
+) -> Result<Json<MsgReply>, Error> {
+    tracing::debug!("enter -> change_password()");
+    require_first_party(&db, &grant.grant.client_id).await?;
+    let owner = AuthUser::from_str(&grant.grant.owner_id).map_err(|_| Error::InternalError)?;
+    confirm_password(&db, &throttle, &owner, &form.current_password).await?;
+    if let Err(violation) = policy.check_password(&form.new_password, &owner.username) {
+        let errors = vec![FieldError::new("new_password", violation, &i18n)];
+        return Err(Error::Validation { errors });
+    }
+
+    let password = HashedPassword::new(Secret::new(form.new_password))
+        .await
+        .map_err(|e| Error::Hash { source: e })?;
+    db.set_password(owner.user_id, password)
+        .await
+        .map_err(|e| Error::Database { source: e })?;
+    state.revoke_tokens(&owner, Some(bearer.token()));
+    tracing::info!("user {} changed their password", owner.username);
+
+    Ok(Json(MsgReply { success: true }))
+}
+
+/// Delete the account, along with the owner's authorizations and every token issued for them.
+async fn delete_account(
+    State(state): State<crate::oauth::state::State>,
+    State(db): State<Database>,
+    throttle: ClientThrottle,
+    grant: Grant<Write<Account>>,
+    Json(form): Json<ConfirmPassword>,
+) -> Result<StatusCode, Error> {
+    tracing::debug!("enter -> delete_account()");
+    require_first_party(&db, &grant.grant.client_id).await?;
+    let owner = AuthUser::from_str(&grant.grant.owner_id).map_err(|_| Error::InternalError)?;
+    confirm_password(&db, &throttle, &owner, &form.password).await?;
+
+    state.revoke_tokens(&owner, None);
+    db.delete_user(owner.user_id)
+        .await
+        .map_err(|e| Error::Database { source: e })?;
+    tracing::info!("user {} deleted their account", owner.username);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A client the owner authorized.
+#[derive(Debug, Serialize)]
+pub struct ConnectedApp {
+    pub id: ClientId,
+    pub name: String,
+    pub scope: Vec<String>,
+    pub source: ConsentSource,
+    /// Seconds since the Unix epoch.
+    pub granted_at: u64,
+    pub updated_at: u64,
+    pub last_used_at: Option<u64>,
+}
+
+async fn connected_apps(
+    State(db): State<Database>,
+    grant: Grant<Read<Account>>,
+) -> Result<Json<Vec<ConnectedApp>>, Error> {
+    let owner = AuthUser::from_str(&grant.grant.owner_id).map_err(|_| Error::InternalError)?;
+    let record = db
+        .get_user_by_id(&owner)
+        .await
+        .map_err(|e| Error::Database { source: e })?;
+    let mut apps = Vec::new();
+    for authorization in record.get_authorized_clients() {
+        let name = db
+            .get_client_name(authorization.client_id)
+            .await
+            .map_err(|e| Error::Database { source: e })?;
+        let mut scope = authorization
+            .scope
+            .iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        scope.sort();
+        apps.push(ConnectedApp {
+            id: authorization.client_id,
+            name: name.inner,
+            scope,
+            source: authorization.source,
+            granted_at: authorization.granted_at,
+            updated_at: authorization.updated_at,
+            last_used_at: authorization.last_used_at,
+        });
+    }
+
+    Ok(Json(apps))
+}
+
+/// Disconnect an app: remove its authorization and revoke the tokens it has for the owner. It takes
+/// a scope of its own, so that apps allowed to edit the profile can't cut off the others.
+async fn disconnect_app(
+    State(state): State<crate::oauth::state::State>,
+    grant: Grant<AccountDisconnect>,
+    Path(client_id): Path<String>,
+) -> Result<StatusCode, Error> {
+    let owner = AuthUser::from_str(&grant.grant.owner_id).map_err(|_| Error::InternalError)?;
+    let client_id = client_id.parse::<ClientId>().map_err(|_| Error::NotFound)?;
+    state
+        .revoke_authorization(&owner, client_id)
+        .await
+        .map_err(|e| match e {
+            StoreError::DoesNotExist => Error::NotFound,
+            e => Error::Database { source: e },
+        })?;
+    tracing::info!("user {} disconnected client {}", owner.username, client_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
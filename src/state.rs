@@ -1,10 +1,22 @@
+use std::sync::Arc;
+
 use async_session::MemoryStore;
 
-use crate::oauth::{database::Database, state::State as AuthState};
+use crate::oauth::{
+    database::Database, mailer::Mailer, maintenance::MaintenanceMetrics, policy::SignupPolicy,
+    settings::Settings, state::State as AuthState, templates::TemplateProvider,
+    throttle::SignInThrottle,
+};
 
 #[derive(Clone, axum_macros::FromRef)]
 pub struct AppState {
     pub sessions: MemoryStore,
     pub state: AuthState,
     pub database: Database,
+    pub templates: TemplateProvider,
+    pub throttle: SignInThrottle,
+    pub mailer: Arc<dyn Mailer>,
+    pub signup_policy: SignupPolicy,
+    pub settings: Arc<Settings>,
+    pub maintenance: Arc<MaintenanceMetrics>,
 }
@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::settings::Settings;
+
+use crate::{
+    helpers::{
+        assert_is_redirect_to, csrf_token, spawn_app_with_settings, ClientResponse, ClientType,
+        TestState,
+    },
+    user::{authorized_state, get_user, refresh},
+};
+
+async fn get_apps(state: &TestState, token: &str) -> serde_json::Value {
+    let response = state
+        .api_client
+        .get(format!("{}/api/user/apps", &state.app_address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("request to client api failed");
+    assert_eq!(response.status().as_u16(), 200);
+
+    response.json().await.unwrap()
+}
+
+async fn disconnect(state: &TestState, token: &str, client_id: &str) -> reqwest::Response {
+    state
+        .api_client
+        .delete(format!(
+            "{}/api/user/apps/{}",
+            &state.app_address, client_id
+        ))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("request to client api failed")
+}
+
+/// Authorize another client for the signed in owner, one that may disconnect apps, returning it
+/// with its access token.
+pub async fn authorize_another_client(state: &mut TestState) -> (ClientResponse, String) {
+    // The consent flow of the helpers expects the same name
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state
+        .authorization_flow_with_scope(
+            &client,
+            "account:read account:write account:disconnect offline_access",
+        )
+        .await;
+    let token = state.token.access_token.clone().unwrap();
+
+    (client, token)
+}
+
+#[tokio::test]
+async fn connected_apps_list_their_scopes_and_use() {
+    // Arrange
+    let (state, client) = authorized_state().await;
+    let token = state.token.access_token.clone().unwrap();
+
+    // Act
+    let apps = get_apps(&state, &token).await;
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/account/apps", &state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    let apps = apps.as_array().unwrap();
+    assert_eq!(apps.len(), 1);
+    assert_eq!(apps[0]["id"], client.client_id.as_str());
+    assert_eq!(apps[0]["name"], "foo client");
+    assert_eq!(
+        apps[0]["scope"],
+        serde_json::json!(["account:read", "account:write", "offline_access"])
+    );
+    assert!(apps[0]["granted_at"].as_u64().unwrap() > 0);
+    assert!(
+        apps[0]["last_used_at"].as_u64().is_some(),
+        "Listing the apps used the token"
+    );
+    assert_eq!(page.status().as_u16(), 200);
+    let page = page.text().await.unwrap();
+    assert!(page.contains("foo client"), "{}", page);
+    assert!(page.contains(&format!(
+        r#"action="/oauth/account/apps/{}/revoke""#,
+        client.client_id
+    )));
+}
+
+#[tokio::test]
+async fn disconnecting_an_app_revokes_only_its_tokens() {
+    // Arrange
+    let (mut state, client) = authorized_state().await;
+    let revoked = state.token.clone();
+    let (other, token) = authorize_another_client(&mut state).await;
+
+    // Act
+    let response = disconnect(&state, &token, &client.client_id).await;
+    let again = disconnect(&state, &token, &client.client_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(again.status().as_u16(), 404);
+    assert_eq!(
+        get_user(&state, revoked.access_token.as_deref().unwrap())
+            .await
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_ne!(
+        refresh(&state, &client, &revoked).await.status().as_u16(),
+        200
+    );
+    let apps = get_apps(&state, &token).await;
+    assert_eq!(
+        apps.as_array().unwrap().len(),
+        1,
+        "The other app stays connected"
+    );
+    assert_eq!(apps[0]["id"], other.client_id.as_str());
+}
+
+#[tokio::test]
+async fn disconnecting_an_app_takes_its_own_scope() {
+    // Arrange
+    let (mut state, client) = authorized_state().await;
+    let account_write = state.token.access_token.clone().unwrap();
+    let (other, _) = authorize_another_client(&mut state).await;
+
+    // Act
+    let response = disconnect(&state, &account_write, &other.client_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let challenge = response.headers()["www-authenticate"].to_str().unwrap();
+    assert!(challenge.contains("insufficient_scope"), "{challenge}");
+    let apps = get_apps(&state, &account_write).await;
+    assert_eq!(
+        apps.as_array().unwrap().len(),
+        2,
+        "An account:write token disconnects nothing"
+    );
+    assert_eq!(apps[0]["id"], client.client_id.as_str());
+}
+
+#[tokio::test]
+async fn disconnecting_an_app_revokes_its_pending_codes() {
+    // Arrange
+    let (mut state, _) = authorized_state().await;
+    let (_, token) = authorize_another_client(&mut state).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let other = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    let (code, verifier) = state.authorization_code(&other, "account:read").await;
+
+    // Act
+    let response = disconnect(&state, &token, &other.client_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let response = state.exchange_code(&other, &code, &verifier).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+}
+
+#[tokio::test]
+async fn apps_can_be_disconnected_from_the_page() {
+    // Arrange
+    let (state, client) = authorized_state().await;
+    let token = state.token.access_token.clone().unwrap();
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/account/apps", &state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    let form = serde_json::json!({ "csrf_token": csrf_token(&page) });
+
+    // Act
+    let response = state
+        .api_client
+        .post(format!(
+            "{}/oauth/account/apps/{}/revoke",
+            &state.app_address, client.client_id
+        ))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_is_redirect_to(&response, 303, "/oauth/account/apps", false);
+    assert_eq!(get_user(&state, &token).await.status().as_u16(), 401);
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/account/apps", &state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(!page.contains("foo client"), "{}", page);
+}
+
+#[tokio::test]
+async fn authorizations_record_consent_and_token_use() {
+    // Arrange
+    let (state, _) = authorized_state().await;
+
+    // Act
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/account/apps", &state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    let apps = get_apps(&state, state.token.access_token.as_deref().unwrap()).await;
+
+    // Assert
+    assert!(
+        page.contains("last used on") && !page.contains("not used yet"),
+        "Getting tokens is a use: {}",
+        page
+    );
+    assert_eq!(apps[0]["source"], "user");
+    assert!(apps[0]["updated_at"].as_u64() >= apps[0]["granted_at"].as_u64());
+}
+
+#[tokio::test]
+async fn expired_consent_is_asked_for_again() {
+    // Arrange
+    let mut state = spawn_app_with_settings(Settings {
+        consent_lifetime: Some(Duration::ZERO),
+        ..Default::default()
+    })
+    .await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+    state.authorization_flow(&client).await;
+
+    // Act
+    // The flow fails unless it's shown the consent page again
+    state.authorization_flow(&client).await;
+
+    // Assert
+    assert!(state.token.access_token.is_some());
+}
@@ -1,8 +1,9 @@
 use csrf::CsrfToken;
 use serde::Serialize;
 
-use crate::helpers::{spawn_app, ClientResponse, ClientType, Token};
+use crate::helpers::{assert_is_redirect_to, spawn_app, ClientResponse, ClientType, Token};
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize)]
 struct AuthorizationQuery {
     client_id: String,
@@ -46,13 +47,22 @@ pub async fn register_client_form_errors() {
             }),
             "missing client type",
         ),
+        (
+            serde_json::json!({
+                "name": "foo client",
+                "redirect_uri": "https://foo/authorized",
+                "type": "public",
+                "homepage_uri": "javascript:alert(1)",
+            }),
+            "homepage is not an http(s) link",
+        ),
         (serde_json::json!({}), "all fields missing"),
     ];
 
     for (case, msg) in invalid_cases {
         // Act
         let response = client
-            .post(&format!("{}/oauth/client", &state.app_address))
+            .post(format!("{}/oauth/client", &state.app_address))
             .form(&case)
             .send()
             .await
@@ -81,7 +91,7 @@ pub async fn happy_path_register_client_confidential() {
     // Act
     let response = state
         .api_client
-        .post(&format!("{}/oauth/client", &state.app_address))
+        .post(format!("{}/oauth/client", &state.app_address))
         .form(&params)
         .send()
         .await
@@ -131,7 +141,7 @@ pub async fn happy_path_register_client_public() {
     // Act
     let response = state
         .api_client
-        .post(&format!("{}/oauth/client", &state.app_address))
+        .post(format!("{}/oauth/client", &state.app_address))
         .form(&form)
         .send()
         .await
@@ -182,7 +192,7 @@ pub async fn happy_path_confidential_client_authorization_flow() {
         "response_type": "code",
         "redirect_uri": "http://localhost:3001/endpoint",
         "client_id": res.client_id.clone(),
-        "scope": "account:read account:write account:follow",
+        "scope": "account:read account:write account:follow offline_access",
         "code_challenge": code_challenge,
         "code_challenge_method": "S256",
         "state": csrf_token,
@@ -191,10 +201,12 @@ pub async fn happy_path_confidential_client_authorization_flow() {
     // Act - 1
     let body = state.get_consent_prompt_confidential(&query).await;
     let consent_response = state.owner_consent_allow(&body).await;
+    let approved = state.consent_scopes(&body);
     let authorization_code = state
         .capture_authorizer_redirect(
             &res,
             &consent_response,
+            &approved,
             ClientType::Confidential,
             &csrf_token,
         )
@@ -256,17 +268,24 @@ pub async fn happy_path_public_client_authorization_flow() {
         "response_type": "code",
         "redirect_uri": "http://localhost:3001/endpoint",
         "client_id": res.client_id.clone(),
-        "scope": "account:read account:write",
+        "scope": "account:read account:write offline_access",
         "code_challenge": code_challenge,
         "code_challenge_method": "S256",
         "state": csrf_token,
     });
 
     // Act - 1
-    let body = state.get_consent_prompt_public(&&query).await;
+    let body = state.get_consent_prompt_public(&query).await;
     let consent_response = state.owner_consent_allow(&body).await;
+    let approved = state.consent_scopes(&body);
     let authorization_code = state
-        .capture_authorizer_redirect(&res, &consent_response, ClientType::Public, &csrf_token)
+        .capture_authorizer_redirect(
+            &res,
+            &consent_response,
+            &approved,
+            ClientType::Public,
+            &csrf_token,
+        )
         .await;
 
     // Arrange - 2
@@ -307,6 +326,207 @@ pub async fn happy_path_public_client_authorization_flow() {
         .await;
 }
 
+#[tokio::test]
+pub async fn consent_to_subset_of_requested_scopes() {
+    // Arrange - 1
+    let state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    state.signin("bob", "secret").await;
+    let res = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+
+    let code_verifier = pkce::code_verifier(128);
+    let code_challenge = pkce::code_challenge(&code_verifier);
+    let csrf_token = CsrfToken::new(nanoid::nanoid!().into_bytes()).b64_string();
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read account:write",
+        "code_challenge": code_challenge,
+        "code_challenge_method": "S256",
+        "state": csrf_token,
+    });
+
+    // Act - 1
+    let body = state.get_consent_prompt_confidential(&query).await;
+    assert_eq!(
+        state.consent_scopes(&body),
+        vec!["account:read", "account:write"],
+        "Consent page has a checkbox for each requested scope"
+    );
+    let consent_response = state.owner_consent_allow(&body).await;
+    let authorization_code = state
+        .capture_authorizer_redirect(
+            &res,
+            &consent_response,
+            &["account:read".to_string()],
+            ClientType::Confidential,
+            &csrf_token,
+        )
+        .await;
+
+    // Act - 2
+    let cv = String::from_utf8_lossy(&code_verifier);
+    let params = vec![
+        ("grant_type", "authorization_code"),
+        ("redirect_uri", "http://localhost:3001/endpoint"),
+        ("code", &authorization_code),
+        ("code_verifier", &cv),
+    ];
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/token", state.app_address))
+        .basic_auth(res.client_id.clone(), res.client_secret.clone())
+        .form(&params)
+        .send()
+        .await
+        .expect("failed to get response from api client");
+
+    // Assert - 2
+    assert_eq!(response.status().as_u16(), 200, "Token request succeeds");
+    let token: Token = response.json().await.expect("failed to parse token");
+    assert_eq!(
+        token.scope, "account:read",
+        "Token scope is narrowed to the approved scopes"
+    );
+    let access_token = token.access_token.unwrap();
+    let json_user = r#""login":"bob","name":"Robert","authorized_clients":"#;
+    state
+        .access_resource_success(&access_token, json_user)
+        .await;
+    let response = state
+        .api_client
+        .post(format!("{}/api/user", state.app_address))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({ "given_name": "Bobby" }))
+        .send()
+        .await
+        .expect("request to client api failed");
+    assert_ne!(
+        response.status().as_u16(),
+        200,
+        "Token can't be used for a scope the owner didn't approve"
+    );
+
+    // Assert - 3: the stored authorization only covers account:read, so asking for both prompts again
+    state.get_consent_prompt_confidential(&query).await;
+}
+
+#[tokio::test]
+pub async fn consent_is_recorded_when_first_given() {
+    // Arrange
+    let state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    state.signin("bob", "secret").await;
+    let res = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+
+    let code_verifier = pkce::code_verifier(128);
+    let code_challenge = pkce::code_challenge(&code_verifier);
+    let csrf_token = CsrfToken::new(nanoid::nanoid!().into_bytes()).b64_string();
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read",
+        "code_challenge": code_challenge,
+        "code_challenge_method": "S256",
+        "state": csrf_token,
+    });
+
+    // Act - 1
+    let body = state.get_consent_prompt_confidential(&query).await;
+    let consent_response = state.owner_consent_allow(&body).await;
+    state
+        .capture_authorizer_redirect(
+            &res,
+            &consent_response,
+            &["account:read".to_string()],
+            ClientType::Confidential,
+            &csrf_token,
+        )
+        .await;
+
+    // Act - 2
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .query(&query)
+        .send()
+        .await
+        .expect("failed to get response from api client");
+
+    // Assert
+    assert_is_redirect_to(&response, 302, "http://localhost:3001/endpoint?code=", true);
+}
+
+#[tokio::test]
+pub async fn consent_page_describes_scopes_and_client() {
+    // Arrange
+    let state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+        "logo_uri": "https://foo.example/logo.png",
+        "homepage_uri": "https://foo.example/",
+        "policy_uri": "https://foo.example/privacy",
+    });
+    let res = state.register_client(&params, ClientType::Public).await;
+    state.signin("bob", "secret").await;
+    let code_verifier = pkce::code_verifier(128);
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read account:write",
+        "code_challenge": pkce::code_challenge(&code_verifier),
+        "code_challenge_method": "S256",
+    });
+
+    // Act
+    let body = state.get_consent_prompt_public(&query).await;
+
+    // Assert
+    for (needle, msg) in [
+        (
+            "View your username, your name and the apps connected to your account",
+            "description of account:read",
+        ),
+        (
+            "Change the name on your account",
+            "description of account:write",
+        ),
+        (
+            "The app will be able to change your profile without asking you again.",
+            "warning for account:write",
+        ),
+        (r#"<img src="https://foo.example/logo.png""#, "client logo"),
+        (r#"<a href="https://foo.example/""#, "client homepage"),
+        (
+            r#"<a href="https://foo.example/privacy""#,
+            "client privacy policy",
+        ),
+        (
+            "This application has not been verified",
+            "verification status",
+        ),
+    ] {
+        assert!(body.contains(needle), "Consent page shows the {}", msg);
+    }
+}
+
 #[tokio::test]
 #[ignore]
 pub async fn happy_path_client_credentials_authorization_flow() {
@@ -352,7 +572,7 @@ pub async fn happy_path_client_credentials_authorization_flow() {
         assert!(token.scope.contains(s), "Token scope includes {}", s);
     }
     assert!(
-        !token.access_token.is_none(),
+        token.access_token.is_some(),
         "Access token contains a value"
     );
     assert!(
@@ -365,3 +585,147 @@ pub async fn happy_path_client_credentials_authorization_flow() {
         .access_resource_success(&token.access_token.unwrap(), res.client_id.clone().as_str())
         .await;
 }
+
+#[tokio::test]
+pub async fn consent_page_follows_ui_locales() {
+    // Arrange
+    let state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+    });
+    let res = state.register_client(&params, ClientType::Public).await;
+    state.signin("bob", "secret").await;
+    let code_verifier = pkce::code_verifier(128);
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read account:write",
+        "code_challenge": pkce::code_challenge(&code_verifier),
+        "code_challenge_method": "S256",
+        "ui_locales": "fr-CA en",
+    });
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .header("Accept-Language", "de")
+        .query(&query)
+        .send()
+        .await
+        .expect("failed to get response from api client");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    for (needle, msg) in [
+        (r#"<html lang="fr""#, "page language"),
+        ("Autoriser foo client", "heading"),
+        (
+            "Voir votre nom d&#x27;utilisateur",
+            "description of account:read",
+        ),
+        (
+            "L&#x27;application pourra modifier votre profil",
+            "warning for account:write",
+        ),
+    ] {
+        assert!(body.contains(needle), "ui_locales selects the {}", msg);
+    }
+}
+
+#[tokio::test]
+pub async fn authorize_error_page_is_localized() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": "unknown",
+        "scope": "account:read",
+    });
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .header("Accept-Language", "de")
+        .query(&query)
+        .send()
+        .await
+        .expect("failed to get response from api client");
+
+    // Assert
+    let status = response.status();
+    let body = response.text().await.unwrap();
+    assert!(!status.is_success(), "Unknown client is rejected");
+    assert!(
+        body.contains("<h1>Fehler"),
+        "Error page is translated: {}",
+        body
+    );
+}
+
+#[tokio::test]
+pub async fn consent_requires_csrf_token() {
+    // Arrange
+    let state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+    });
+    let res = state.register_client(&params, ClientType::Public).await;
+    state.signin("bob", "secret").await;
+    let code_verifier = pkce::code_verifier(128);
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read",
+        "code_challenge": pkce::code_challenge(&code_verifier),
+        "code_challenge_method": "S256",
+        "state": "xyz",
+    });
+    let body = state.get_consent_prompt_public(&query).await;
+    let consent = state.owner_consent_allow(&body).await;
+
+    for (token, msg) in [("", "missing token"), ("forged", "unknown token")] {
+        // Act
+        let response = state
+            .api_client
+            .post(&consent.action)
+            .form(&[("account:read", "on"), ("csrf_token", token)])
+            .send()
+            .await
+            .expect("failed to get response from api client");
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "Consent with {} is rejected",
+            msg
+        );
+        assert!(
+            response.headers().get("Location").is_none(),
+            "No authorization code is issued for consent with {}",
+            msg
+        );
+    }
+
+    // The token issued with the consent page is still valid
+    state
+        .capture_authorizer_redirect(
+            &res,
+            &consent,
+            &["account:read".to_string()],
+            ClientType::Public,
+            "xyz",
+        )
+        .await;
+}
@@ -0,0 +1,385 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::{
+    mailer::{Email, MemoryMailer},
+    settings::{MailSettings, MailTransport, Settings, UnverifiedAccounts},
+};
+use regex::Regex;
+
+use crate::{
+    helpers::{csrf_token, spawn_app_with_settings, ClientType, TestState, CODE_CHALLENGE},
+    mfa::is_signed_in,
+};
+
+async fn spawn_app_with_mailer(
+    unverified_accounts: UnverifiedAccounts,
+) -> (TestState, MemoryMailer) {
+    let mailer = MemoryMailer::new();
+    let settings = Settings {
+        mail: MailSettings {
+            transport: MailTransport::Memory(mailer.clone()),
+            ..Default::default()
+        },
+        unverified_accounts,
+        ..Default::default()
+    };
+
+    (spawn_app_with_settings(settings).await, mailer)
+}
+
+async fn sign_up(state: &TestState, username: &str, email: &str) -> reqwest::Response {
+    let form = serde_json::json!({
+        "username": username,
+        "email": email,
+        "password": "correct horse battery",
+        "given_name": "Alice",
+        "csrf_token": state.get_csrf_token("signup").await,
+    });
+    state
+        .api_client
+        .post(format!("{}/oauth/signup", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn sign_in(state: &TestState, username: &str, password: &str) -> reqwest::Response {
+    let form = serde_json::json!({
+        "username": username,
+        "password": password,
+        "csrf_token": state.get_csrf_token("signin").await,
+    });
+    state
+        .api_client
+        .post(format!("{}/oauth/signin", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+/// The last email to `to`. Reset links are sent in the background, so it may take a moment.
+async fn email_to(mailer: &MemoryMailer, to: &str) -> Email {
+    for _ in 0..100 {
+        if let Some(email) = mailer.last_to(to) {
+            return email;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("no email was sent to {to}")
+}
+
+/// The path and query of the link in the last email to `to`.
+async fn emailed_link(mailer: &MemoryMailer, to: &str) -> String {
+    let email = email_to(mailer, to).await;
+    let re_link = Regex::new(r"http://localhost:3000(/oauth/\S+)").unwrap();
+    let caps = re_link.captures(&email.body).expect("the email has a link");
+
+    caps[1].to_owned()
+}
+
+async fn get(state: &TestState, path: &str) -> reqwest::Response {
+    state
+        .api_client
+        .get(format!("{}{}", state.app_address, path))
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn reset_password(state: &TestState, link: &str, password: &str) -> reqwest::Response {
+    let page = get(state, link).await.text().await.unwrap();
+    let token = link.split("token=").nth(1).unwrap();
+    let form = serde_json::json!({
+        "token": token,
+        "password": password,
+        "csrf_token": csrf_token(&page),
+    });
+    state
+        .api_client
+        .post(format!("{}/oauth/password/reset", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn forgot_password(state: &TestState, email: &str) -> reqwest::Response {
+    let form = serde_json::json!({
+        "email": email,
+        "csrf_token": state.get_csrf_token("password/forgot").await,
+    });
+    state
+        .api_client
+        .post(format!("{}/oauth/password/forgot", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+#[tokio::test]
+async fn signup_sends_a_single_use_verification_link() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+    let response = sign_up(&state, "alice", "alice@example.com").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let link = emailed_link(&mailer, "alice@example.com").await;
+    assert!(link.starts_with("/oauth/email/verify?token="), "{}", link);
+
+    // Act
+    let verified = get(&state, &link).await;
+    let reused = get(&state, &link).await;
+
+    // Assert
+    assert_eq!(verified.status().as_u16(), 200);
+    assert_eq!(reused.status().as_u16(), 400, "The link only works once");
+}
+
+#[tokio::test]
+async fn signup_rejects_invalid_email_addresses() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+
+    // Act
+    let invalid = sign_up(&state, "alice", "not an address").await;
+
+    // Assert
+    assert_eq!(invalid.status().as_u16(), 422);
+    assert!(mailer.sent().is_empty());
+}
+
+#[tokio::test]
+async fn signup_with_a_verified_address_mails_its_owner() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+
+    // Act
+    let taken = sign_up(&state, "alice", "BOB@example.com").await;
+
+    // Assert
+    assert_eq!(
+        taken.status().as_u16(),
+        201,
+        "Sign-up doesn't reveal that the address is taken"
+    );
+    let email = email_to(&mailer, "bob@example.com").await;
+    assert_eq!(email.subject, "You already have an account");
+    assert!(
+        email
+            .body
+            .contains("http://localhost:3000/oauth/password/forgot"),
+        "{}",
+        email.body
+    );
+    assert_eq!(
+        sign_up(&state, "alice", "alice@example.com")
+            .await
+            .status()
+            .as_u16(),
+        201,
+        "No account was created"
+    );
+}
+
+#[tokio::test]
+async fn unverified_addresses_can_be_signed_up_with_again() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+    sign_up(&state, "alice", "carol@example.com").await;
+    let squatted = emailed_link(&mailer, "carol@example.com").await;
+
+    // Act
+    let response = sign_up(&state, "carol", "carol@example.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let link = emailed_link(&mailer, "carol@example.com").await;
+    assert_ne!(link, squatted);
+    assert_eq!(
+        get(&state, &squatted).await.status().as_u16(),
+        400,
+        "The address is no longer the first account's"
+    );
+    assert_eq!(get(&state, &link).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn password_reset_replaces_the_password() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+    state.signin("bob", "secret").await;
+    let response = forgot_password(&state, "bob@example.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let link = emailed_link(&mailer, "bob@example.com").await;
+    assert!(link.starts_with("/oauth/password/reset?token="), "{}", link);
+    let weak = reset_password(&state, &link, "short").await;
+    assert_eq!(weak.status().as_u16(), 422, "The sign-up policy applies");
+
+    // Act
+    let response = reset_password(&state, &link, "new secret").await;
+    let reused = reset_password(&state, &link, "another secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(reused.status().as_u16(), 400, "The link only works once");
+    assert!(!is_signed_in(&state).await, "Sessions are signed out");
+    assert_eq!(
+        sign_in(&state, "bob", "secret").await.status().as_u16(),
+        401,
+        "The old password no longer works"
+    );
+    state.signin("bob", "new secret").await;
+}
+
+#[tokio::test]
+async fn password_reset_revokes_pending_codes() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+    let (code, verifier) = state.authorization_code(&client, "account:read").await;
+    forgot_password(&state, "bob@example.com").await;
+    let link = emailed_link(&mailer, "bob@example.com").await;
+
+    // Act
+    let response = reset_password(&state, &link, "new secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let response = state.exchange_code(&client, &code, &verifier).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+}
+
+#[tokio::test]
+async fn forgot_password_does_not_reveal_unknown_addresses() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+
+    // Act
+    let known = forgot_password(&state, "bob@example.com").await;
+    let unknown = forgot_password(&state, "nobody@example.com").await;
+
+    // Assert
+    assert_eq!(known.status().as_u16(), 200);
+    assert_eq!(unknown.status().as_u16(), 200);
+    let without_token = |body: String| body.replace(&csrf_token(&body), "");
+    assert_eq!(
+        without_token(known.text().await.unwrap()),
+        without_token(unknown.text().await.unwrap()),
+        "Both pages say the same"
+    );
+    email_to(&mailer, "bob@example.com").await;
+    assert_eq!(mailer.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn forgot_password_is_throttled_per_address() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::Allow).await;
+    for _ in 0..5 {
+        let response = forgot_password(&state, "bob@example.com").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act
+    let throttled = forgot_password(&state, "BOB@example.com").await;
+    let other = forgot_password(&state, "nobody@example.com").await;
+
+    // Assert
+    assert_eq!(throttled.status().as_u16(), 429);
+    assert_eq!(
+        other.status().as_u16(),
+        200,
+        "Other addresses aren't throttled"
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(mailer.sent().len(), 5);
+}
+
+#[tokio::test]
+async fn unverified_accounts_can_be_kept_from_signing_in() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::NoSignIn).await;
+    sign_up(&state, "alice", "alice@example.com").await;
+
+    // Act
+    let refused = sign_in(&state, "alice", "correct horse battery").await;
+    let link = emailed_link(&mailer, "alice@example.com").await;
+    get(&state, &link).await;
+
+    // Assert
+    assert_eq!(refused.status().as_u16(), 403);
+    state.signin("alice", "correct horse battery").await;
+}
+
+#[tokio::test]
+async fn unverified_accounts_can_be_kept_from_authorizing_clients() {
+    // Arrange
+    let (state, mailer) = spawn_app_with_mailer(UnverifiedAccounts::NoAuthorization).await;
+    sign_up(&state, "alice", "alice@example.com").await;
+    state.signin("alice", "correct horse battery").await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+    });
+    let client = state.register_client(&params, ClientType::Public).await;
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": client.client_id,
+        "scope": "account:read",
+        "state": "12345",
+        "code_challenge": CODE_CHALLENGE,
+        "code_challenge_method": "S256",
+    });
+    let authorize = || async {
+        state
+            .api_client
+            .get(format!("{}/oauth/authorize", state.app_address))
+            .query(&query)
+            .send()
+            .await
+            .expect("request to server api failed")
+    };
+
+    // Act
+    let refused = authorize().await;
+    let page = refused.text().await.unwrap();
+    let form = serde_json::json!({ "csrf_token": csrf_token(&page) });
+    let resent = state
+        .api_client
+        .post(format!("{}/oauth/email/verify", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+    let link = emailed_link(&mailer, "alice@example.com").await;
+    get(&state, &link).await;
+    let allowed = authorize().await;
+
+    // Assert
+    assert!(
+        page.contains(r#"action="/oauth/email/verify""#),
+        "The owner can ask for another link"
+    );
+    assert_eq!(resent.status().as_u16(), 200);
+    assert_eq!(mailer.sent().len(), 2);
+    assert_eq!(
+        allowed.status().as_u16(),
+        200,
+        "Once verified, the consent page is shown"
+    );
+}
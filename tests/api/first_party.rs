@@ -0,0 +1,106 @@
+use axum_oauth::oauth::settings::Settings;
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with_admin, ClientResponse, ClientType, TestState,
+    ADMIN_TOKEN, CODE_CHALLENGE,
+};
+
+async fn register_client(state: &TestState) -> ClientResponse {
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+        "first_party": true,
+    });
+
+    state.register_client(&params, ClientType::Public).await
+}
+
+pub async fn trust_client(state: &TestState, client_id: &str, token: &str) -> reqwest::Response {
+    state
+        .api_client
+        .patch(format!(
+            "{}/oauth/admin/clients/{}",
+            &state.app_address, client_id
+        ))
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "first_party": true }))
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn authorize(state: &TestState, client_id: &str) -> reqwest::Response {
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": client_id,
+        "scope": "account:read",
+        "state": "12345",
+        "code_challenge": CODE_CHALLENGE,
+        "code_challenge_method": "S256",
+    });
+    state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .query(&query)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+#[tokio::test]
+async fn first_party_clients_are_authorized_without_consent() {
+    // Arrange
+    let state = spawn_app_with_admin(Settings::default()).await;
+    let client = register_client(&state).await;
+    let response = trust_client(&state, &client.client_id, ADMIN_TOKEN).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let metadata: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(metadata["first_party"], true);
+    state.signin("bob", "secret").await;
+
+    // Act
+    let response = authorize(&state, &client.client_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, 302, "http://localhost:3001/endpoint?code=", true);
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/account/apps", &state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        page.contains("connected without asking you"),
+        "The approval is recorded: {}",
+        page
+    );
+}
+
+#[tokio::test]
+async fn only_admins_make_clients_first_party() {
+    // Arrange
+    let state = spawn_app_with_admin(Settings::default()).await;
+    let client = register_client(&state).await;
+    let unconfigured = spawn_app().await;
+    let other = register_client(&unconfigured).await;
+
+    // Act
+    let wrong_token = trust_client(&state, &client.client_id, "not-the-token").await;
+    let no_admin_api = trust_client(&unconfigured, &other.client_id, ADMIN_TOKEN).await;
+    state.signin("bob", "secret").await;
+    let response = authorize(&state, &client.client_id).await;
+
+    // Assert
+    assert_eq!(wrong_token.status().as_u16(), 401);
+    assert_eq!(no_admin_api.status().as_u16(), 404);
+    assert_eq!(
+        response.status().as_u16(),
+        200,
+        "Registering as first-party isn't enough to skip the consent page"
+    );
+}
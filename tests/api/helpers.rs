@@ -1,6 +1,8 @@
+use axum_oauth::oauth::settings::Settings;
 use csrf::CsrfToken;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::task::JoinHandle;
@@ -50,21 +52,36 @@ where
 #[derive(Debug, Default)]
 pub struct TestState {
     pub app_address: String,
+    #[allow(dead_code)]
     pub port: u16,
     pub api_client: reqwest::Client,
     pub token: Token,
 }
 
 impl TestState {
+    /// Fetch a form page and return the CSRF token issued with it.
+    pub async fn get_csrf_token(&self, path: &str) -> String {
+        let response = self
+            .api_client
+            .get(format!("{}/oauth/{}", &self.app_address, path))
+            .send()
+            .await
+            .expect("request to server api failed");
+        assert_eq!(response.status().as_u16(), 200, "form page is available");
+
+        csrf_token(&response.text().await.unwrap())
+    }
+
     pub async fn signin(&self, username: &str, password: &str) {
         let form = serde_json::json!({
             "username": username,
             "password": password,
+            "csrf_token": self.get_csrf_token("signin").await,
         });
 
         let response = self
             .api_client
-            .post(&format!("{}/oauth/signin", &self.app_address))
+            .post(format!("{}/oauth/signin", &self.app_address))
             .form(&form)
             .send()
             .await
@@ -78,6 +95,36 @@ impl TestState {
         assert_is_redirect_to(&response, 303, "/oauth/", false);
     }
 
+    /// Request the page without a session, sign in where it redirects to, and return the location
+    /// signing in redirects to.
+    pub async fn signin_from(&self, path: &str, username: &str, password: &str) -> String {
+        let response = self
+            .api_client
+            .get(format!("{}{}", &self.app_address, path))
+            .send()
+            .await
+            .expect("request to server api failed");
+        assert_eq!(response.status().as_u16(), 303, "{path} needs a session");
+        let signin = response.headers()["Location"].to_str().unwrap().to_owned();
+        assert!(signin.starts_with("/oauth/signin?"), "{signin}");
+
+        let form = serde_json::json!({
+            "username": username,
+            "password": password,
+            "csrf_token": self.get_csrf_token("signin").await,
+        });
+        let response = self
+            .api_client
+            .post(format!("{}{}", &self.app_address, signin))
+            .form(&form)
+            .send()
+            .await
+            .expect("request to server api failed");
+        assert_eq!(response.status().as_u16(), 303);
+
+        response.headers()["Location"].to_str().unwrap().to_owned()
+    }
+
     pub async fn register_client(&self, params: &Value, client_type: ClientType) -> ClientResponse {
         // Arrange
         tracing::debug!("Test::POST /oauth/client (Register Client)");
@@ -85,7 +132,7 @@ impl TestState {
         // Act
         let response = self
             .api_client
-            .post(&format!("{}/oauth/client", self.app_address))
+            .post(format!("{}/oauth/client", self.app_address))
             .form(params)
             .send()
             .await
@@ -212,27 +259,45 @@ impl TestState {
         body
     }
 
-    pub async fn owner_consent_allow(&self, body: &str) -> String {
+    pub async fn owner_consent_allow(&self, body: &str) -> ConsentForm {
         let re_action = Regex::new("formaction=\"(.*)\"").unwrap();
-        let caps = re_action.captures(&body).unwrap();
+        let caps = re_action.captures(body).unwrap();
         let allow_path = caps.get(1).map_or("/", |m| m.as_str());
         let allow_path = urlencoding::decode(allow_path).expect("failed to decode formaction");
         let allow_path = html_escape::decode_html_entities(&allow_path);
         let allow_uri = format!("{}/oauth/{}", self.app_address, allow_path);
         tracing::debug!("allow uri: {}", allow_uri);
 
-        allow_uri
+        ConsentForm {
+            action: allow_uri,
+            csrf_token: csrf_token(body),
+        }
+    }
+
+    /// Scopes that are checked (and can be unchecked) on the consent form.
+    pub fn consent_scopes(&self, body: &str) -> Vec<String> {
+        let re_scope = Regex::new("name=\"([^\"]+)\" checked>").unwrap();
+        re_scope
+            .captures_iter(body)
+            .map(|caps| html_escape::decode_html_entities(&caps[1]).into_owned())
+            .collect()
     }
 
     pub async fn capture_authorizer_redirect(
         &self,
         client: &ClientResponse,
-        consent_response: &str,
+        consent_response: &ConsentForm,
+        approved: &[String],
         client_type: ClientType,
         csrf: &str,
     ) -> String {
         // Send response from owner consent to authorization endpoint
-        let client_request = self.api_client.post(consent_response);
+        let form = approved
+            .iter()
+            .map(|scope| (scope.as_str(), "on"))
+            .chain([("csrf_token", consent_response.csrf_token.as_str())])
+            .collect::<Vec<_>>();
+        let client_request = self.api_client.post(&consent_response.action).form(&form);
         let client_request = match client_type {
             ClientType::Confidential => client_request.basic_auth(
                 client.client_id.clone(),
@@ -255,13 +320,13 @@ impl TestState {
             .expect("failed to get redirect location");
         tracing::debug!("Client redirect: {}", location);
         let re_code = Regex::new("\\?code=(.*)\\&").unwrap();
-        let caps = re_code.captures(&location).unwrap();
+        let caps = re_code.captures(location).unwrap();
         let code = caps.get(1).map_or("X", |m| m.as_str());
         let code = urlencoding::decode(code).expect("failed to decode authorization code");
         tracing::debug!("Extracted code: {}", code);
 
         let re_code = Regex::new("\\&state=(.*)").unwrap();
-        let caps = re_code.captures(&location).unwrap();
+        let caps = re_code.captures(location).unwrap();
         let state = caps.get(1).map_or("X", |m| m.as_str());
         let state = urlencoding::decode(state).expect("failed to decode state");
         tracing::debug!("Extracted state: {}", state);
@@ -308,12 +373,12 @@ impl TestState {
             assert!(token.scope.contains(s), "Token scope includes {}", s);
         }
         assert!(
-            !token.access_token.is_none(),
+            token.access_token.is_some(),
             "Access token contains a value"
         );
         assert!(
-            !token.refresh_token.is_none(),
-            "Refresh token contains a value"
+            token.refresh_token.is_some() || !token.scope.contains("offline_access"),
+            "Refresh token is issued for offline access"
         );
         assert!(
             token.error.is_none(),
@@ -362,11 +427,11 @@ impl TestState {
         }
         let new_token = token.access_token.clone().unwrap();
         assert!(
-            !token.access_token.is_none() && new_token != str_old_token,
+            token.access_token.is_some() && new_token != str_old_token,
             "New access token is different from the previous token"
         );
         assert!(
-            !token.refresh_token.is_none(),
+            token.refresh_token.is_some(),
             "Refresh token contains a value"
         );
         assert!(
@@ -381,7 +446,7 @@ impl TestState {
         // Act
         let response = self
             .api_client
-            .get(&format!("{}/api/user", &self.app_address))
+            .get(format!("{}/api/user", &self.app_address))
             .bearer_auth(token)
             .send()
             .await
@@ -402,6 +467,32 @@ impl TestState {
     }
 
     pub async fn authorization_flow(&mut self, client: &ClientResponse) {
+        self.authorization_flow_with_scope(client, "account:read account:write offline_access")
+            .await;
+    }
+
+    pub async fn authorization_flow_with_scope(&mut self, client: &ClientResponse, scope: &str) {
+        let (authorization_code, code_verifier) = self.authorization_code(client, scope).await;
+
+        // Bearer token
+        let params = vec![
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", "http://localhost:3001/endpoint"),
+            ("code", &authorization_code),
+            ("code_verifier", &code_verifier),
+        ];
+        self.token = self
+            .exchange_auth_code_for_token(client, ClientType::Confidential, &params)
+            .await;
+    }
+
+    /// Run the authorization flow until the client gets a code, without exchanging it. Returns
+    /// the code and its PKCE verifier.
+    pub async fn authorization_code(
+        &self,
+        client: &ClientResponse,
+        scope: &str,
+    ) -> (String, String) {
         let code_verifier = pkce::code_verifier(128);
         let code_challenge = pkce::code_challenge(&code_verifier);
         let csrf_token = CsrfToken::new(nanoid::nanoid!().into_bytes()).b64_string();
@@ -409,7 +500,7 @@ impl TestState {
             "response_type": "code",
             "redirect_uri": "http://localhost:3001/endpoint",
             "client_id": client.client_id.clone(),
-            "scope": "account:read account:write",
+            "scope": scope,
             "code_challenge": code_challenge,
             "code_challenge_method": "S256",
             "state": csrf_token,
@@ -418,26 +509,42 @@ impl TestState {
         // Owner consent prompt + allow response + authorization code
         let body = self.get_consent_prompt_confidential(&query).await;
         let consent_response = self.owner_consent_allow(&body).await;
+        let approved = self.consent_scopes(&body);
         let authorization_code = self
             .capture_authorizer_redirect(
                 client,
                 &consent_response,
+                &approved,
                 ClientType::Confidential,
                 &csrf_token,
             )
             .await;
 
-        // Bearer token
-        let cv = String::from_utf8_lossy(&code_verifier);
-        let params = vec![
-            ("grant_type", "authorization_code"),
-            ("redirect_uri", "http://localhost:3001/endpoint"),
-            ("code", &authorization_code),
-            ("code_verifier", &cv),
-        ];
-        self.token = self
-            .exchange_auth_code_for_token(client, ClientType::Confidential, &params)
-            .await;
+        (
+            authorization_code,
+            String::from_utf8_lossy(&code_verifier).into_owned(),
+        )
+    }
+
+    /// Exchange a code of a confidential client, leaving the response to the caller.
+    pub async fn exchange_code(
+        &self,
+        client: &ClientResponse,
+        code: &str,
+        code_verifier: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/oauth/token", self.app_address))
+            .basic_auth(&client.client_id, client.client_secret.as_ref())
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", "http://localhost:3001/endpoint"),
+                ("code", code),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .expect("failed to get response from api client")
     }
 }
 
@@ -454,14 +561,39 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+/// The admin token of the apps spawned with `spawn_app_with_admin`.
+pub const ADMIN_TOKEN: &str = "admin-secret";
+
+/// A PKCE challenge for authorization requests whose code is never exchanged.
+pub const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
 pub async fn spawn_app() -> TestState {
+    spawn_app_with_settings(Settings::default()).await
+}
+
+/// Spawn the app with the admin API enabled, answering to `ADMIN_TOKEN`.
+pub async fn spawn_app_with_admin(settings: Settings) -> TestState {
+    spawn_app_with_settings(Settings {
+        admin_token: Some(Secret::new(ADMIN_TOKEN.to_string())),
+        ..settings
+    })
+    .await
+}
+
+pub async fn spawn_app_with_settings(settings: Settings) -> TestState {
     // Initialize tracing stack
     Lazy::force(&TRACING);
 
     // Launch app
-    let (router, listener) = axum_oauth::build_service(Some("0.0.0.0:0".to_string()), 3000).await;
+    let (router, listener, maintenance) =
+        axum_oauth::build_service(Some("0.0.0.0:0".to_string()), 3000, settings).await;
     let port = listener.local_addr().unwrap().port();
-    tokio::spawn(axum_oauth::serve(router, listener));
+    tokio::spawn(axum_oauth::serve_with_shutdown(
+        router,
+        listener,
+        maintenance,
+        std::future::pending(),
+    ));
 
     let reqwest_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -471,7 +603,7 @@ pub async fn spawn_app() -> TestState {
 
     let res = TestState {
         app_address: format!("http://localhost:{}", port),
-        port: port,
+        port,
         api_client: reqwest_client,
         ..Default::default()
     };
@@ -480,6 +612,16 @@ pub async fn spawn_app() -> TestState {
     res
 }
 
+/// The CSRF token of the form on a page.
+pub fn csrf_token(body: &str) -> String {
+    let re_token = Regex::new("name=\"csrf_token\" value=\"([^\"]+)\"").unwrap();
+    let caps = re_token
+        .captures(body)
+        .expect("the page has a form with a CSRF token");
+
+    html_escape::decode_html_entities(&caps[1]).into_owned()
+}
+
 pub fn assert_is_redirect_to(
     response: &reqwest::Response,
     status_code: u16,
@@ -514,6 +656,7 @@ pub fn assert_is_redirect_to(
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize)]
 struct AuthorizationCode {
     code: String,
@@ -538,6 +681,12 @@ pub struct Token {
     pub error: Option<String>,
 }
 
+/// Where the owner's answer on the consent page is posted to.
+pub struct ConsentForm {
+    pub action: String,
+    pub csrf_token: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ClientResponse {
     pub client_id: String,
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::settings::{Settings, TokenLifetimes};
+
+use crate::{
+    helpers::{spawn_app_with_admin, ClientType, ADMIN_TOKEN},
+    user::{authorized_state, authorized_state_with, refresh},
+};
+
+#[tokio::test]
+async fn access_tokens_last_an_hour_by_default() {
+    // Act
+    let (state, _) = authorized_state().await;
+
+    // Assert
+    let expires_in = state.token.expires_in.unwrap();
+    assert!((3590..=3600).contains(&expires_in), "{expires_in}");
+}
+
+#[tokio::test]
+async fn access_token_lifetime_can_be_configured() {
+    // Act
+    let (state, _) = authorized_state_with(Settings {
+        lifetimes: TokenLifetimes {
+            access_token: Duration::from_secs(120),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+
+    // Assert
+    let expires_in = state.token.expires_in.unwrap();
+    assert!((110..=120).contains(&expires_in), "{expires_in}");
+}
+
+#[tokio::test]
+async fn clients_can_have_lifetimes_of_their_own() {
+    // Arrange
+    let mut state = spawn_app_with_admin(Settings::default()).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    let lifetimes = serde_json::json!({ "access_token_secs": 60 });
+
+    // Act
+    let response = state
+        .api_client
+        .put(format!(
+            "{}/oauth/admin/clients/{}/lifetimes",
+            &state.app_address, client.client_id
+        ))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&lifetimes)
+        .send()
+        .await
+        .expect("request to admin api failed");
+    state.signin("bob", "secret").await;
+    state.authorization_flow(&client).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let expires_in = state.token.expires_in.unwrap();
+    assert!((50..=60).contains(&expires_in), "{expires_in}");
+    let response = state
+        .api_client
+        .get(format!(
+            "{}/oauth/admin/clients/{}/lifetimes",
+            &state.app_address, client.client_id
+        ))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("request to admin api failed");
+    let stored: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stored["access_token_secs"], 60);
+    assert_eq!(
+        stored["refresh_token_secs"],
+        serde_json::Value::Null,
+        "Lifetimes left out are the defaults"
+    );
+}
+
+#[tokio::test]
+async fn refreshed_access_tokens_get_the_configured_lifetime() {
+    // Arrange
+    let (state, client) = authorized_state_with(Settings {
+        lifetimes: TokenLifetimes {
+            access_token: Duration::from_secs(120),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+
+    // Act
+    let response = refresh(&state, &client, &state.token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let token: serde_json::Value = response.json().await.unwrap();
+    let expires_in = token["expires_in"].as_i64().unwrap();
+    assert!((110..=120).contains(&expires_in), "{expires_in}");
+}
+
+#[tokio::test]
+async fn expired_refresh_tokens_are_rejected() {
+    // Arrange
+    let (state, client) = authorized_state_with(Settings {
+        lifetimes: TokenLifetimes {
+            refresh_token: Duration::from_secs(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // Act
+    let response = refresh(&state, &client, &state.token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+}
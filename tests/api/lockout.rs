@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::{
+    database::Database,
+    settings::{LockoutSettings, Settings},
+    throttle::SignInThrottle,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app_with_admin, spawn_app_with_settings, TestState, ADMIN_TOKEN,
+};
+
+async fn spawn_app_with_lockout(lockout: LockoutSettings) -> TestState {
+    spawn_app_with_admin(Settings {
+        lockout,
+        ..Default::default()
+    })
+    .await
+}
+
+async fn attempt_signin(state: &TestState, username: &str, password: &str) -> reqwest::Response {
+    let form = serde_json::json!({
+        "username": username,
+        "password": password,
+        "csrf_token": state.get_csrf_token("signin").await,
+    });
+
+    state
+        .api_client
+        .post(format!("{}/oauth/signin", &state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn assert_locked_out(response: reqwest::Response, msg: &str) {
+    assert_eq!(response.status().as_u16(), 429, "{}", msg);
+    assert!(
+        response.headers().get("Retry-After").is_some(),
+        "Lockouts tell the client when to retry"
+    );
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains("Too many failed sign-in attempts"),
+        "The sign in page explains the lockout"
+    );
+}
+
+#[tokio::test]
+async fn account_is_locked_after_repeated_failures() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        account_threshold: 3,
+        ..Default::default()
+    })
+    .await;
+    for _ in 0..2 {
+        let response = attempt_signin(&state, "bob", "wrong").await;
+        assert_eq!(response.status().as_u16(), 401);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("Wrong username or password."));
+    }
+
+    // Act
+    let response = attempt_signin(&state, "bob", "wrong").await;
+
+    // Assert
+    assert_locked_out(
+        response,
+        "The failure reaching the threshold locks the account",
+    )
+    .await;
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_locked_out(response, "The right password doesn't get past a lockout").await;
+}
+
+#[tokio::test]
+async fn address_is_locked_across_accounts() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        address_threshold: 3,
+        ..Default::default()
+    })
+    .await;
+    for username in ["alice", "carol"] {
+        let response = attempt_signin(&state, username, "guess").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Act
+    let response = attempt_signin(&state, "dave", "guess").await;
+
+    // Assert
+    assert_locked_out(response, "Failures from one address add up").await;
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_locked_out(response, "Other accounts are locked from that address").await;
+}
+
+#[tokio::test]
+async fn successful_signin_resets_account_failures() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        account_threshold: 2,
+        ..Default::default()
+    })
+    .await;
+    let response = attempt_signin(&state, "bob", "wrong").await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+
+    // Act
+    let response = attempt_signin(&state, "bob", "wrong").await;
+
+    // Assert
+    assert_eq!(
+        response.status().as_u16(),
+        401,
+        "Only failures since the last sign-in count"
+    );
+}
+
+#[tokio::test]
+async fn lockout_backs_off_exponentially_and_expires() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        window: Duration::from_secs(60),
+        account_threshold: 1,
+        base_lockout: Duration::from_millis(1000),
+        ..Default::default()
+    })
+    .await;
+    let response = attempt_signin(&state, "bob", "wrong").await;
+    assert_locked_out(response, "The first failure locks the account for a second").await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // Act
+    let response = attempt_signin(&state, "bob", "wrong").await;
+    assert_locked_out(
+        response,
+        "The lockout is over, the next failure locks again",
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // Assert
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_locked_out(response, "The second lockout lasts twice as long").await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+}
+
+#[tokio::test]
+async fn admin_can_inspect_and_unlock_accounts() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        account_threshold: 2,
+        ..Default::default()
+    })
+    .await;
+    for _ in 0..2 {
+        attempt_signin(&state, "bob", "wrong").await;
+    }
+    let lockout_uri = format!("{}/oauth/admin/lockouts/bob", &state.app_address);
+
+    // Act
+    let response = state
+        .api_client
+        .get(&lockout_uri)
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let lockout = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(lockout["failures"], 2);
+    assert!(lockout["locked_until"].is_u64(), "The account is locked");
+
+    // Act
+    let response = state
+        .api_client
+        .delete(&lockout_uri)
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let response = attempt_signin(&state, "bob", "secret").await;
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+}
+
+#[tokio::test]
+async fn admin_api_requires_admin_token() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings::default()).await;
+    let disabled = spawn_app_with_settings(Settings::default()).await;
+    let lockout_uri = format!("{}/oauth/admin/lockouts/bob", &state.app_address);
+    let cases = [
+        (state.api_client.delete(&lockout_uri), 401, "no token"),
+        (
+            state.api_client.delete(&lockout_uri).bearer_auth("wrong"),
+            401,
+            "wrong token",
+        ),
+        (
+            disabled
+                .api_client
+                .delete(format!(
+                    "{}/oauth/admin/lockouts/bob",
+                    &disabled.app_address
+                ))
+                .bearer_auth(ADMIN_TOKEN),
+            404,
+            "no admin token configured",
+        ),
+    ];
+
+    for (request, status, msg) in cases {
+        // Act
+        let response = request.send().await.expect("request to server api failed");
+
+        // Assert
+        assert_eq!(response.status().as_u16(), status, "{}", msg);
+    }
+}
+
+#[tokio::test]
+async fn failures_count_against_the_account_however_the_username_is_written() {
+    // Arrange
+    let state = spawn_app_with_lockout(LockoutSettings {
+        account_threshold: 3,
+        ..Default::default()
+    })
+    .await;
+    for username in ["bob", "BOB"] {
+        let response = attempt_signin(&state, username, "wrong").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Act
+    let response = attempt_signin(&state, "Bob", "wrong").await;
+
+    // Assert
+    assert_locked_out(
+        response,
+        "Every spelling of the username is the same account",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn failures_are_capped_per_account() {
+    // Arrange
+    let throttle = SignInThrottle::new(
+        Database::new(),
+        LockoutSettings {
+            account_threshold: 3,
+            base_lockout: Duration::from_secs(30),
+            max_lockout: Duration::from_secs(15 * 60),
+            ..Default::default()
+        },
+    );
+
+    // Act
+    for _ in 0..100 {
+        throttle.record_failure("bob", None).await;
+    }
+
+    // Assert
+    let status = throttle.status("bob").await;
+    assert_eq!(
+        status.failures, 9,
+        "Failures past the one that reaches the longest lockout aren't kept"
+    );
+    assert!(status.locked_until.is_some());
+}
+
+#[tokio::test]
+async fn failures_past_the_window_are_purged() {
+    // Arrange
+    let throttle = SignInThrottle::new(
+        Database::new(),
+        LockoutSettings {
+            window: Duration::from_millis(1),
+            ..Default::default()
+        },
+    );
+    for i in 0..50 {
+        let address = format!("10.0.0.{i}").parse().unwrap();
+        throttle
+            .record_failure(&format!("user{i}"), Some(address))
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Act
+    let purged = throttle.purge_expired().await;
+
+    // Assert
+    assert_eq!(purged, 100, "Every account and address is purged");
+    assert_eq!(throttle.purge_expired().await, 0, "Nothing is left");
+}
@@ -1,8 +1,19 @@
+mod apps;
 mod client;
+mod email;
+mod first_party;
 mod helpers;
 mod index;
+mod lifetimes;
+mod lockout;
+mod maintenance;
+mod mfa;
 // mod oauth_client_helper;
+mod passkeys;
+mod rate_limit;
+mod refresh;
 mod signin;
 mod signout;
 mod signup;
+mod templates;
 mod user;
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::{
+    database::{Database, EmailTokenPurpose},
+    models::UserId,
+    primitives::{CodeMap, Lifetimes, OwnerTokenMap, UsageIssuer},
+    settings::{MaintenanceSettings, Settings, TokenLifetimes},
+};
+use chrono::Utc;
+use oxide_auth::primitives::{
+    generator::RandomGenerator,
+    grant::{Extensions, Grant},
+};
+use oxide_auth_async::primitives::{Authorizer, Issuer};
+
+use crate::{
+    helpers::{spawn_app_with_admin, ADMIN_TOKEN},
+    user::authorized_state,
+};
+
+fn grant(lifetime: chrono::Duration) -> Grant {
+    Grant {
+        owner_id: "owner".to_string(),
+        client_id: "client".to_string(),
+        scope: "account:read offline_access".parse().unwrap(),
+        redirect_uri: "http://localhost:3001/endpoint".parse().unwrap(),
+        until: Utc::now() + lifetime,
+        extensions: Extensions::new(),
+    }
+}
+
+#[tokio::test]
+async fn expired_codes_are_purged() {
+    // Arrange
+    let codes = CodeMap::new(RandomGenerator::new(16));
+    let expired = (&codes)
+        .authorize(grant(chrono::Duration::seconds(-1)))
+        .await
+        .unwrap();
+    let live = (&codes)
+        .authorize(grant(chrono::Duration::minutes(10)))
+        .await
+        .unwrap();
+
+    // Act
+    let purged = codes.purge_expired(Utc::now());
+
+    // Assert
+    assert_eq!(purged, 1);
+    assert!((&codes).extract(&expired).await.unwrap().is_none());
+    assert!(
+        (&codes).extract(&live).await.unwrap().is_some(),
+        "Codes that haven't expired are kept"
+    );
+}
+
+#[tokio::test]
+async fn expired_access_tokens_are_purged_and_refresh_tokens_kept() {
+    // Arrange
+    let db = Database::new();
+    let tokens = OwnerTokenMap::new(RandomGenerator::new(16));
+    let defaults = TokenLifetimes::default();
+    let instant = TokenLifetimes {
+        access_token: Duration::ZERO,
+        ..Default::default()
+    };
+    let mut issuer = UsageIssuer::new(&tokens, &db, Lifetimes::new(&defaults, &db));
+    let expired = UsageIssuer::new(&tokens, &db, Lifetimes::new(&instant, &db))
+        .issue(grant(chrono::Duration::hours(1)))
+        .await
+        .unwrap();
+    let live = issuer
+        .issue(grant(chrono::Duration::hours(1)))
+        .await
+        .unwrap();
+
+    // Act
+    let purged = tokens.purge_expired(Utc::now());
+
+    // Assert
+    assert_eq!(purged, (1, 0));
+    assert!(issuer
+        .recover_token(&expired.token)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(issuer.recover_token(&live.token).await.unwrap().is_some());
+    assert!(
+        issuer
+            .recover_refresh(expired.refresh.as_deref().unwrap())
+            .await
+            .unwrap()
+            .is_some(),
+        "The refresh token outlives its access token"
+    );
+    assert_eq!(
+        tokens.purge_expired(Utc::now()),
+        (0, 0),
+        "Nothing is purged twice"
+    );
+}
+
+#[tokio::test]
+async fn expired_email_tokens_are_purged() {
+    // Arrange
+    let db = Database::new();
+    let now = Utc::now().timestamp() as u64;
+    for (email, expires) in [("a@example.com", now), ("b@example.com", now + 3600)] {
+        db.issue_email_token(
+            UserId::new(),
+            EmailTokenPurpose::ResetPassword,
+            email,
+            expires,
+        )
+        .await;
+    }
+
+    // Act
+    let purged = db.purge_email_tokens(now).await;
+
+    // Assert
+    assert_eq!(purged, 1);
+    assert_eq!(
+        db.purge_email_tokens(now).await,
+        0,
+        "Nothing is purged twice"
+    );
+    assert_eq!(
+        db.purge_email_tokens(now + 3600).await,
+        1,
+        "Tokens that haven't expired are kept until they do"
+    );
+}
+
+#[tokio::test]
+async fn maintenance_runs_are_reported_to_admins() {
+    // Arrange
+    let state = spawn_app_with_admin(Settings {
+        maintenance: MaintenanceSettings {
+            interval: Duration::from_millis(50),
+        },
+        ..Default::default()
+    })
+    .await;
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/admin/maintenance", &state.app_address))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("request to admin api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert!(report["runs"].as_u64().unwrap() >= 1, "{report}");
+    assert!(report["last_run_at"].as_u64().is_some());
+    assert_eq!(
+        report["purged"],
+        serde_json::json!({
+            "codes": 0,
+            "access_tokens": 0,
+            "refresh_tokens": 0,
+            "sessions_approx": 0,
+            "email_tokens": 0,
+            "sign_in_failures": 0,
+        })
+    );
+}
+
+#[tokio::test]
+async fn maintenance_report_needs_the_admin_token() {
+    // Arrange
+    let (state, _) = authorized_state().await;
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/admin/maintenance", &state.app_address))
+        .bearer_auth(state.token.access_token.as_deref().unwrap())
+        .send()
+        .await
+        .expect("request to admin api failed");
+
+    // Assert
+    assert_eq!(
+        response.status().as_u16(),
+        404,
+        "The admin API is off without an admin token"
+    );
+}
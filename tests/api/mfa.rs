@@ -0,0 +1,314 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{assert_is_redirect_to, csrf_token, spawn_app, ClientType, TestState};
+
+/// An authenticator app that was set up with the secret from the enrollment page.
+pub struct Authenticator {
+    totp: TOTP,
+}
+
+impl Authenticator {
+    /// The code shown `steps` time steps from now.
+    fn code(&self, steps: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.totp.generate((now as i64 + steps * 30) as u64)
+    }
+}
+
+pub struct Enrollment {
+    authenticator: Authenticator,
+    /// The code that confirmed the enrollment.
+    code: String,
+    recovery_codes: Vec<String>,
+}
+
+/// Enroll an authenticator for the signed in owner.
+pub async fn enroll(state: &TestState) -> Enrollment {
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/account/totp", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<svg"), "The enrollment page shows a QR code");
+    assert!(
+        body.contains("otpauth://totp/"),
+        "The enrollment page links the otpauth URI"
+    );
+    let secret = Regex::new("<code>([A-Z2-7]+)</code>")
+        .unwrap()
+        .captures(&body)
+        .unwrap()[1]
+        .to_string();
+    let authenticator = Authenticator {
+        totp: TOTP::new_unchecked(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            Secret::Encoded(secret).to_bytes().unwrap(),
+            None,
+            String::new(),
+        ),
+    };
+
+    let code = authenticator.code(0);
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/account/totp", state.app_address))
+        .form(&[("code", code.as_str()), ("csrf_token", &csrf_token(&body))])
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(
+        response.status().as_u16(),
+        200,
+        "The code confirms enrollment"
+    );
+    let body = response.text().await.unwrap();
+    let recovery_codes = Regex::new("<li><code>([a-z0-9-]+)</code></li>")
+        .unwrap()
+        .captures_iter(&body)
+        .map(|caps| caps[1].to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(recovery_codes.len(), 10, "Recovery codes are shown once");
+
+    Enrollment {
+        authenticator,
+        code,
+        recovery_codes,
+    }
+}
+
+pub async fn sign_out(state: &TestState) {
+    let form = serde_json::json!({ "csrf_token": state.get_csrf_token("signout").await });
+    state
+        .api_client
+        .post(format!("{}/oauth/signout", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+}
+
+/// Enter the password, which leads to the second step.
+pub async fn enter_password(state: &TestState) {
+    let form = serde_json::json!({
+        "username": "bob",
+        "password": "secret",
+        "csrf_token": state.get_csrf_token("signin").await,
+    });
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/signin", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_is_redirect_to(&response, 303, "/oauth/signin/totp?", true);
+}
+
+async fn enter_code(state: &TestState, code: &str) -> reqwest::Response {
+    let form = serde_json::json!({
+        "code": code,
+        "csrf_token": state.get_csrf_token("signin/totp").await,
+    });
+    state
+        .api_client
+        .post(format!("{}/oauth/signin/totp", state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+pub async fn is_signed_in(state: &TestState) -> bool {
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/account/totp", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    response.status().is_success()
+}
+
+#[tokio::test]
+async fn signin_requires_code_after_enrollment() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+    let enrollment = enroll(&state).await;
+    sign_out(&state).await;
+
+    // Act
+    enter_password(&state).await;
+    assert!(
+        !is_signed_in(&state).await,
+        "The password alone doesn't sign the owner in"
+    );
+    let replayed = enter_code(&state, &enrollment.code).await;
+    let response = enter_code(&state, &enrollment.authenticator.code(1)).await;
+
+    // Assert
+    assert_eq!(
+        replayed.status().as_u16(),
+        401,
+        "The code used for enrollment can't be replayed"
+    );
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+    assert!(is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn second_step_requires_password() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+    let enrollment = enroll(&state).await;
+    sign_out(&state).await;
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/signin/totp", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_is_redirect_to(&response, 303, "/oauth/signin?", true);
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/signin/totp", state.app_address))
+        .form(&[
+            ("code", enrollment.authenticator.code(1).as_str()),
+            ("csrf_token", &state.get_csrf_token("signin").await),
+        ])
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_is_redirect_to(&response, 303, "/oauth/signin?", true);
+    assert!(!is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+    let enrollment = enroll(&state).await;
+    let code = enrollment.recovery_codes[3].to_uppercase();
+    sign_out(&state).await;
+
+    // Act
+    enter_password(&state).await;
+    let response = enter_code(&state, &code).await;
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+    sign_out(&state).await;
+    enter_password(&state).await;
+    let response = enter_code(&state, &code).await;
+
+    // Assert
+    assert_eq!(
+        response.status().as_u16(),
+        401,
+        "A used recovery code is rejected"
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Wrong code."));
+}
+
+#[tokio::test]
+async fn wrong_codes_lock_the_account() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+    let enrollment = enroll(&state).await;
+    sign_out(&state).await;
+    enter_password(&state).await;
+
+    // Act
+    for _ in 0..4 {
+        let response = enter_code(&state, "000000").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = enter_code(&state, "000000").await;
+    let locked = enter_code(&state, &enrollment.authenticator.code(1)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(
+        locked.status().as_u16(),
+        429,
+        "The right code is rejected while locked out"
+    );
+    assert!(!is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn tokens_report_how_the_owner_signed_in() {
+    // Arrange
+    let mut state = spawn_app().await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    // Once authorized, clients skip the consent page, which the flow expects
+    let other_client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+    state.authorization_flow(&client).await;
+    let password_token = state.token.access_token.clone().unwrap();
+    let enrollment = enroll(&state).await;
+    sign_out(&state).await;
+    enter_password(&state).await;
+    let response = enter_code(&state, &enrollment.authenticator.code(1)).await;
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+
+    // Act
+    state.authorization_flow(&other_client).await;
+    let mfa_token = state.token.access_token.clone().unwrap();
+
+    // Assert
+    state
+        .access_resource_success(&password_token, r#""amr":["pwd"],"acr":"1""#)
+        .await;
+    state
+        .access_resource_success(&mfa_token, r#""amr":["pwd","otp","mfa"],"acr":"2""#)
+        .await;
+}
+
+#[tokio::test]
+async fn signin_returns_to_the_enrollment_pages() {
+    for page in ["/oauth/account/totp", "/oauth/account/passkeys"] {
+        // Arrange
+        let state = spawn_app().await;
+
+        // Act
+        let location = state.signin_from(page, "bob", "secret").await;
+
+        // Assert
+        assert_eq!(location, page);
+        let response = state
+            .api_client
+            .get(format!("{}{}", &state.app_address, location))
+            .send()
+            .await
+            .expect("request to server api failed");
+        assert_eq!(response.status().as_u16(), 200, "{page}");
+    }
+}
@@ -0,0 +1,390 @@
+use axum_oauth::oauth::{
+    settings::{Settings, WebauthnSettings},
+    webauthn,
+};
+use regex::Regex;
+use reqwest::Method;
+use serde_json::Value;
+use url::Url;
+use webauthn_authenticator_rs::{
+    prelude::WebauthnCError, softtoken::SoftToken, AuthenticatorBackend, WebauthnAuthenticator,
+};
+use webauthn_rs_core::proto::{
+    AllowCredentials, CreationChallengeResponse, PublicKeyCredential,
+    PublicKeyCredentialCreationOptions, PublicKeyCredentialRequestOptions,
+    RegisterPublicKeyCredential, RequestChallengeResponse,
+};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_settings, TestState},
+    mfa::{enroll, enter_password, is_signed_in, sign_out},
+};
+
+/// A software authenticator, and the browser that runs its ceremonies.
+struct Authenticator {
+    token: SoftToken,
+    /// The origin that the browser reports.
+    origin: Url,
+    /// The credential it registered. The token can't store discoverable credentials, so the
+    /// browser names it when the server doesn't, as if the user picked it.
+    credential_id: Option<Vec<u8>>,
+}
+
+impl Authenticator {
+    /// `verifies_user` is whether it verifies the user, e.g. with a PIN, when asked to, or only
+    /// ever checks that they're present.
+    fn new(state: &TestState, verifies_user: bool) -> Self {
+        let (token, _) = SoftToken::new(verifies_user).unwrap();
+        Self {
+            token,
+            origin: Url::parse(&state.app_address).unwrap(),
+            credential_id: None,
+        }
+    }
+
+    /// Another authenticator holding the same keys and counter.
+    fn copy(&self) -> Self {
+        Self {
+            token: SoftToken::from_cbor(&self.token.to_cbor().unwrap()).unwrap(),
+            origin: self.origin.clone(),
+            credential_id: self.credential_id.clone(),
+        }
+    }
+
+    /// Respond to `navigator.credentials.create()`.
+    fn create(&mut self, options: &Value) -> Value {
+        let public_key: PublicKeyCredentialCreationOptions =
+            serde_json::from_value(options.clone()).unwrap();
+        let origin = self.origin.clone();
+        let credential = WebauthnAuthenticator::new(Lent(&mut self.token))
+            .do_registration(origin, CreationChallengeResponse { public_key })
+            .unwrap();
+        self.credential_id = Some(credential.raw_id.to_vec());
+
+        serde_json::to_value(credential).unwrap()
+    }
+
+    /// Respond to `navigator.credentials.get()`.
+    fn get(&mut self, options: &Value) -> Value {
+        let mut public_key: PublicKeyCredentialRequestOptions =
+            serde_json::from_value(options.clone()).unwrap();
+        if public_key.allow_credentials.is_empty() {
+            public_key.allow_credentials = self
+                .credential_id
+                .iter()
+                .map(|id| AllowCredentials {
+                    type_: "public-key".to_string(),
+                    id: id.clone().into(),
+                    transports: None,
+                })
+                .collect();
+        }
+        let origin = self.origin.clone();
+        let options = RequestChallengeResponse {
+            public_key,
+            mediation: None,
+        };
+        let credential = WebauthnAuthenticator::new(Lent(&mut self.token))
+            .do_authentication(origin, options)
+            .unwrap();
+
+        serde_json::to_value(credential).unwrap()
+    }
+}
+
+/// Lends the token to the browser for one ceremony.
+struct Lent<'a>(&'a mut SoftToken);
+
+impl AuthenticatorBackend for Lent<'_> {
+    fn perform_register(
+        &mut self,
+        origin: Url,
+        options: PublicKeyCredentialCreationOptions,
+        timeout_ms: u32,
+    ) -> Result<RegisterPublicKeyCredential, WebauthnCError> {
+        AuthenticatorBackend::perform_register(self.0, origin, options, timeout_ms)
+    }
+
+    fn perform_auth(
+        &mut self,
+        origin: Url,
+        options: PublicKeyCredentialRequestOptions,
+        timeout_ms: u32,
+    ) -> Result<PublicKeyCredential, WebauthnCError> {
+        AuthenticatorBackend::perform_auth(self.0, origin, options, timeout_ms)
+    }
+}
+
+async fn post_json(state: &TestState, path: &str, body: &Value) -> reqwest::Response {
+    state
+        .api_client
+        .post(format!("{}/oauth/{}", state.app_address, path))
+        .json(body)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn request_options(state: &TestState, path: &str) -> Value {
+    let response = post_json(state, path, &Value::Null).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response.json().await.unwrap()
+}
+
+/// The CSRF token that the passkey page hands its script.
+async fn page_csrf_token(state: &TestState) -> String {
+    let body = state
+        .api_client
+        .get(format!("{}/oauth/account/passkeys", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    let re_token = Regex::new("data-csrf-token=\"([^\"]+)\"").unwrap();
+
+    re_token.captures(&body).expect("the page has a token")[1].to_owned()
+}
+
+/// A request of the passkey page's script, with the CSRF token in the header.
+async fn script_request(
+    state: &TestState,
+    method: Method,
+    path: &str,
+    body: &Value,
+    csrf_token: &str,
+) -> reqwest::Response {
+    state
+        .api_client
+        .request(method, format!("{}/oauth/{}", state.app_address, path))
+        .header("X-CSRF-Token", csrf_token)
+        .json(body)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+/// The token for the script's next request.
+fn next_csrf_token(response: &reqwest::Response) -> String {
+    response.headers()["x-csrf-token"]
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+async fn register_passkey(
+    state: &TestState,
+    authenticator: &mut Authenticator,
+) -> reqwest::Response {
+    let csrf_token = page_csrf_token(state).await;
+    let response = script_request(
+        state,
+        Method::POST,
+        "account/passkeys/options",
+        &Value::Null,
+        &csrf_token,
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let csrf_token = next_csrf_token(&response);
+    let options = response.json().await.unwrap();
+    let credential = authenticator.create(&options);
+    let body = serde_json::json!({ "name": "My laptop", "credential": credential });
+
+    script_request(state, Method::POST, "account/passkeys", &body, &csrf_token).await
+}
+
+async fn sign_in_with_passkey(
+    state: &TestState,
+    authenticator: &mut Authenticator,
+) -> reqwest::Response {
+    let options = request_options(state, "signin/passkey/options").await;
+    let credential = authenticator.get(&options);
+
+    post_json(state, "signin/passkey", &credential).await
+}
+
+#[tokio::test]
+async fn passkey_signs_in_without_password() {
+    // Arrange
+    let state = spawn_app().await;
+    let mut authenticator = Authenticator::new(&state, true);
+    state.signin("bob", "secret").await;
+    let response = register_passkey(&state, &mut authenticator).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body = state
+        .api_client
+        .get(format!("{}/oauth/account/passkeys", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("My laptop"), "The passkey is listed");
+    sign_out(&state).await;
+
+    // Act
+    let response = sign_in_with_passkey(&state, &mut authenticator).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(body["location"], "/oauth/");
+    assert!(is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn passwordless_sign_in_requires_user_verification() {
+    // Arrange
+    let state = spawn_app().await;
+    let mut authenticator = Authenticator::new(&state, false);
+    state.signin("bob", "secret").await;
+    let response = register_passkey(&state, &mut authenticator).await;
+    assert_eq!(
+        response.status().as_u16(),
+        201,
+        "Presence is enough to register"
+    );
+    sign_out(&state).await;
+
+    // Act
+    let response = sign_in_with_passkey(&state, &mut authenticator).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(!is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn forged_replayed_and_cloned_assertions_are_rejected() {
+    // Arrange
+    let state = spawn_app().await;
+    let mut authenticator = Authenticator::new(&state, true);
+    let mut forger = Authenticator::new(&state, true);
+    state.signin("bob", "secret").await;
+    register_passkey(&state, &mut authenticator).await;
+    register_passkey(&state, &mut forger).await;
+    let mut clone = authenticator.copy();
+    sign_out(&state).await;
+
+    // Act
+    let options = request_options(&state, "signin/passkey/options").await;
+    let mut credential = forger.get(&options);
+    let victim = webauthn::encode(authenticator.credential_id.as_deref().unwrap());
+    credential["id"] = victim.clone().into();
+    credential["rawId"] = victim.into();
+    let forged = post_json(&state, "signin/passkey", &credential).await;
+    let options = request_options(&state, "signin/passkey/options").await;
+    let credential = authenticator.get(&options);
+    let response = post_json(&state, "signin/passkey", &credential).await;
+    assert_eq!(response.status().as_u16(), 200);
+    sign_out(&state).await;
+    request_options(&state, "signin/passkey/options").await;
+    let replayed = post_json(&state, "signin/passkey", &credential).await;
+    let cloned = sign_in_with_passkey(&state, &mut clone).await;
+
+    // Assert
+    assert_eq!(
+        forged.status().as_u16(),
+        401,
+        "A signature by another key is rejected"
+    );
+    assert_eq!(
+        replayed.status().as_u16(),
+        401,
+        "An assertion for an earlier challenge is rejected"
+    );
+    assert_eq!(
+        cloned.status().as_u16(),
+        401,
+        "An assertion whose counter didn't go up is rejected"
+    );
+    assert!(!is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn passkeys_are_managed_with_the_csrf_token() {
+    // Arrange
+    let state = spawn_app().await;
+    let mut authenticator = Authenticator::new(&state, true);
+    state.signin("bob", "secret").await;
+    let response = register_passkey(&state, &mut authenticator).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let csrf_token = next_csrf_token(&response);
+    let passkey: Value = response.json().await.unwrap();
+    let path = format!("account/passkeys/{}", passkey["id"].as_str().unwrap());
+
+    // Act
+    let options = post_json(&state, "account/passkeys/options", &Value::Null).await;
+    let forged = state
+        .api_client
+        .delete(format!("{}/oauth/{}", state.app_address, path))
+        .send()
+        .await
+        .expect("request to server api failed");
+    let removed = script_request(&state, Method::DELETE, &path, &Value::Null, &csrf_token).await;
+    let replayed = script_request(&state, Method::DELETE, &path, &Value::Null, &csrf_token).await;
+
+    // Assert
+    assert_eq!(options.status().as_u16(), 403);
+    assert_eq!(forged.status().as_u16(), 403);
+    assert_eq!(removed.status().as_u16(), 204);
+    assert_eq!(replayed.status().as_u16(), 403, "Tokens only work once");
+}
+
+#[tokio::test]
+async fn registration_from_another_origin_is_rejected() {
+    // Arrange
+    let state = spawn_app_with_settings(Settings {
+        webauthn: WebauthnSettings {
+            origins: vec![Url::parse("https://accounts.localhost").unwrap()],
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+    let mut authenticator = Authenticator::new(&state, true);
+    state.signin("bob", "secret").await;
+
+    // Act
+    let response = register_passkey(&state, &mut authenticator).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn passkey_stands_in_for_authenticator_code() {
+    // Arrange
+    let state = spawn_app().await;
+    // After the password, presence is enough
+    let mut authenticator = Authenticator::new(&state, false);
+    state.signin("bob", "secret").await;
+    register_passkey(&state, &mut authenticator).await;
+    enroll(&state).await;
+    sign_out(&state).await;
+    enter_password(&state).await;
+    let page = state
+        .api_client
+        .get(format!("{}/oauth/signin/totp", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        page.contains(r#"data-passkey="signin""#),
+        "The second step offers the passkey"
+    );
+
+    // Act
+    let response = sign_in_with_passkey(&state, &mut authenticator).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_signed_in(&state).await);
+}
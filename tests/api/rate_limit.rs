@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::settings::{Limit, RateLimitSettings, RouteLimits, Settings};
+use serde_json::Value;
+
+use crate::helpers::{spawn_app_with_settings, ClientResponse, ClientType, TestState};
+
+async fn spawn_app_with_rate_limit(rate_limit: RateLimitSettings) -> TestState {
+    spawn_app_with_settings(Settings {
+        rate_limit,
+        ..Default::default()
+    })
+    .await
+}
+
+fn burst(burst: u32) -> Limit {
+    Limit {
+        burst,
+        period: Duration::from_secs(60),
+    }
+}
+
+async fn request_token(state: &TestState, client: Option<&ClientResponse>) -> reqwest::Response {
+    let request = state
+        .api_client
+        .post(format!("{}/oauth/token", state.app_address));
+    let request = match client {
+        Some(client) => request.basic_auth(&client.client_id, client.client_secret.as_ref()),
+        None => request,
+    };
+
+    request
+        .form(&[("grant_type", "authorization_code"), ("code", "unknown")])
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+async fn assert_rate_limited(response: reqwest::Response, msg: &str) {
+    assert_eq!(response.status().as_u16(), 429, "{}", msg);
+    let retry_after = response
+        .headers()
+        .get("Retry-After")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .expect("Rate limited responses tell the client when to retry");
+    assert!(retry_after > 0 && retry_after <= 60);
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(body["error"], "temporarily_unavailable");
+    assert!(body["error_description"].is_string());
+}
+
+#[tokio::test]
+async fn registration_is_limited_per_address() {
+    // Arrange
+    let state = spawn_app_with_rate_limit(RateLimitSettings {
+        registration: RouteLimits::new(burst(2)),
+        ..Default::default()
+    })
+    .await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+    });
+
+    // Act
+    for _ in 0..2 {
+        state.register_client(&params, ClientType::Public).await;
+    }
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/client", state.app_address))
+        .form(&params)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_rate_limited(response, "Registrations past the burst are rejected").await;
+}
+
+#[tokio::test]
+async fn token_requests_without_credentials_are_limited_per_address() {
+    // Arrange
+    let state = spawn_app_with_rate_limit(RateLimitSettings {
+        token: RouteLimits::new(burst(3)),
+        ..Default::default()
+    })
+    .await;
+
+    // Act
+    for _ in 0..3 {
+        let response = request_token(&state, None).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Requests within the burst reach the token endpoint"
+        );
+    }
+    let response = request_token(&state, None).await;
+
+    // Assert
+    assert_rate_limited(response, "Token requests past the burst are rejected").await;
+}
+
+#[tokio::test]
+async fn authenticated_clients_are_limited_apart_from_their_address() {
+    // Arrange
+    let state = spawn_app_with_rate_limit(RateLimitSettings {
+        token: RouteLimits::new(burst(2)),
+        ..Default::default()
+    })
+    .await;
+    let client = register_confidential_client(&state).await;
+    let impostor = ClientResponse {
+        client_id: client.client_id.clone(),
+        client_secret: Some("wrong".to_string()),
+    };
+
+    // Act
+    for _ in 0..2 {
+        let response = request_token(&state, Some(&client)).await;
+        assert_ne!(
+            response.status().as_u16(),
+            429,
+            "The client has requests left"
+        );
+    }
+    let response = request_token(&state, Some(&client)).await;
+    assert_rate_limited(response, "The client is out of requests").await;
+
+    // Assert
+    let response = request_token(&state, Some(&impostor)).await;
+    assert_ne!(
+        response.status().as_u16(),
+        429,
+        "Invalid credentials are counted against the address, which has requests left"
+    );
+    let response = request_token(&state, None).await;
+    assert_ne!(response.status().as_u16(), 429);
+    let response = request_token(&state, Some(&impostor)).await;
+    assert_rate_limited(response, "The address is out of requests").await;
+}
+
+async fn register_confidential_client(state: &TestState) -> ClientResponse {
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    state
+        .register_client(&params, ClientType::Confidential)
+        .await
+}
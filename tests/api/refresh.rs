@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use axum_oauth::oauth::settings::{Settings, TokenLifetimes};
+
+use crate::{
+    apps::authorize_another_client,
+    helpers::{spawn_app, spawn_app_with_admin, ClientType, Token, ADMIN_TOKEN},
+    user::{authorized_state, authorized_state_with, get_user, refresh},
+};
+
+#[tokio::test]
+async fn refreshing_rotates_the_refresh_token() {
+    // Arrange
+    let (state, client) = authorized_state().await;
+    let issued = state.token.clone();
+
+    // Act
+    let response = refresh(&state, &client, &issued).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let rotated: Token = response.json().await.expect("failed to parse token");
+    assert!(rotated.refresh_token.is_some());
+    assert_ne!(
+        rotated.refresh_token, issued.refresh_token,
+        "Refreshing issues a new refresh token"
+    );
+    assert_eq!(
+        refresh(&state, &client, &rotated).await.status().as_u16(),
+        200,
+        "The new refresh token can be used in turn"
+    );
+}
+
+#[tokio::test]
+async fn reusing_a_rotated_refresh_token_revokes_its_family() {
+    // Arrange
+    let (mut state, client) = authorized_state().await;
+    let issued = state.token.clone();
+    let response = refresh(&state, &client, &issued).await;
+    let rotated: Token = response.json().await.expect("failed to parse token");
+    let (_, other) = authorize_another_client(&mut state).await;
+
+    // Act
+    let response = refresh(&state, &client, &issued).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+    assert_eq!(
+        get_user(&state, rotated.access_token.as_deref().unwrap())
+            .await
+            .status()
+            .as_u16(),
+        401,
+        "The access token of the family is revoked"
+    );
+    assert_ne!(
+        refresh(&state, &client, &rotated).await.status().as_u16(),
+        200,
+        "The current refresh token of the family is revoked"
+    );
+    assert_eq!(
+        get_user(&state, &other).await.status().as_u16(),
+        200,
+        "Tokens of other grants still work"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_refreshes_with_one_token_are_a_reuse() {
+    // Arrange
+    let (state, client) = authorized_state().await;
+    let issued = state.token.clone();
+
+    // Act
+    let responses =
+        futures::future::join_all((0..8).map(|_| refresh(&state, &client, &issued))).await;
+
+    // Assert
+    let (winners, losers): (Vec<_>, Vec<_>) = responses
+        .into_iter()
+        .partition(|response| response.status().as_u16() == 200);
+    assert_eq!(winners.len(), 1, "One refresh wins");
+    for response in losers {
+        assert_eq!(response.status().as_u16(), 400, "The others are refused");
+        let error: serde_json::Value = response.json().await.expect("failed to parse error");
+        assert_eq!(error["error"], "invalid_grant");
+    }
+    let rotated: Token = winners
+        .into_iter()
+        .next()
+        .unwrap()
+        .json()
+        .await
+        .expect("failed to parse token");
+    assert_ne!(
+        refresh(&state, &client, &rotated).await.status().as_u16(),
+        200,
+        "The family is revoked, as for any other reuse"
+    );
+}
+
+#[tokio::test]
+async fn refresh_tokens_are_only_issued_for_offline_access() {
+    // Arrange
+    let mut state = spawn_app().await;
+    let client = state
+        .register_client(&client_params(), ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+
+    // Act
+    state
+        .authorization_flow_with_scope(&client, "account:read account:write")
+        .await;
+
+    // Assert
+    assert!(state.token.access_token.is_some());
+    assert!(
+        state.token.refresh_token.is_none(),
+        "No refresh token without offline_access"
+    );
+}
+
+#[tokio::test]
+async fn clients_with_offline_access_get_refresh_tokens_without_asking() {
+    // Arrange
+    let mut state = spawn_app_with_admin(Settings::default()).await;
+    let client = state
+        .register_client(&client_params(), ClientType::Confidential)
+        .await;
+    let response = state
+        .api_client
+        .patch(format!(
+            "{}/oauth/admin/clients/{}",
+            &state.app_address, client.client_id
+        ))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&serde_json::json!({ "offline_access": true }))
+        .send()
+        .await
+        .expect("request to admin api failed");
+    assert_eq!(response.status().as_u16(), 200);
+    state.signin("bob", "secret").await;
+
+    // Act
+    state
+        .authorization_flow_with_scope(&client, "account:read account:write")
+        .await;
+
+    // Assert
+    assert!(state.token.refresh_token.is_some());
+}
+
+#[tokio::test]
+async fn refreshing_keeps_the_family_alive_past_the_idle_lifetime() {
+    // Arrange
+    let (state, client) = authorized_state_with(Settings {
+        lifetimes: TokenLifetimes {
+            refresh_token: Duration::from_secs(3),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+    let mut token = state.token.clone();
+
+    // Act
+    for _ in 0..2 {
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        let response = refresh(&state, &client, &token).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "The refresh token is used in time"
+        );
+        token = response.json().await.expect("failed to parse token");
+    }
+}
+
+#[tokio::test]
+async fn refresh_token_families_expire_at_their_maximum_lifetime() {
+    // Arrange
+    let (state, client) = authorized_state_with(Settings {
+        lifetimes: TokenLifetimes {
+            refresh_token_max: Duration::from_secs(3),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let response = refresh(&state, &client, &state.token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let rotated: Token = response.json().await.expect("failed to parse token");
+    tokio::time::sleep(Duration::from_millis(2200)).await;
+
+    // Act
+    let response = refresh(&state, &client, &rotated).await;
+
+    // Assert
+    assert_eq!(
+        response.status().as_u16(),
+        400,
+        "Refreshing doesn't extend the family"
+    );
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+}
+
+fn client_params() -> serde_json::Value {
+    serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    })
+}
@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, csrf_token, spawn_app, ClientType, CODE_CHALLENGE};
 
 #[tokio::test]
 async fn signin_form_fields_problem() {
@@ -24,7 +24,7 @@ async fn signin_form_fields_problem() {
     for (case, msg) in invalid_cases {
         // Act
         let response = client
-            .post(&format!("{}/oauth/signin", &test_state.app_address))
+            .post(format!("{}/oauth/signin", &test_state.app_address))
             .form(&case)
             .send()
             .await
@@ -44,7 +44,7 @@ async fn signin_form_fields_problem() {
 async fn signin_form_wrong_credentials() {
     // Arrange
     let test_state = spawn_app().await;
-    let client = test_state.api_client;
+    let client = &test_state.api_client;
     let invalid_cases = [
         (
             serde_json::json!({
@@ -62,10 +62,12 @@ async fn signin_form_wrong_credentials() {
         ),
     ];
 
-    for (case, msg) in invalid_cases {
+    for (mut case, msg) in invalid_cases {
+        case["csrf_token"] = test_state.get_csrf_token("signin").await.into();
+
         // Act
         let response = client
-            .post(&format!("{}/oauth/signin", &test_state.app_address))
+            .post(format!("{}/oauth/signin", &test_state.app_address))
             .form(&case)
             .send()
             .await
@@ -86,15 +88,16 @@ async fn signin_form_wrong_credentials() {
 async fn happy_path_signin_form() {
     // Arrange
     let test_state = spawn_app().await;
-    let client = test_state.api_client;
+    let client = &test_state.api_client;
     let form = serde_json::json!({
         "username": "bob",
         "password": "secret",
+        "csrf_token": test_state.get_csrf_token("signin").await,
     });
 
     // Act
     let response = client
-        .post(&format!("{}/oauth/signin", &test_state.app_address))
+        .post(format!("{}/oauth/signin", &test_state.app_address))
         .form(&form)
         .send()
         .await
@@ -108,3 +111,326 @@ async fn happy_path_signin_form() {
     );
     assert_is_redirect_to(&response, 303, "/oauth/", false);
 }
+
+#[tokio::test]
+async fn signin_page_follows_accept_language() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let client = test_state.api_client;
+
+    // Act
+    let response = client
+        .get(format!("{}/oauth/signin", &test_state.app_address))
+        .header("Accept-Language", "de-CH, fr;q=0.8, en;q=0.5")
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(r#"<html lang="de""#),
+        "Page language is German"
+    );
+    assert!(
+        body.contains("Mit einem bestehenden Konto anmelden"),
+        "Sign in page is translated"
+    );
+}
+
+#[tokio::test]
+async fn signin_form_requires_csrf_token() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let client = &test_state.api_client;
+    let other_session = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let foreign_token = other_session
+        .get(format!("{}/oauth/signin", &test_state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed")
+        .text()
+        .await
+        .map(|body| csrf_token(&body))
+        .unwrap();
+    let used_token = test_state.get_csrf_token("signin").await;
+    let response = client
+        .post(format!("{}/oauth/signin", &test_state.app_address))
+        .form(&serde_json::json!({
+            "username": "bob",
+            "password": "not_my_secret",
+            "csrf_token": used_token,
+        }))
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(response.status().as_u16(), 401);
+    let invalid_cases = [
+        (None, "missing token"),
+        (Some("forged".to_string()), "unknown token"),
+        (Some(foreign_token), "token of another session"),
+        (Some(used_token), "token that was already used"),
+    ];
+
+    for (token, msg) in invalid_cases {
+        // Act
+        let mut form = serde_json::json!({
+            "username": "bob",
+            "password": "secret",
+        });
+        if let Some(token) = token {
+            form["csrf_token"] = token.into();
+        }
+        let response = client
+            .post(format!("{}/oauth/signin", &test_state.app_address))
+            .form(&form)
+            .send()
+            .await
+            .expect("request to server api failed");
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "{} returns 403 Forbidden",
+            msg
+        );
+    }
+}
+
+fn session_cookie(response: &reqwest::Response) -> Option<String> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == "axum_oauth")
+        .map(|cookie| cookie.value().to_owned())
+}
+
+#[tokio::test]
+async fn failed_signin_grants_no_access() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let client = &test_state.api_client;
+    let form = serde_json::json!({
+        "username": "bob",
+        "password": "not_my_secret",
+        "csrf_token": test_state.get_csrf_token("signin").await,
+    });
+    let response = client
+        .post(format!("{}/oauth/signin", &test_state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Act
+    let response = client
+        .get(format!("{}/oauth/authorize", &test_state.app_address))
+        .query(&[
+            ("response_type", "code"),
+            ("client_id", "LocalClient"),
+            ("scope", "account:read"),
+        ])
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_is_redirect_to(&response, 303, "/oauth/signin?callback=authorize", true);
+}
+
+#[tokio::test]
+async fn signin_regenerates_session_id() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let client = &test_state.api_client;
+    let response = client
+        .get(format!("{}/oauth/signin", &test_state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+    let anonymous_session = session_cookie(&response).expect("the sign in page starts a session");
+    let csrf_token = csrf_token(&response.text().await.unwrap());
+
+    // Act
+    let response = client
+        .post(format!("{}/oauth/signin", &test_state.app_address))
+        .form(&serde_json::json!({
+            "username": "bob",
+            "password": "secret",
+            "csrf_token": csrf_token,
+        }))
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_is_redirect_to(&response, 303, "/oauth/", false);
+    let signed_in_session = session_cookie(&response).expect("signing in sets a session cookie");
+    assert_ne!(
+        anonymous_session, signed_in_session,
+        "Signing in changes the session ID"
+    );
+
+    // The session ID from before signing in is not signed in
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}/oauth/authorize", &test_state.app_address))
+        .header("Cookie", format!("axum_oauth={}", anonymous_session))
+        .query(&[("response_type", "code"), ("client_id", "LocalClient")])
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_is_redirect_to(&response, 303, "/oauth/signin?callback=authorize", true);
+}
+
+#[tokio::test]
+async fn signin_only_redirects_to_callbacks_below_oauth() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let client = &test_state.api_client;
+    let cases = [
+        (
+            "authorize?client_id=foo&scope=a+b",
+            "/oauth/authorize?client_id=foo&scope=a+b",
+        ),
+        (
+            "/oauth/authorize?client_id=foo",
+            "/oauth/authorize?client_id=foo",
+        ),
+        ("client/../authorize", "/oauth/authorize"),
+        ("https://evil.example/oauth/", "/oauth/"),
+        ("//evil.example/oauth/", "/oauth/"),
+        ("/\\evil.example/oauth/", "/oauth/"),
+        ("\\\\evil.example/oauth/", "/oauth/"),
+        ("/\t/evil.example/oauth/", "/oauth/"),
+        ("javascript:alert(1)", "/oauth/"),
+        ("/api/user", "/oauth/"),
+        ("../api/user", "/oauth/"),
+        ("/oauth/../api/user", "/oauth/"),
+        ("/oauth/%2e%2e/api/user", "/oauth/"),
+        ("", "/oauth/"),
+    ];
+
+    for (callback, location) in cases {
+        let form = serde_json::json!({
+            "username": "bob",
+            "password": "secret",
+            "csrf_token": test_state.get_csrf_token("signin").await,
+        });
+
+        // Act
+        let response = client
+            .post(format!("{}/oauth/signin", &test_state.app_address))
+            .query(&[("callback", callback)])
+            .form(&form)
+            .send()
+            .await
+            .expect("request to server api failed");
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 303, "callback {:?}", callback);
+        assert_eq!(
+            response.headers().get("Location").unwrap(),
+            location,
+            "callback {:?} redirects to {}",
+            callback,
+            location
+        );
+    }
+}
+
+#[tokio::test]
+async fn failed_signin_keeps_callback() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let form = serde_json::json!({
+        "username": "bob",
+        "password": "not_my_secret",
+        "csrf_token": test_state.get_csrf_token("signin").await,
+    });
+
+    // Act
+    let response = test_state
+        .api_client
+        .post(format!("{}/oauth/signin", &test_state.app_address))
+        .query(&[("callback", "authorize?client_id=foo")])
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(r#"formaction="signin?callback=authorize%3Fclient_id%3Dfoo""#),
+        "The sign in form posts the callback again"
+    );
+}
+
+#[tokio::test]
+async fn signin_username_is_case_insensitive() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("BOB", "secret").await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+    });
+    let client = state.register_client(&params, ClientType::Public).await;
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": client.client_id,
+        "scope": "account:read",
+        "state": "12345",
+        "code_challenge": CODE_CHALLENGE,
+        "code_challenge_method": "S256",
+    });
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .query(&query)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(
+        page.contains("bob") && !page.contains("BOB"),
+        "The owner is signed in with the username as registered"
+    );
+}
+
+#[tokio::test]
+async fn signin_returns_to_nested_pages() {
+    // Arrange
+    let test_state = spawn_app().await;
+
+    // Act
+    let location = test_state
+        .signin_from("/oauth/account/apps", "bob", "secret")
+        .await;
+
+    // Assert
+    assert_eq!(location, "/oauth/account/apps");
+    let response = test_state
+        .api_client
+        .get(format!("{}{}", &test_state.app_address, location))
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,4 +1,7 @@
-use crate::helpers::{spawn_app, ClientType};
+use crate::{
+    helpers::{spawn_app, ClientType},
+    mfa::is_signed_in,
+};
 
 #[tokio::test]
 async fn signout_ok() {
@@ -15,11 +18,14 @@ async fn signout_ok() {
     state.signin("bob", "secret").await;
     state.authorization_flow(&client_id).await;
 
+    let form = serde_json::json!({ "csrf_token": state.get_csrf_token("signout").await });
+
     // Act
-    let client = state.api_client;
-    let response = client
-        .post(&format!("{}/oauth/signout/", &state.app_address))
-        .bearer_auth(state.token.access_token.unwrap())
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/signout", &state.app_address))
+        .bearer_auth(state.token.access_token.as_deref().unwrap())
+        .form(&form)
         .send()
         .await
         .expect("request to client api failed");
@@ -30,4 +36,28 @@ async fn signout_ok() {
         200,
         "signout from session returns 200 Ok"
     );
+    assert!(!is_signed_in(&state).await);
+}
+
+#[tokio::test]
+async fn signout_requires_the_form_token() {
+    // Arrange
+    let state = spawn_app().await;
+    state.signin("bob", "secret").await;
+
+    // Act
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/signout", &state.app_address))
+        .form(&serde_json::json!({}))
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(
+        is_signed_in(&state).await,
+        "Other sites can't sign the owner out"
+    );
 }
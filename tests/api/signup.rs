@@ -1,4 +1,12 @@
-use crate::helpers::spawn_app;
+use std::io::Write;
+
+use axum_oauth::oauth::{
+    database::{resource::user::HashedPassword, Database, StoreError},
+    settings::{Settings, SignupSettings},
+};
+use secrecy::Secret;
+
+use crate::helpers::{spawn_app, spawn_app_with_settings, TestState};
 
 #[tokio::test]
 async fn signup_form_fields_problem() {
@@ -44,7 +52,7 @@ async fn signup_form_fields_problem() {
     for (case, msg) in invalid_cases {
         // Act
         let response = client
-            .post(&format!("{}/oauth/signup", &test_state.app_address))
+            .post(format!("{}/oauth/signup", &test_state.app_address))
             .form(&case)
             .send()
             .await
@@ -64,16 +72,18 @@ async fn signup_form_fields_problem() {
 async fn signup_existing_user() {
     // Arrange
     let test_state = spawn_app().await;
-    let client = test_state.api_client;
+    let client = &test_state.api_client;
     let form = serde_json::json!({
         "username": "bob",
-        "password": "secret",
+        "email": "robert@example.com",
+        "password": "correct horse battery",
         "given_name": "Robert",
+        "csrf_token": test_state.get_csrf_token("signup").await,
     });
 
     // Act
     let response = client
-        .post(&format!("{}/oauth/signup", &test_state.app_address))
+        .post(format!("{}/oauth/signup", &test_state.app_address))
         .form(&form)
         .send()
         .await
@@ -91,16 +101,18 @@ async fn signup_existing_user() {
 async fn happy_path_signup_form() {
     // Arrange
     let test_state = spawn_app().await;
-    let client = test_state.api_client;
+    let client = &test_state.api_client;
     let form = serde_json::json!({
         "username": "alice",
-        "password": "secret",
+        "email": "alice@example.com",
+        "password": "correct horse battery",
         "given_name": "Alice",
+        "csrf_token": test_state.get_csrf_token("signup").await,
     });
 
     // Act
     let response = client
-        .post(&format!("{}/oauth/signup", &test_state.app_address))
+        .post(format!("{}/oauth/signup", &test_state.app_address))
         .form(&form)
         .send()
         .await
@@ -113,3 +125,200 @@ async fn happy_path_signup_form() {
         "successful signup returns 201 Created",
     );
 }
+
+#[tokio::test]
+async fn signup_form_requires_csrf_token() {
+    // Arrange
+    let test_state = spawn_app().await;
+    let form = serde_json::json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "correct horse battery",
+        "given_name": "Alice",
+    });
+
+    // Act
+    let response = test_state
+        .api_client
+        .post(format!("{}/oauth/signup", &test_state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    // Assert
+    assert_eq!(
+        response.status().as_u16(),
+        403,
+        "signup without a CSRF token returns 403 Forbidden",
+    );
+}
+
+async fn sign_up(state: &TestState, form: serde_json::Value) -> reqwest::Response {
+    let mut form = form;
+    form["csrf_token"] = state.get_csrf_token("signup").await.into();
+    state
+        .api_client
+        .post(format!("{}/oauth/signup", &state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed")
+}
+
+/// The codes of the field errors in a response, by field.
+async fn field_errors(response: reqwest::Response) -> Vec<(String, String)> {
+    let body: serde_json::Value = response.json().await.expect("a JSON body");
+    body["errors"]
+        .as_array()
+        .expect("a list of errors")
+        .iter()
+        .map(|error| {
+            (
+                error["field"].as_str().unwrap().to_owned(),
+                error["code"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn signup_reports_every_field_error() {
+    // Arrange
+    let state = spawn_app().await;
+    let form = serde_json::json!({
+        "username": "al ice",
+        "email": "alice@example.com",
+        "password": "short",
+        "given_name": "  ",
+    });
+
+    // Act
+    let response = sign_up(&state, form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let errors = field_errors(response).await;
+    let expected = [
+        ("username", "invalid-characters"),
+        ("password", "too-short"),
+        ("given_name", "required"),
+    ];
+    for (field, code) in expected {
+        assert!(
+            errors.contains(&(field.to_owned(), code.to_owned())),
+            "{} is reported as {}: {:?}",
+            field,
+            code,
+            errors
+        );
+    }
+}
+
+#[tokio::test]
+async fn signup_rejects_weak_passwords() {
+    // Arrange
+    let state = spawn_app().await;
+    let form = |password: &str| {
+        serde_json::json!({
+            "username": "alice",
+            "email": "alice@example.com",
+            "password": password,
+            "given_name": "Alice",
+        })
+    };
+
+    // Act
+    let weak = sign_up(&state, form("aaaaaaaaaa")).await;
+    let with_username = sign_up(&state, form("Alice-is-my-name-1")).await;
+
+    // Assert
+    assert_eq!(
+        field_errors(weak).await,
+        [("password".to_owned(), "too-weak".to_owned())]
+    );
+    assert_eq!(
+        field_errors(with_username).await,
+        [("password".to_owned(), "contains-username".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn signup_usernames_are_unique_regardless_of_case_and_composition() {
+    // Arrange
+    let state = spawn_app().await;
+    let form = |username: &str| {
+        serde_json::json!({
+            "username": username,
+            "email": format!("{}@example.org", username.len()),
+            "password": "correct horse battery",
+            "given_name": "Robert",
+        })
+    };
+
+    for username in ["BOB", "ｂｏｂ"] {
+        // Act
+        let response = sign_up(&state, form(username)).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 409, "{} is taken", username);
+        assert_eq!(
+            field_errors(response).await,
+            [("username".to_owned(), "taken".to_owned())]
+        );
+    }
+}
+
+#[tokio::test]
+async fn signup_rejects_breached_passwords() {
+    // Arrange
+    let mut breached = tempfile::NamedTempFile::new().unwrap();
+    writeln!(breached, "# known from breaches\nCorrect Horse Battery").unwrap();
+    let settings = Settings {
+        signup: SignupSettings {
+            breached_passwords: Some(breached.path().to_owned()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let state = spawn_app_with_settings(settings).await;
+    let form = serde_json::json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "correct horse battery",
+        "given_name": "Alice",
+    });
+
+    // Act
+    let response = sign_up(&state, form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(
+        field_errors(response).await,
+        [("password".to_owned(), "breached".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn registering_with_a_verified_address_leaves_no_account() {
+    // Arrange
+    let mut db = Database::new();
+    let password = HashedPassword::new(Secret::new("secret".to_string()))
+        .await
+        .unwrap();
+    let bob = db
+        .register_user("bob", password.clone(), "Robert")
+        .await
+        .unwrap();
+    db.set_email(bob, "bob@example.com", true).await.unwrap();
+
+    // Act
+    let result = db
+        .register_user_with_email("alice", password, "Alice", "bob@example.com")
+        .await;
+
+    // Assert
+    assert!(matches!(result, Err(StoreError::DuplicateRecord)));
+    assert!(!db.contains_user_name("alice").await);
+}
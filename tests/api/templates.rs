@@ -0,0 +1,211 @@
+use std::path::Path;
+
+use axum_oauth::oauth::{
+    settings::{Settings, TemplateSettings},
+    templates::TemplateProvider,
+};
+
+use crate::helpers::{spawn_app_with_settings, ClientType, TestState};
+
+async fn spawn_app_with_templates(directory: &Path, hot_reload: bool) -> TestState {
+    let settings = Settings {
+        templates: TemplateSettings {
+            directory: Some(directory.to_owned()),
+            hot_reload,
+        },
+        ..Default::default()
+    };
+
+    spawn_app_with_settings(settings).await
+}
+
+async fn get_page(state: &TestState, path: &str) -> (u16, String) {
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/{}", state.app_address, path))
+        .header("Accept-Language", "de")
+        .send()
+        .await
+        .expect("request to server api failed");
+
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn templates_from_directory_override_compiled_pages() {
+    // Arrange
+    let directory = tempfile::tempdir().unwrap();
+    std::fs::write(
+        directory.path().join("layout.html"),
+        r#"<html lang="{{ i18n.lang() }}"><body class="acme">{% block content %}{% endblock %}</body></html>"#,
+    )
+    .unwrap();
+    std::fs::write(
+        directory.path().join("signin.html"),
+        r#"{% extends "layout.html" %}{% block content %}<h1>{{ i18n.t("signin-heading") }} bei ACME</h1><form action="signin?{{ query }}"></form>{% endblock %}"#,
+    )
+    .unwrap();
+    let state = spawn_app_with_templates(directory.path(), false).await;
+
+    // Act
+    let (status, body) = get_page(&state, "signin?callback=authorize%3Fclient_id%3Dfoo").await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert!(
+        body.contains(r#"<html lang="de"><body class="acme">"#),
+        "{}",
+        body
+    );
+    assert!(body.contains("<h1>Anmelden bei ACME</h1>"), "{}", body);
+    assert!(
+        body.contains(r#"action="signin?callback=authorize%3Fclient_id%3Dfoo""#),
+        "The override gets the same fields as the compiled template: {}",
+        body
+    );
+
+    // Pages that aren't overridden use the compiled templates
+    let (status, body) = get_page(&state, "signup").await;
+    assert_eq!(status, 200);
+    assert!(body.contains(r#"name="given_name""#), "{}", body);
+}
+
+#[tokio::test]
+async fn consent_template_override_gets_scopes_and_client() {
+    // Arrange
+    let directory = tempfile::tempdir().unwrap();
+    std::fs::write(
+        directory.path().join("authorize.html"),
+        concat!(
+            r#"<h1>{{ i18n.fmt("authorize-heading", client=client_name) }}</h1>"#,
+            r#"<img src="{{ client.logo_uri }}">"#,
+            "{% for scope in scopes %}",
+            r#"<input name="{{ scope.name }}" checked>{{ scope.description }}"#,
+            "{% endfor %}",
+            r#"<form action="authorize?{{ query }}&consent=allow"></form>"#,
+        ),
+    )
+    .unwrap();
+    let state = spawn_app_with_templates(directory.path(), false).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "public",
+        "logo_uri": "https://foo.example/logo.png",
+    });
+    let res = state.register_client(&params, ClientType::Public).await;
+    state.signin("bob", "secret").await;
+    let code_verifier = pkce::code_verifier(128);
+    let query = serde_json::json!({
+        "response_type": "code",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "client_id": res.client_id.clone(),
+        "scope": "account:read account:write",
+        "code_challenge": pkce::code_challenge(&code_verifier),
+        "code_challenge_method": "S256",
+    });
+
+    // Act
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/authorize", state.app_address))
+        .query(&query)
+        .send()
+        .await
+        .expect("failed to get response from api client");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<h1>Authorize foo client</h1>"), "{}", body);
+    assert!(
+        html_escape::decode_html_entities(&body)
+            .contains(r#"<img src="https://foo.example/logo.png">"#),
+        "{}",
+        body
+    );
+    assert_eq!(
+        state.consent_scopes(&body),
+        vec!["account:read", "account:write"]
+    );
+    assert!(body.contains("Change the name on your account"), "{}", body);
+}
+
+#[tokio::test]
+async fn hot_reload_picks_up_changed_templates() {
+    // Arrange
+    let directory = tempfile::tempdir().unwrap();
+    let signout = directory.path().join("signout.html");
+    std::fs::write(&signout, "<h1>Bye</h1>").unwrap();
+    let state = spawn_app_with_templates(directory.path(), true).await;
+    let (_, body) = get_page(&state, "signout").await;
+    assert_eq!(body, "<h1>Bye</h1>");
+
+    // Act
+    std::fs::write(&signout, "<h1>{{ i18n.t('signout-heading') }}</h1>").unwrap();
+    let (status, body) = get_page(&state, "signout").await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(body, "<h1>Abmelden</h1>", "The changed template is used");
+
+    // Broken templates fall back to the compiled page
+    std::fs::write(&signout, "{{ i18n.missing() }}").unwrap();
+    let (status, body) = get_page(&state, "signout").await;
+    assert_eq!(status, 200);
+    assert!(
+        body.contains(r#"<form method="post" action="signout">"#),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn files_other_than_templates_are_skipped() {
+    // Arrange
+    let directory = tempfile::tempdir().unwrap();
+    std::fs::write(directory.path().join("signout.html"), "<h1>Bye</h1>").unwrap();
+    std::fs::write(
+        directory.path().join("logo.png"),
+        [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0xfe],
+    )
+    .unwrap();
+    std::fs::write(directory.path().join("README.md"), "{% if %}").unwrap();
+
+    // Act
+    let provider = TemplateProvider::new(&TemplateSettings {
+        directory: Some(directory.path().to_owned()),
+        hot_reload: false,
+    });
+    let state = spawn_app_with_templates(directory.path(), false).await;
+    let (status, body) = get_page(&state, "signout").await;
+
+    // Assert
+    assert!(
+        provider.is_ok(),
+        "Images and notes don't stop the templates from loading"
+    );
+    assert_eq!(status, 200);
+    assert!(body.contains("<h1>Bye</h1>"), "{}", body);
+}
+
+#[tokio::test]
+async fn invalid_template_directory_is_rejected_at_startup() {
+    let directory = tempfile::tempdir().unwrap();
+    std::fs::write(directory.path().join("error.html"), "{% if %}").unwrap();
+
+    let syntax_error = TemplateProvider::new(&TemplateSettings {
+        directory: Some(directory.path().to_owned()),
+        hot_reload: false,
+    });
+    let missing_directory = TemplateProvider::new(&TemplateSettings {
+        directory: Some(directory.path().join("missing")),
+        hot_reload: true,
+    });
+
+    assert!(
+        syntax_error.is_err(),
+        "Syntax errors are reported at startup"
+    );
+    assert!(missing_directory.is_err(), "The directory must exist");
+}
@@ -1,4 +1,12 @@
-use crate::helpers::{spawn_app, ClientType};
+use axum_oauth::oauth::settings::Settings;
+
+use crate::{
+    first_party::trust_client,
+    helpers::{
+        assert_is_redirect_to, spawn_app, spawn_app_with_admin, spawn_app_with_settings,
+        ClientResponse, ClientType, TestState, Token, ADMIN_TOKEN,
+    },
+};
 
 #[tokio::test]
 pub async fn unauthenticated_access() {
@@ -8,7 +16,7 @@ pub async fn unauthenticated_access() {
 
     // Act
     let response = client
-        .get(&format!("{}/api/user", &state.app_address))
+        .get(format!("{}/api/user", &state.app_address))
         .send()
         .await
         .expect("request to client api failed");
@@ -39,7 +47,7 @@ pub async fn happy_path_authenticated_access() {
     // Act
     let client = state.api_client;
     let response = client
-        .get(&format!("{}/api/user", &state.app_address))
+        .get(format!("{}/api/user", &state.app_address))
         .bearer_auth(state.token.access_token.unwrap())
         .send()
         .await
@@ -52,3 +60,271 @@ pub async fn happy_path_authenticated_access() {
         "authenticated access to api endpoint returns 200 Ok"
     );
 }
+
+#[tokio::test]
+pub async fn concurrent_requests_with_a_token_succeed() {
+    // Arrange
+    let (state, _) = authorized_state().await;
+    let token = state.token.access_token.clone().unwrap();
+
+    // Act
+    let responses = futures::future::join_all((0..32).map(|_| get_user(&state, &token))).await;
+
+    // Assert
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+pub async fn authorized_state() -> (TestState, ClientResponse) {
+    authorized_state_with(Settings::default()).await
+}
+
+pub async fn authorized_state_with(settings: Settings) -> (TestState, ClientResponse) {
+    let mut state = spawn_app_with_settings(settings).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+    state.authorization_flow(&client).await;
+
+    (state, client)
+}
+
+/// Like `authorized_state`, with a client the admin marked first-party after the owner consented.
+async fn first_party_state() -> (TestState, ClientResponse) {
+    let mut state = spawn_app_with_admin(Settings::default()).await;
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.signin("bob", "secret").await;
+    state.authorization_flow(&client).await;
+    let response = trust_client(&state, &client.client_id, ADMIN_TOKEN).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    (state, client)
+}
+
+pub async fn get_user(state: &TestState, token: &str) -> reqwest::Response {
+    state
+        .api_client
+        .get(format!("{}/api/user", &state.app_address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("request to client api failed")
+}
+
+async fn change_password(state: &TestState, token: &str, current: &str) -> reqwest::Response {
+    state
+        .api_client
+        .post(format!("{}/api/user/password", &state.app_address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "current_password": current,
+            "new_password": "new secret",
+        }))
+        .send()
+        .await
+        .expect("request to client api failed")
+}
+
+async fn delete_account(state: &TestState, token: &str, password: &str) -> reqwest::Response {
+    state
+        .api_client
+        .delete(format!("{}/api/user", &state.app_address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "password": password }))
+        .send()
+        .await
+        .expect("request to client api failed")
+}
+
+pub async fn refresh(
+    state: &TestState,
+    client: &ClientResponse,
+    token: &Token,
+) -> reqwest::Response {
+    state
+        .api_client
+        .post(format!("{}/oauth/token", state.app_address))
+        .basic_auth(&client.client_id, client.client_secret.as_ref())
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", token.refresh_token.as_deref().unwrap()),
+        ])
+        .send()
+        .await
+        .expect("request to client api failed")
+}
+
+#[tokio::test]
+pub async fn change_password_requires_current_password() {
+    // Arrange
+    let (state, _) = first_party_state().await;
+    let token = state.token.access_token.clone().unwrap();
+
+    // Act
+    let response = change_password(&state, &token, "wrong").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        get_user(&state, &token).await.status().as_u16(),
+        200,
+        "Nothing was revoked"
+    );
+    state.signin("bob", "secret").await;
+}
+
+#[tokio::test]
+pub async fn change_password_revokes_other_sessions_and_tokens() {
+    // Arrange
+    let (mut state, client) = first_party_state().await;
+    let other = state.token.clone();
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let second_client = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    state.authorization_flow(&second_client).await;
+    trust_client(&state, &second_client.client_id, ADMIN_TOKEN).await;
+    let current = state.token.access_token.clone().unwrap();
+
+    // Act
+    let response = change_password(&state, &current, "secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_user(&state, other.access_token.as_deref().unwrap())
+            .await
+            .status()
+            .as_u16(),
+        401,
+        "Other access tokens are revoked"
+    );
+    assert_ne!(
+        refresh(&state, &client, &other).await.status().as_u16(),
+        200,
+        "Their refresh tokens are revoked too"
+    );
+    assert_eq!(
+        get_user(&state, &current).await.status().as_u16(),
+        200,
+        "The token that changed the password still works"
+    );
+    let response = state
+        .api_client
+        .get(format!("{}/oauth/account/totp", state.app_address))
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_is_redirect_to(&response, 303, "/oauth/signin?", true);
+    state.signin("bob", "new secret").await;
+}
+
+#[tokio::test]
+pub async fn third_party_clients_cannot_change_credentials() {
+    // Arrange
+    let (state, _) = authorized_state().await;
+    let token = state.token.access_token.clone().unwrap();
+
+    // Act
+    let password = change_password(&state, &token, "secret").await;
+    let deletion = delete_account(&state, &token, "secret").await;
+
+    // Assert
+    assert_eq!(password.status().as_u16(), 403);
+    assert_eq!(deletion.status().as_u16(), 403);
+    assert_eq!(
+        get_user(&state, &token).await.status().as_u16(),
+        200,
+        "The account is still there"
+    );
+    state.signin("bob", "secret").await;
+}
+
+#[tokio::test]
+pub async fn delete_account_cascades() {
+    // Arrange
+    let (state, client) = first_party_state().await;
+    let token = state.token.clone();
+    let access_token = token.access_token.clone().unwrap();
+    assert_eq!(
+        delete_account(&state, &access_token, "wrong")
+            .await
+            .status()
+            .as_u16(),
+        403
+    );
+
+    // Act
+    let response = delete_account(&state, &access_token, "secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(get_user(&state, &access_token).await.status().as_u16(), 401);
+    assert_ne!(
+        refresh(&state, &client, &token).await.status().as_u16(),
+        200
+    );
+    let form = serde_json::json!({
+        "username": "bob",
+        "email": "bob@example.com",
+        "password": "correct horse battery",
+        "given_name": "Robert",
+        "csrf_token": state.get_csrf_token("signup").await,
+    });
+    let response = state
+        .api_client
+        .post(format!("{}/oauth/signup", &state.app_address))
+        .form(&form)
+        .send()
+        .await
+        .expect("request to server api failed");
+    assert_eq!(
+        response.status().as_u16(),
+        201,
+        "The username and email address are free again"
+    );
+}
+
+#[tokio::test]
+pub async fn delete_account_revokes_pending_codes() {
+    // Arrange
+    let (state, _) = first_party_state().await;
+    let access_token = state.token.access_token.clone().unwrap();
+    let params = serde_json::json!({
+        "name": "foo client",
+        "redirect_uri": "http://localhost:3001/endpoint",
+        "type": "confidential",
+    });
+    let other = state
+        .register_client(&params, ClientType::Confidential)
+        .await;
+    let (code, verifier) = state.authorization_code(&other, "account:read").await;
+
+    // Act
+    let response = delete_account(&state, &access_token, "secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let response = state.exchange_code(&other, &code, &verifier).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.expect("failed to parse error");
+    assert_eq!(error["error"], "invalid_grant");
+}